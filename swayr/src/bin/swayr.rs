@@ -31,7 +31,46 @@ fn main() {
     env_logger::Builder::from_env(Env::default().default_filter_or("warn"))
         .init();
     let opts: Opts = Opts::parse();
-    if let Err(err) = swayr::client::send_swayr_cmd(opts.command) {
-        log::error!("Could not send command: {}", err);
+    if let swayr::cmds::SwayrCommand::Subscribe = opts.command {
+        let result = swayr::client::subscribe(|json| {
+            use std::io::Write;
+            let mut stdout = std::io::stdout().lock();
+            // Stop when stdout is closed, e.g., when piped into `head`.
+            if writeln!(stdout, "{}", json)
+                .and_then(|_| stdout.flush())
+                .is_err()
+            {
+                std::process::exit(0);
+            }
+        });
+        if let Err(err) = result {
+            log::error!("Could not subscribe: {}", err);
+            std::process::exit(2);
+        }
+        return;
+    }
+    match swayr::client::send_swayr_cmd(opts.command) {
+        Ok(response) => {
+            if let Some(err) = response.error {
+                eprintln!("{}", err);
+                if let Some(details) = response.error_details {
+                    println!("{}", details);
+                }
+                std::process::exit(1);
+            }
+            if let Some(data) = response.data {
+                match serde_json::to_string_pretty(&data) {
+                    Ok(json) => println!("{}", json),
+                    Err(err) => {
+                        log::error!("Could not print response: {}", err);
+                        std::process::exit(1);
+                    }
+                }
+            }
+        }
+        Err(err) => {
+            log::error!("Could not send command: {}", err);
+            std::process::exit(2);
+        }
     }
 }
@@ -14,13 +14,54 @@
 // this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use crate::cmds;
+use crate::protocol;
 use crate::util;
-use std::io::Write;
 use std::os::unix::net::UnixStream;
 
+/// Sends `cmd` to swayrd and waits for its response.
 pub fn send_swayr_cmd(
     cmd: cmds::SwayrCommand,
-) -> std::result::Result<(), std::io::Error> {
+) -> std::result::Result<protocol::Response, std::io::Error> {
     let mut sock = UnixStream::connect(util::get_swayr_socket_path())?;
-    sock.write_all(serde_json::to_string(&cmd).unwrap().as_bytes())
+    send_request(&mut sock, cmd)
+}
+
+/// Subscribes to swayrd's events and calls `handler` with the JSON of each
+/// event until swayrd closes the connection.
+pub fn subscribe<F>(mut handler: F) -> std::result::Result<(), std::io::Error>
+where
+    F: FnMut(&str),
+{
+    let mut sock = UnixStream::connect(util::get_swayr_socket_path())?;
+    let response = send_request(&mut sock, cmds::SwayrCommand::Subscribe)?;
+    if let Some(err) = response.error {
+        return Err(std::io::Error::other(err));
+    }
+    loop {
+        let frame = match protocol::read_frame(&mut sock) {
+            Ok(frame) => frame,
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return Ok(())
+            }
+            Err(err) => return Err(err),
+        };
+        handler(&String::from_utf8_lossy(&frame));
+    }
+}
+
+fn send_request(
+    sock: &mut UnixStream,
+    cmd: cmds::SwayrCommand,
+) -> std::result::Result<protocol::Response, std::io::Error> {
+    protocol::write_frame(sock, &protocol::Request::new(cmd))?;
+    let frame = protocol::read_frame(sock)?;
+    let response: protocol::Response = serde_json::from_slice(&frame)?;
+    if response.version != protocol::PROTOCOL_VERSION {
+        log::warn!(
+            "swayrd speaks protocol version {} but swayr speaks version {}.",
+            response.version,
+            protocol::PROTOCOL_VERSION
+        );
+    }
+    Ok(response)
 }
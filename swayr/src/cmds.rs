@@ -17,11 +17,20 @@
 
 use crate::config as cfg;
 use crate::criteria;
+use crate::dynamic_layout;
+use crate::events;
 use crate::focus::FocusData;
 use crate::focus::FocusMessage;
+use crate::focus::LruScope;
+use crate::launcher;
 use crate::layout;
+use crate::query;
+use crate::saved_layout;
+use crate::scratchpad;
+use crate::session;
 use crate::shared::ipc;
 use crate::shared::ipc::NodeMethods;
+use crate::slots;
 use crate::tree as t;
 use crate::util;
 use crate::util::DisplayFormat;
@@ -29,25 +38,82 @@ use once_cell::sync::Lazy;
 use rand::prelude::SliceRandom;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::sync::Mutex;
 use std::sync::MutexGuard;
+use std::sync::TryLockError;
+use std::thread;
+use std::time::Duration;
 use swayipc as s;
 
-pub fn run_sway_command_1(cmd: &str) {
-    log::debug!("Running sway command: {}", cmd);
-    match s::Connection::new() {
-        Ok(mut con) => {
-            if let Err(err) = con.run_command(cmd) {
-                log::error!("Could not run sway command: {}", err)
-            }
+/// The successful outcome of executing a [`SwayrCommand`].
+#[derive(Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CmdOutput {
+    /// The con_ids of the windows, containers, or workspaces affected by the
+    /// command.
+    pub con_ids: Vec<i64>,
+    /// The data returned by query commands such as `get-windows`.
+    pub data: Option<serde_json::Value>,
+}
+
+impl From<Vec<i64>> for CmdOutput {
+    fn from(con_ids: Vec<i64>) -> CmdOutput {
+        CmdOutput {
+            con_ids,
+            data: None,
+        }
+    }
+}
+
+/// Why executing a [`SwayrCommand`] failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CmdError {
+    pub message: String,
+    /// Structured details about the error, e.g., the offset and the expected
+    /// tokens of an invalid criteria query.
+    pub details: Option<serde_json::Value>,
+}
+
+impl fmt::Display for CmdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<String> for CmdError {
+    fn from(message: String) -> CmdError {
+        CmdError {
+            message,
+            details: None,
+        }
+    }
+}
+
+impl From<&str> for CmdError {
+    fn from(message: &str) -> CmdError {
+        CmdError::from(message.to_owned())
+    }
+}
+
+impl From<criteria::CriteriaError> for CmdError {
+    fn from(err: criteria::CriteriaError) -> CmdError {
+        CmdError {
+            message: err.to_string(),
+            details: serde_json::to_value(&err).ok(),
         }
-        Err(err) => panic!("{}", err),
     }
 }
 
-pub fn run_sway_command(args: &[&str]) {
+/// The result of executing a [`SwayrCommand`], or why it failed.
+pub type CmdResult = Result<CmdOutput, CmdError>;
+
+pub fn run_sway_command_1(cmd: &str) -> Result<(), String> {
+    ipc::run_command(cmd)
+}
+
+pub fn run_sway_command(args: &[&str]) -> Result<(), String> {
     let cmd = args.join(" ");
-    run_sway_command_1(&cmd);
+    run_sway_command_1(&cmd)
 }
 
 #[derive(clap::Parser, Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
@@ -64,6 +130,8 @@ pub enum ConsiderWindows {
     AllWorkspaces,
     /// Consider windows of only the current workspaces.
     CurrentWorkspace,
+    /// Consider windows of only the current output.
+    CurrentOutput,
 }
 
 #[derive(clap::Parser, PartialEq, Eq, Debug, Clone, Deserialize, Serialize)]
@@ -84,6 +152,62 @@ pub enum SwayrCommand {
         )]
         skip_origin: bool,
     },
+    /// Switch to the most recently used window on the current workspace.
+    /// Windows which have been moved there count as focused on the workspace
+    /// only after they have been focused there.
+    SwitchToLruWindowOnWorkspace,
+    /// Switch to the most recently used window on the current output.
+    /// Windows which have been moved there count as focused on the output
+    /// only after they have been focused there.
+    SwitchToLruWindowOnOutput,
+    /// Switch to the most recently used workspace on the current output.
+    SwitchToLruWorkspaceOnOutput,
+    /// Go back to the previous window in the focus history.  In contrast to
+    /// the LRU order, the focus history isn't reordered by going back and
+    /// forward.
+    FocusBack,
+    /// Go forward to the next window in the focus history after having gone
+    /// back.
+    FocusForward,
+    /// Put the focused window into the numbered slot replacing the window
+    /// which has been there before.  The window also gets the mark
+    /// `_swayr_slot_<slot>`.
+    AssignSlot {
+        /// The slot number.
+        slot: u32,
+    },
+    /// Switch to the window in the numbered slot if that's not focused
+    /// already.  If it is, switch to the next urgent window (if any) or to
+    /// last recently used window.
+    JumpToSlot {
+        /// The slot number.
+        slot: u32,
+
+        #[clap(short = 'u', long, help = "Skip urgent windows")]
+        skip_urgent: bool,
+        #[clap(short = 'l', long, help = "Skip the last recently used window")]
+        skip_lru: bool,
+        #[clap(
+            short = 'o',
+            long,
+            help = "Don't switch back to the origin window"
+        )]
+        skip_origin: bool,
+    },
+    /// Show all slots and their windows in a menu and switch to the selected
+    /// one.
+    ShowSlots,
+    /// Show a scratchpad window selected from a menu listing the scratchpad
+    /// windows in LRU order.
+    SwitchScratchpadWindow,
+    /// Hide the scratchpad window shown by swayr and show the next one in LRU
+    /// order.
+    NextScratchpadWindow,
+    /// Hide the scratchpad window shown by swayr and show the previous one in
+    /// LRU order.
+    PrevScratchpadWindow,
+    /// Move the focused window to the scratchpad.
+    HideToScratchpad,
     /// Switch to the given app (given by app_id or window class) if that's not
     /// focused already.  If it is, switch to the next urgent window (if any)
     /// or to last recently used window.
@@ -240,6 +364,70 @@ pub enum SwayrCommand {
         /// The criteria query defining which windows to switch to.
         criteria: String,
     },
+    /// Focus the last recently used window matching the criteria query or
+    /// cycle through the matching windows if one of them is focused already.
+    /// If no window matches, run the given command.
+    RunOrRaise {
+        /// The criteria query defining which windows to switch to.
+        criteria: String,
+        /// The shell command to run if no window matches.
+        command: String,
+        /// Move the new window to this workspace.
+        #[clap(long)]
+        workspace: Option<String>,
+        /// Make the new window floating.
+        #[clap(long)]
+        floating: bool,
+        /// Add this mark to the new window.
+        #[clap(long)]
+        mark: Option<String>,
+        /// The number of seconds to wait for the new window.
+        #[clap(long, default_value_t = 10)]
+        timeout: u64,
+    },
+    /// Run a sway command for each window matching the criteria query.
+    ForEachMatching {
+        /// The criteria query defining the windows to act on.
+        criteria: String,
+        /// The sway command to run for each window, e.g., `floating enable`.
+        command: String,
+        /// Only print the matching windows without acting on them.
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Quit all windows matching the criteria query.
+    QuitMatching {
+        /// The criteria query defining the windows to act on.
+        criteria: String,
+        #[clap(
+            short,
+            long,
+            help = "Kill the windows' processes rather than just quitting them"
+        )]
+        kill: bool,
+        /// Only print the matching windows without acting on them.
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Move all windows matching the criteria query to the given workspace.
+    MoveMatchingToWorkspace {
+        /// The criteria query defining the windows to act on.
+        criteria: String,
+        /// The name of the workspace to move the windows to.
+        workspace: String,
+        /// Only print the matching windows without acting on them.
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Steal all windows matching the criteria query into the current
+    /// workspace.
+    StealMatching {
+        /// The criteria query defining the windows to act on.
+        criteria: String,
+        /// Only print the matching windows without acting on them.
+        #[clap(long)]
+        dry_run: bool,
+    },
     /// Move the currently focused window or container to the selected
     /// workspace.
     MoveFocusedToWorkspace,
@@ -273,12 +461,81 @@ pub enum SwayrCommand {
         #[clap(subcommand)]
         floating: ConsiderFloating,
     },
+    /// Save the tree of tiled containers and windows of the current
+    /// workspace under the given name.
+    SaveLayout {
+        /// The name of the layout.
+        name: String,
+    },
+    /// Rebuild the layout saved under the given name on the current
+    /// workspace, moving the best matching windows into place.
+    RestoreLayout {
+        /// The name of the layout.
+        name: String,
+    },
+    /// Show a menu of installed applications sorted by frecency and launch
+    /// the selected one.
+    LaunchApp,
+    /// Save the apps of all windows together with the workspaces, outputs
+    /// and floating states of their windows.
+    SaveSession,
+    /// Move windows of the saved session to their places and relaunch the
+    /// apps of windows which don't exist anymore.  Their windows are moved
+    /// into place as they appear.
+    RestoreSession {
+        /// The number of seconds to wait for the windows of relaunched apps.
+        #[clap(long, default_value_t = 30)]
+        timeout: u64,
+        /// Assign the saved workspaces to their saved outputs.  The
+        /// assignments last until sway is restarted.
+        #[clap(long)]
+        assign_outputs: bool,
+    },
+    /// Swap the focused window with the master window of the current
+    /// workspace's dynamic layout.  The master window itself is swapped with
+    /// the first stack window.
+    SwapWithMaster,
+    /// Increase the number of master windows of the current workspace's
+    /// dynamic layout.
+    IncreaseMasterCount,
+    /// Decrease the number of master windows of the current workspace's
+    /// dynamic layout.
+    DecreaseMasterCount,
+    /// Switch the current workspace to the next dynamic layout.
+    CycleLayout,
     /// Select and execute a swaymsg command.
     ExecuteSwaymsgCommand,
     /// Select and execute a swayr command.
     ExecuteSwayrCommand,
     /// Configure outputs.
     ConfigureOutputs,
+    /// Reload the config file.  swayrd also reloads it on SIGHUP and when the
+    /// file has been changed.
+    ReloadConfig,
+    /// Print all windows as JSON in the order of `switch-window`, i.e., urgent
+    /// windows first, then in LRU order, and the focused window last.
+    GetWindows,
+    /// Print all workspaces and their windows as JSON in the order of
+    /// `switch-workspace-or-window`.
+    GetWorkspaces,
+    /// Print all windows as JSON in plain LRU order, i.e., the most recently
+    /// focused window first.
+    GetLru,
+    /// Print swayrd's state as JSON for debugging: its uptime, config file,
+    /// the LRU order with focus ticks, a pending lock-in, whether a sequence
+    /// of cycling commands is active, the last command, and the connection
+    /// state.
+    Status,
+    /// Print the parsed criteria query and the windows it currently matches
+    /// as JSON, or where the query is invalid.
+    CheckCriteria {
+        /// The criteria query to check.
+        criteria: String,
+    },
+    /// Print swayrd's events as JSON, one per line, until interrupted.  Events
+    /// are LRU focus lock-ins, starts and ends of window cycling sequences,
+    /// and executed commands.
+    Subscribe,
 }
 
 impl SwayrCommand {
@@ -297,6 +554,22 @@ impl SwayrCommand {
                 | SwayrCommand::PrevWindowOfSameLayout { .. }
                 | SwayrCommand::NextMatchingWindow { .. }
                 | SwayrCommand::PrevMatchingWindow { .. }
+                | SwayrCommand::RunOrRaise { .. }
+                | SwayrCommand::NextScratchpadWindow
+                | SwayrCommand::PrevScratchpadWindow
+        )
+    }
+
+    /// Queries only return data and don't affect the state of command
+    /// sequences.
+    pub fn is_query(&self) -> bool {
+        matches!(
+            self,
+            SwayrCommand::GetWindows
+                | SwayrCommand::GetWorkspaces
+                | SwayrCommand::GetLru
+                | SwayrCommand::Status
+                | SwayrCommand::CheckCriteria { .. }
         )
     }
 }
@@ -363,14 +636,75 @@ impl SwitchToMatchingData {
 static SWITCH_TO_MATCHING_DATA: Lazy<Mutex<SwitchToMatchingData>> =
     Lazy::new(|| Mutex::new(SwitchToMatchingData::new()));
 
-pub fn exec_swayr_cmd(args: ExecSwayrCmdArgs) {
+/// Locks the state of command sequences.  Commands showing a menu keep it
+/// locked until the menu is closed, so such a menu is cancelled rather than
+/// waiting for the user if `cancel_menu` is true.  The state is recovered if
+/// some command panicked while holding the lock.
+fn lock_sequence_state(
+    cancel_menu: bool,
+) -> (
+    MutexGuard<'static, SwayrCommand>,
+    MutexGuard<'static, SwitchToMatchingData>,
+) {
+    let last_command = loop {
+        match LAST_COMMAND.try_lock() {
+            Ok(guard) => break guard,
+            Err(TryLockError::Poisoned(err)) => break err.into_inner(),
+            Err(TryLockError::WouldBlock) => {
+                if cancel_menu {
+                    util::cancel_menu();
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+    };
+    let switch_to_matching_data = SWITCH_TO_MATCHING_DATA
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    (last_command, switch_to_matching_data)
+}
+
+/// Whether `cmd` can act on a given window rather than the focused one, see
+/// [`exec_swayr_cmd_for_window`].
+pub fn can_target_window(cmd: &SwayrCommand) -> bool {
+    matches!(
+        cmd,
+        SwayrCommand::AssignSlot { .. } | SwayrCommand::HideToScratchpad
+    )
+}
+
+/// Runs `cmd` for the window with the given id rather than the focused one,
+/// e.g., as an action of a window rule.  In contrast to `exec_swayr_cmd`, it
+/// doesn't take part in command sequences and never cancels an open menu.
+pub fn exec_swayr_cmd_for_window(cmd: &SwayrCommand, id: i64) -> CmdResult {
+    log::info!("Running SwayrCommand {:?} for window {}", cmd, id);
+    match cmd {
+        SwayrCommand::AssignSlot { slot } => {
+            slots::assign_slot(*slot, id)?;
+            Ok(vec![id].into())
+        }
+        SwayrCommand::HideToScratchpad => {
+            scratchpad::hide_window_to_scratchpad(id)
+        }
+        _ => Err(format!("{:?} can't act on a given window.", cmd).into()),
+    }
+}
+
+pub fn exec_swayr_cmd(args: ExecSwayrCmdArgs) -> CmdResult {
     log::info!("Running SwayrCommand {:?}", args.cmd);
     let fdata = args.focus_data;
 
-    let mut last_command = LAST_COMMAND.lock().expect("Could not lock mutex");
-    let mut switch_to_matching_data = SWITCH_TO_MATCHING_DATA
-        .lock()
-        .expect("Could not lock mutex");
+    if args.cmd.is_query() {
+        return query::exec_query(args.cmd, fdata);
+    }
+    if let SwayrCommand::Subscribe = args.cmd {
+        return Err("Subscriptions are handled by swayrd itself.".into());
+    }
+
+    // A nop, e.g., bound to releasing the modifier key, waits for an open
+    // menu rather than cancelling it.
+    let (mut last_command, mut switch_to_matching_data) =
+        lock_sequence_state(*args.cmd != SwayrCommand::Nop);
 
     // If this command is not equal to the last command, nuke the
     // switch_to_matching_data so that we start a new sequence.
@@ -384,8 +718,9 @@ pub fn exec_swayr_cmd(args: ExecSwayrCmdArgs) {
         fdata.send(FocusMessage::TickUpdateActivate);
     }
 
-    match args.cmd {
-        SwayrCommand::Nop => {}
+    let mut selected_cmd: Option<SwayrCommand> = None;
+    let result = match args.cmd {
+        SwayrCommand::Nop => Ok(CmdOutput::default()),
         SwayrCommand::SwitchToUrgentOrLRUWindow {
             skip_urgent,
             skip_lru,
@@ -397,6 +732,45 @@ pub fn exec_swayr_cmd(args: ExecSwayrCmdArgs) {
 
             switch_to_urgent_or_lru_window(&mut switch_to_matching_data, fdata)
         }
+        SwayrCommand::SwitchToLruWindowOnWorkspace => {
+            switch_to_lru_window(&ConsiderWindows::CurrentWorkspace, fdata)
+        }
+        SwayrCommand::SwitchToLruWindowOnOutput => {
+            switch_to_lru_window(&ConsiderWindows::CurrentOutput, fdata)
+        }
+        SwayrCommand::SwitchToLruWorkspaceOnOutput => {
+            switch_to_lru_workspace_on_output(fdata)
+        }
+        SwayrCommand::FocusBack => {
+            focus_back_or_forward(Direction::Backward, fdata)
+        }
+        SwayrCommand::FocusForward => {
+            focus_back_or_forward(Direction::Forward, fdata)
+        }
+        SwayrCommand::AssignSlot { slot } => assign_slot(*slot, fdata),
+        SwayrCommand::JumpToSlot {
+            slot,
+            skip_urgent,
+            skip_lru,
+            skip_origin,
+        } => {
+            switch_to_matching_data.skip_urgent = *skip_urgent;
+            switch_to_matching_data.skip_lru = *skip_lru;
+            switch_to_matching_data.skip_origin = *skip_origin;
+
+            jump_to_slot(*slot, &mut switch_to_matching_data, fdata)
+        }
+        SwayrCommand::ShowSlots => show_slots(fdata),
+        SwayrCommand::SwitchScratchpadWindow => {
+            scratchpad::switch_scratchpad_window(fdata)
+        }
+        SwayrCommand::NextScratchpadWindow => {
+            scratchpad::cycle_scratchpad_windows(Direction::Forward, fdata)
+        }
+        SwayrCommand::PrevScratchpadWindow => {
+            scratchpad::cycle_scratchpad_windows(Direction::Backward, fdata)
+        }
+        SwayrCommand::HideToScratchpad => scratchpad::hide_to_scratchpad(fdata),
         SwayrCommand::SwitchToAppOrUrgentOrLRUWindow {
             name,
             skip_urgent,
@@ -447,7 +821,9 @@ pub fn exec_swayr_cmd(args: ExecSwayrCmdArgs) {
         }
         SwayrCommand::SwitchWindow => switch_window(fdata),
         SwayrCommand::StealWindow => steal_window(fdata),
-        SwayrCommand::StealWindowOrContainer => steal_window_or_container(fdata),
+        SwayrCommand::StealWindowOrContainer => {
+            steal_window_or_container(fdata)
+        }
         SwayrCommand::SwitchWorkspace => switch_workspace(fdata),
         SwayrCommand::SwitchOutput => switch_output(),
         SwayrCommand::SwitchWorkspaceOrWindow => {
@@ -558,14 +934,74 @@ pub fn exec_swayr_cmd(args: ExecSwayrCmdArgs) {
                 Direction::Forward,
                 criteria,
                 fdata,
-            );
+            )
         }
         SwayrCommand::PrevMatchingWindow { criteria } => {
             focus_matching_window_in_direction(
                 Direction::Backward,
                 criteria,
                 fdata,
-            );
+            )
+        }
+        SwayrCommand::RunOrRaise {
+            criteria,
+            command,
+            workspace,
+            floating,
+            mark,
+            timeout,
+        } => run_or_raise(
+            criteria,
+            command,
+            launcher::Placement {
+                workspace: workspace.clone(),
+                floating: *floating,
+                mark: mark.clone(),
+            },
+            Duration::from_secs(*timeout),
+            fdata,
+        ),
+        SwayrCommand::ForEachMatching {
+            criteria,
+            command,
+            dry_run,
+        } => {
+            if command.contains(';') {
+                Err(SEMICOLON_IN_COMMAND.into())
+            } else {
+                for_each_matching(criteria, *dry_run, fdata, |win| {
+                    Ok(vec![format!("[con_id={}] {}", win.id, command)])
+                })
+            }
+        }
+        SwayrCommand::QuitMatching {
+            criteria,
+            kill,
+            dry_run,
+        } => quit_matching(criteria, *kill, *dry_run, fdata),
+        SwayrCommand::MoveMatchingToWorkspace {
+            criteria,
+            workspace,
+            dry_run,
+        } => for_each_matching(criteria, *dry_run, fdata, |win| {
+            Ok(vec![format!(
+                "[con_id={}] move to workspace {}{}",
+                win.id,
+                if DIGIT_AND_NAME.is_match(workspace) {
+                    "number "
+                } else {
+                    ""
+                },
+                ipc::quote(workspace)
+            )])
+        }),
+        SwayrCommand::StealMatching { criteria, dry_run } => {
+            for_each_matching(criteria, *dry_run, fdata, |win| {
+                Ok(vec![format!(
+                    "[con_id={}] move to workspace current",
+                    win.id
+                )])
+            })
         }
         SwayrCommand::TileWorkspace { floating } => {
             tile_current_workspace(floating, false)
@@ -579,7 +1015,42 @@ pub fn exec_swayr_cmd(args: ExecSwayrCmdArgs) {
         SwayrCommand::ToggleTabShuffleTileWorkspace { floating } => {
             toggle_tab_tile_current_workspace(floating)
         }
+        SwayrCommand::SaveLayout { name } => saved_layout::save_layout(name),
+        SwayrCommand::RestoreLayout { name } => {
+            saved_layout::restore_layout(name)
+        }
+        SwayrCommand::LaunchApp => launcher::launch_app(),
+        SwayrCommand::SaveSession => session::save_session(),
+        SwayrCommand::RestoreSession {
+            timeout,
+            assign_outputs,
+        } => session::restore_session(
+            Duration::from_secs(*timeout),
+            *assign_outputs,
+        ),
+        SwayrCommand::SwapWithMaster => dynamic_layout::swap_with_master(),
+        SwayrCommand::IncreaseMasterCount => {
+            dynamic_layout::change_master_count(true)
+        }
+        SwayrCommand::DecreaseMasterCount => {
+            dynamic_layout::change_master_count(false)
+        }
+        SwayrCommand::CycleLayout => dynamic_layout::cycle_layout(),
         SwayrCommand::ConfigureOutputs => configure_outputs(),
+        SwayrCommand::ReloadConfig => cfg::reload_config()
+            .map(|_| {
+                events::broadcast(&events::Event::ConfigReloaded);
+                CmdOutput::default()
+            })
+            .map_err(CmdError::from),
+        SwayrCommand::GetWindows
+        | SwayrCommand::GetWorkspaces
+        | SwayrCommand::GetLru
+        | SwayrCommand::Status
+        | SwayrCommand::CheckCriteria { .. }
+        | SwayrCommand::Subscribe => {
+            unreachable!("Queries and subscriptions are handled above.")
+        }
         SwayrCommand::ExecuteSwaymsgCommand => exec_swaymsg_command(),
         SwayrCommand::ExecuteSwayrCommand => {
             let mut cmds = vec![
@@ -598,7 +1069,28 @@ pub fn exec_swayr_cmd(args: ExecSwayrCmdArgs) {
                     skip_lru: false,
                     skip_origin: false,
                 },
+                SwayrCommand::SwitchToLruWindowOnWorkspace,
+                SwayrCommand::SwitchToLruWindowOnOutput,
+                SwayrCommand::SwitchToLruWorkspaceOnOutput,
+                SwayrCommand::FocusBack,
+                SwayrCommand::FocusForward,
+                SwayrCommand::ShowSlots,
+                SwayrCommand::SwitchScratchpadWindow,
+                SwayrCommand::NextScratchpadWindow,
+                SwayrCommand::PrevScratchpadWindow,
+                SwayrCommand::HideToScratchpad,
+                SwayrCommand::SwapWithMaster,
+                SwayrCommand::IncreaseMasterCount,
+                SwayrCommand::DecreaseMasterCount,
+                SwayrCommand::CycleLayout,
+                SwayrCommand::LaunchApp,
+                SwayrCommand::SaveSession,
+                SwayrCommand::RestoreSession {
+                    timeout: 30,
+                    assign_outputs: false,
+                },
                 SwayrCommand::ConfigureOutputs,
+                SwayrCommand::ReloadConfig,
                 SwayrCommand::ExecuteSwaymsgCommand,
             ];
             for f in [
@@ -626,6 +1118,7 @@ pub fn exec_swayr_cmd(args: ExecSwayrCmdArgs) {
             for w in [
                 ConsiderWindows::AllWorkspaces,
                 ConsiderWindows::CurrentWorkspace,
+                ConsiderWindows::CurrentOutput,
             ] {
                 cmds.push(SwayrCommand::NextWindow { windows: w.clone() });
                 cmds.push(SwayrCommand::PrevWindow { windows: w.clone() });
@@ -645,48 +1138,55 @@ pub fn exec_swayr_cmd(args: ExecSwayrCmdArgs) {
                 })
             }
 
-            if let Ok(c) = util::select_from_menu("Select swayr command", &cmds)
-            {
-                exec_swayr_cmd(ExecSwayrCmdArgs {
-                    cmd: c,
-                    focus_data: args.focus_data,
-                });
-            }
+            // The selected command is executed below after the sequence
+            // state has been unlocked.
+            selected_cmd =
+                util::select_from_menu("Select swayr command", &cmds)
+                    .ok()
+                    .cloned();
+            Ok(CmdOutput::default())
         }
-    }
+    };
 
     *last_command = args.cmd.clone();
+    drop(last_command);
+    drop(switch_to_matching_data);
+
+    match selected_cmd {
+        Some(cmd) => exec_swayr_cmd(ExecSwayrCmdArgs {
+            cmd: &cmd,
+            focus_data: args.focus_data,
+        }),
+        None => result,
+    }
 }
 
-fn steal_window_by_id(id: i64) -> i64 {
-    run_sway_command(&[format!("[con_id={}]", id).as_str(), "move to workspace current"]);
-    id
-}
-
-fn focus_window_by_id(id: i64) -> i64 {
-    run_sway_command(&[format!("[con_id={}]", id).as_str(), "focus"]);
-    id
+fn steal_window_by_id(id: i64) -> CmdResult {
+    run_sway_command(&[
+        format!("[con_id={}]", id).as_str(),
+        "move to workspace current",
+    ])?;
+    Ok(vec![id].into())
 }
 
-fn quit_window_by_id(id: i64) {
-    run_sway_command(&[format!("[con_id={}]", id).as_str(), "kill"]);
+fn focus_window_by_id(id: i64) -> CmdResult {
+    run_sway_command(&[format!("[con_id={}]", id).as_str(), "focus"])?;
+    Ok(vec![id].into())
 }
 
-pub fn get_outputs() -> Vec<s::Output> {
-    match s::Connection::new() {
-        Ok(mut con) => con.get_outputs().expect("Got no outputs"),
-        Err(err) => panic!("{}", err),
-    }
+fn quit_window_by_id(id: i64) -> CmdResult {
+    run_sway_command(&[format!("[con_id={}]", id).as_str(), "kill"])?;
+    Ok(vec![id].into())
 }
 
 pub fn switch_to_urgent_or_lru_window(
     stm_data: &mut MutexGuard<SwitchToMatchingData>,
     fdata: &FocusData,
-) {
-    let root = ipc::get_root_node(false);
+) -> CmdResult {
+    let root = ipc::get_root_node(false)?;
     let tree = t::get_tree(&root);
     let wins = tree.get_windows(fdata);
-    focus_urgent_or_matching_or_lru_window(&wins, fdata, stm_data, |_| false);
+    focus_urgent_or_matching_or_lru_window(&wins, fdata, stm_data, |_| false)
 }
 
 pub fn focus_urgent_or_matching_or_lru_window<P>(
@@ -694,7 +1194,8 @@ pub fn focus_urgent_or_matching_or_lru_window<P>(
     fdata: &FocusData,
     stm_data: &mut MutexGuard<SwitchToMatchingData>,
     pred: P,
-) where
+) -> CmdResult
+where
     P: Fn(&t::DisplayNode) -> bool,
 {
     let focused = wins.iter().find(|w| w.node.focused);
@@ -742,7 +1243,7 @@ pub fn focus_urgent_or_matching_or_lru_window<P>(
     }) {
         log::debug!("Switching to by urgency or matching predicate");
         stm_data.visited.push(win.node.id);
-        focus_window_by_id(win.node.id);
+        focus_window_by_id(win.node.id)
     } else if !skip_lru
         && stm_data.lru.is_some()
         && stm_data.lru != Some(focused_id)
@@ -752,20 +1253,22 @@ pub fn focus_urgent_or_matching_or_lru_window<P>(
         log::debug!("Switching to LRU");
         let id = stm_data.lru.unwrap();
         stm_data.visited.push(id);
-        focus_window_by_id(id);
+        focus_window_by_id(id)
     } else if !skip_origin {
         log::debug!("Switching back to origin");
         if let Some(id) = stm_data.origin {
             if id != focused_id && wins.iter().any(|w| w.node.id == id) {
                 stm_data.reset(false);
-                focus_window_by_id(id);
+                focus_window_by_id(id)
             } else {
                 log::debug!("Origin is already focused or is gone; resetting.");
                 stm_data.reset(false);
                 if !initialized_now {
                     focus_urgent_or_matching_or_lru_window(
                         wins, fdata, stm_data, pred,
-                    );
+                    )
+                } else {
+                    Err(NO_WINDOW_TO_SWITCH_TO.into())
                 }
             }
         } else {
@@ -774,73 +1277,234 @@ pub fn focus_urgent_or_matching_or_lru_window<P>(
             if !initialized_now {
                 focus_urgent_or_matching_or_lru_window(
                     wins, fdata, stm_data, pred,
-                );
+                )
+            } else {
+                Err(NO_WINDOW_TO_SWITCH_TO.into())
             }
         }
     } else {
         log::debug!("Cycle exhausted; resetting.");
         stm_data.reset(false);
         if !initialized_now {
-            focus_urgent_or_matching_or_lru_window(wins, fdata, stm_data, pred);
+            focus_urgent_or_matching_or_lru_window(wins, fdata, stm_data, pred)
+        } else {
+            Err(NO_WINDOW_TO_SWITCH_TO.into())
         }
     }
 }
 
+const NO_WINDOW_TO_SWITCH_TO: &str = "No window to switch to.";
+
+/// Sway would run the commands after a `;` without the criteria prefixed to
+/// the first one, i.e., on the focused window rather than the intended one.
+pub const SEMICOLON_IN_COMMAND: &str = "The command must not contain ';'.";
+
+/// Retains only the windows in `wins` which are on the current workspace or
+/// output according to `consider_wins` and returns the scope of their LRU
+/// order.
+fn retain_considered_windows(
+    tree: &t::Tree,
+    wins: &mut Vec<t::DisplayNode>,
+    consider_wins: &ConsiderWindows,
+) -> LruScope {
+    let parent_id = |w: &t::DisplayNode, typ| {
+        tree.get_parent_node_of_type(w.node.id, typ).map(|n| n.id)
+    };
+    match consider_wins {
+        ConsiderWindows::AllWorkspaces => LruScope::Global,
+        ConsiderWindows::CurrentWorkspace => {
+            let cur_ws = tree.get_current_workspace().id;
+            wins.retain(|w| parent_id(w, ipc::Type::Workspace) == Some(cur_ws));
+            LruScope::Workspace(cur_ws)
+        }
+        ConsiderWindows::CurrentOutput => {
+            let cur_ws = tree.get_current_workspace().id;
+            let output =
+                tree.get_parent_node_of_type(cur_ws, ipc::Type::Output);
+            wins.retain(|w| {
+                parent_id(w, ipc::Type::Output) == output.map(|o| o.id)
+            });
+            match output {
+                Some(o) => LruScope::Output(o.get_name().to_owned()),
+                None => LruScope::Global,
+            }
+        }
+    }
+}
+
+fn switch_to_lru_window(
+    consider_wins: &ConsiderWindows,
+    fdata: &FocusData,
+) -> CmdResult {
+    let root = ipc::get_root_node(false)?;
+    let tree = t::get_tree(&root);
+    let mut wins = tree.get_windows(fdata);
+    let scope = retain_considered_windows(&tree, &mut wins, consider_wins);
+    match wins
+        .iter()
+        .filter(|w| !w.node.focused)
+        .max_by_key(|w| fdata.lru_key(w.node.id, &scope))
+    {
+        Some(win) => focus_window_by_id(win.node.id),
+        None => Err(NO_WINDOW_TO_SWITCH_TO.into()),
+    }
+}
+
+fn focus_back_or_forward(dir: Direction, fdata: &FocusData) -> CmdResult {
+    let mut history = fdata.focus_history.write().unwrap();
+    let id = match dir {
+        Direction::Backward => history.back(),
+        Direction::Forward => history.forward(),
+    };
+    // Release the lock before sway sends the focus event.
+    drop(history);
+    match id {
+        Some(id) => focus_window_by_id(id),
+        None => Err("No window to go to in the focus history.".into()),
+    }
+}
+
+fn switch_to_lru_workspace_on_output(fdata: &FocusData) -> CmdResult {
+    let root = ipc::get_root_node(false)?;
+    let tree = t::get_tree(&root);
+    let cur_ws = tree.get_current_workspace();
+    let output = tree
+        .get_parent_node_of_type(cur_ws.id, ipc::Type::Output)
+        .ok_or_else(|| "No current output.".to_owned())?;
+    let scope = LruScope::Output(output.get_name().to_owned());
+    match output
+        .nodes_of_type(ipc::Type::Workspace)
+        .into_iter()
+        .filter(|ws| ws.id != cur_ws.id)
+        .max_by_key(|ws| fdata.lru_key(ws.id, &scope))
+    {
+        Some(ws) => {
+            run_sway_command(&["workspace", ws.get_name()])?;
+            Ok(vec![ws.id].into())
+        }
+        None => Err("No workspace to switch to.".into()),
+    }
+}
+
 pub fn switch_to_app_or_urgent_or_lru_window(
     name: &str,
     stm_data: &mut MutexGuard<SwitchToMatchingData>,
     fdata: &FocusData,
-) {
-    let root = ipc::get_root_node(false);
+) -> CmdResult {
+    let root = ipc::get_root_node(false)?;
     let tree = t::get_tree(&root);
     let wins = tree.get_windows(fdata);
     let pred = |w: &t::DisplayNode| w.node.get_app_name() == name;
 
-    focus_urgent_or_matching_or_lru_window(&wins, fdata, stm_data, pred);
+    focus_urgent_or_matching_or_lru_window(&wins, fdata, stm_data, pred)
 }
 
 pub fn switch_to_mark_or_urgent_or_lru_window(
     con_mark: &str,
     stm_data: &mut MutexGuard<SwitchToMatchingData>,
     fdata: &FocusData,
-) {
-    let root = ipc::get_root_node(false);
+) -> CmdResult {
+    let root = ipc::get_root_node(false)?;
     let tree = t::get_tree(&root);
     let wins = tree.get_windows(fdata);
     let con_mark = &con_mark.to_owned();
     let pred = |w: &t::DisplayNode| w.node.marks.contains(con_mark);
 
-    focus_urgent_or_matching_or_lru_window(&wins, fdata, stm_data, pred);
+    focus_urgent_or_matching_or_lru_window(&wins, fdata, stm_data, pred)
 }
 
 fn switch_to_matching_or_urgent_or_lru_window(
     criteria: &str,
     switch_to_matching_data: &mut MutexGuard<SwitchToMatchingData>,
     fdata: &FocusData,
-) {
-    let root = ipc::get_root_node(false);
+) -> CmdResult {
+    let root = ipc::get_root_node(false)?;
     let tree = t::get_tree(&root);
     let wins = tree.get_windows(fdata);
 
-    if let Some(crit) = criteria::parse_criteria(criteria) {
-        let pred = criteria::criterion_to_predicate(&crit, &wins);
-        focus_urgent_or_matching_or_lru_window(
-            &wins,
-            fdata,
-            switch_to_matching_data,
-            pred,
-        )
+    let crit = criteria::parse_criteria(criteria)?;
+    let pred = criteria::criterion_to_predicate(&crit, &wins, fdata);
+    focus_urgent_or_matching_or_lru_window(
+        &wins,
+        fdata,
+        switch_to_matching_data,
+        pred,
+    )
+}
+
+fn assign_slot(slot: u32, fdata: &FocusData) -> CmdResult {
+    let root = ipc::get_root_node(false)?;
+    let tree = t::get_tree(&root);
+    match tree.get_windows(fdata).iter().find(|w| w.node.focused) {
+        Some(win) => {
+            slots::assign_slot(slot, win.node.id)?;
+            Ok(vec![win.node.id].into())
+        }
+        None => Err("No focused window.".into()),
+    }
+}
+
+fn jump_to_slot(
+    slot: u32,
+    stm_data: &mut MutexGuard<SwitchToMatchingData>,
+    fdata: &FocusData,
+) -> CmdResult {
+    let id = slots::get_slot(slot)
+        .ok_or_else(|| format!("Slot {} is empty.", slot))?;
+    let root = ipc::get_root_node(false)?;
+    let tree = t::get_tree(&root);
+    let wins = tree.get_windows(fdata);
+    let pred = |w: &t::DisplayNode| w.node.id == id;
+
+    focus_urgent_or_matching_or_lru_window(&wins, fdata, stm_data, pred)
+}
+
+/// A window in a slot as shown by `show-slots`.
+struct SlotChoice<'a> {
+    slot: u32,
+    win: &'a t::DisplayNode<'a>,
+}
+
+impl DisplayFormat for SlotChoice<'_> {
+    fn format_for_display(&self, cfg: &cfg::Config) -> String {
+        format!("{}: {}", self.slot, self.win.format_for_display(cfg))
+    }
+
+    fn get_indent_level(&self) -> usize {
+        0
+    }
+}
+
+fn show_slots(fdata: &FocusData) -> CmdResult {
+    let root = ipc::get_root_node(true)?;
+    let tree = t::get_tree(&root);
+    let wins = tree.get_windows(fdata);
+    let choices: Vec<SlotChoice> = slots::get_slots()
+        .into_iter()
+        .filter_map(|(slot, id)| {
+            wins.iter()
+                .find(|w| w.node.id == id)
+                .map(|win| SlotChoice { slot, win })
+        })
+        .collect();
+    if choices.is_empty() {
+        return Err("All slots are empty.".into());
+    }
+    match util::select_from_menu("Select slot", &choices) {
+        Ok(choice) => focus_window_by_id(choice.win.node.id),
+        Err(input) if input.is_empty() => Ok(CmdOutput::default()),
+        Err(input) => Err(format!("No slot matches '{}'.", input).into()),
     }
 }
 
 static DIGIT_AND_NAME: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^(\d):(.*)").unwrap());
 
-fn create_workspace(ws_name: &str) {
+fn create_workspace(ws_name: &str) -> Result<(), String> {
     if DIGIT_AND_NAME.is_match(ws_name) {
-        run_sway_command(&["workspace", "number", ws_name]);
+        run_sway_command(&["workspace", "number", ws_name])
     } else {
-        run_sway_command(&["workspace", ws_name]);
+        run_sway_command(&["workspace", ws_name])
     }
 }
 
@@ -863,37 +1527,42 @@ fn chop_sway_shortcut(input: &str) -> &str {
     }
 }
 
-fn handle_non_matching_input(input: &str) {
+fn handle_non_matching_input(input: &str) -> CmdResult {
     if input.is_empty() {
-        return;
+        return Ok(CmdOutput::default());
     }
 
     if let Some(c) = SPECIAL_SWAY.captures(input) {
-        run_sway_command(&c[1].split_ascii_whitespace().collect::<Vec<&str>>());
+        run_sway_command(
+            &c[1].split_ascii_whitespace().collect::<Vec<&str>>(),
+        )?;
     } else {
         let ws = chop_workspace_shortcut(input);
-        create_workspace(ws);
+        create_workspace(ws)?;
     }
+    Ok(CmdOutput::default())
 }
 
-fn select_and_focus(prompt: &str, choices: &[t::DisplayNode]) {
+fn select_and_focus(prompt: &str, choices: &[t::DisplayNode]) -> CmdResult {
     match util::select_from_menu(prompt, choices) {
         Ok(tn) => match tn.node.get_type() {
             ipc::Type::Output => {
                 if !tn.node.is_scratchpad() {
-                    run_sway_command(&["focus output", tn.node.get_name()]);
+                    run_sway_command(&["focus output", tn.node.get_name()])?;
                 }
+                Ok(vec![tn.node.id].into())
             }
             ipc::Type::Workspace => {
                 if !tn.node.is_scratchpad() {
-                    run_sway_command(&["workspace", tn.node.get_name()]);
+                    run_sway_command(&["workspace", tn.node.get_name()])?;
                 }
+                Ok(vec![tn.node.id].into())
             }
             ipc::Type::Window | ipc::Type::Container => {
-                focus_window_by_id(tn.node.id);
+                focus_window_by_id(tn.node.id)
             }
             t => {
-                log::error!("Cannot handle {:?} in select_and_focus", t)
+                Err(format!("Cannot handle {:?} in select_and_focus", t).into())
             }
         },
         Err(non_matching_input) => {
@@ -902,150 +1571,170 @@ fn select_and_focus(prompt: &str, choices: &[t::DisplayNode]) {
     }
 }
 
-fn select_and_steal(prompt: &str, choices: &[t::DisplayNode]) {
+fn select_and_steal(prompt: &str, choices: &[t::DisplayNode]) -> CmdResult {
     match util::select_from_menu(prompt, choices) {
         Ok(tn) => match tn.node.get_type() {
             ipc::Type::Window | ipc::Type::Container => {
-                steal_window_by_id(tn.node.id);
-            },
-            ipc::Type::Workspace => {
-                log::info!("Can't steal whole workspace")
+                steal_window_by_id(tn.node.id)
             }
+            ipc::Type::Workspace => Err("Can't steal whole workspace".into()),
             t => {
-                log::error!("Cannot handle {:?} in select_and_steal", t)
+                Err(format!("Cannot handle {:?} in select_and_steal", t).into())
             }
         },
-        Err(non_matching_input) => {
-            log::error!("Cannot handle {:?} in select and steal", non_matching_input)
+        Err(non_matching_input) if non_matching_input.is_empty() => {
+            Ok(CmdOutput::default())
         }
+        Err(non_matching_input) => Err(format!(
+            "Cannot handle {:?} in select and steal",
+            non_matching_input
+        )
+        .into()),
     }
 }
 
-pub fn switch_window(fdata: &FocusData) {
-    let root = ipc::get_root_node(true);
+pub fn switch_window(fdata: &FocusData) -> CmdResult {
+    let root = ipc::get_root_node(true)?;
     let tree = t::get_tree(&root);
-    select_and_focus("Select window", &tree.get_windows(fdata));
+    select_and_focus("Select window", &tree.get_windows(fdata))
 }
 
-pub fn steal_window(fdata: &FocusData) {
-    let root = ipc::get_root_node(true);
+pub fn steal_window(fdata: &FocusData) -> CmdResult {
+    let root = ipc::get_root_node(true)?;
     let tree = t::get_tree(&root);
-    select_and_steal("Select window", &tree.get_windows(fdata));
+    select_and_steal("Select window", &tree.get_windows(fdata))
 }
 
-pub fn steal_window_or_container(fdata: &FocusData) {
-    let root = ipc::get_root_node(true);
+pub fn steal_window_or_container(fdata: &FocusData) -> CmdResult {
+    let root = ipc::get_root_node(true)?;
     let tree = t::get_tree(&root);
     select_and_steal(
         "Select window or container",
-        &tree.get_workspaces_containers_and_windows(fdata)
-    );
+        &tree.get_workspaces_containers_and_windows(fdata),
+    )
 }
 
-pub fn switch_workspace(fdata: &FocusData) {
-    let root = ipc::get_root_node(false);
+pub fn switch_workspace(fdata: &FocusData) -> CmdResult {
+    let root = ipc::get_root_node(false)?;
     let tree = t::get_tree(&root);
-    select_and_focus("Select workspace", &tree.get_workspaces(fdata));
+    select_and_focus("Select workspace", &tree.get_workspaces(fdata))
 }
 
-pub fn switch_output() {
-    let root = ipc::get_root_node(false);
+pub fn switch_output() -> CmdResult {
+    let root = ipc::get_root_node(false)?;
     let tree = t::get_tree(&root);
-    select_and_focus("Select output", &tree.get_outputs());
+    select_and_focus("Select output", &tree.get_outputs())
 }
 
-pub fn switch_workspace_or_window(fdata: &FocusData) {
-    let root = ipc::get_root_node(true);
+pub fn switch_workspace_or_window(fdata: &FocusData) -> CmdResult {
+    let root = ipc::get_root_node(true)?;
     let tree = t::get_tree(&root);
     select_and_focus(
         "Select workspace or window",
         &tree.get_workspaces_and_windows(fdata),
-    );
+    )
 }
 
-pub fn switch_workspace_container_or_window(fdata: &FocusData) {
-    let root = ipc::get_root_node(true);
+pub fn switch_workspace_container_or_window(fdata: &FocusData) -> CmdResult {
+    let root = ipc::get_root_node(true)?;
     let tree = t::get_tree(&root);
     select_and_focus(
         "Select workspace, container or window",
         &tree.get_workspaces_containers_and_windows(fdata),
-    );
+    )
 }
 
-pub fn switch_to(fdata: &FocusData) {
-    let root = ipc::get_root_node(true);
+pub fn switch_to(fdata: &FocusData) -> CmdResult {
+    let root = ipc::get_root_node(true)?;
     let tree = t::get_tree(&root);
     select_and_focus(
         "Select output, workspace, container or window",
         &tree.get_outputs_workspaces_containers_and_windows(fdata),
-    );
+    )
 }
 
-fn kill_process_by_pid(pid: Option<i32>) {
+fn kill_process_by_pid(pid: Option<i32>) -> Result<(), String> {
     if let Some(pid) = pid {
-        if let Err(err) = std::process::Command::new("kill")
+        match std::process::Command::new("kill")
             .arg("-9")
             .arg(format!("{}", pid))
             .output()
         {
-            log::error!("Error killing process {}: {}", pid, err)
+            Ok(output) if output.status.success() => Ok(()),
+            Ok(output) => Err(format!(
+                "Error killing process {}: {}",
+                pid,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(err) => Err(format!("Error killing process {}: {}", pid, err)),
         }
     } else {
-        log::error!("Cannot kill window with no pid.");
+        Err("Cannot kill window with no pid.".to_string())
     }
 }
 
-fn select_and_quit(prompt: &str, choices: &[t::DisplayNode], kill: bool) {
+fn select_and_quit(
+    prompt: &str,
+    choices: &[t::DisplayNode],
+    kill: bool,
+) -> CmdResult {
     if let Ok(tn) = util::select_from_menu(prompt, choices) {
         match tn.node.get_type() {
             ipc::Type::Workspace | ipc::Type::Container => {
+                let mut ids = vec![];
                 for win in
                     tn.node.iter().filter(|n| n.get_type() == ipc::Type::Window)
                 {
-                    quit_window_by_id(win.id)
+                    ids.append(&mut quit_window_by_id(win.id)?.con_ids);
                 }
+                Ok(ids.into())
             }
             ipc::Type::Window => {
                 if kill {
-                    kill_process_by_pid(tn.node.pid)
+                    kill_process_by_pid(tn.node.pid)?;
+                    Ok(vec![tn.node.id].into())
                 } else {
                     quit_window_by_id(tn.node.id)
                 }
             }
-            t => {
-                log::error!("Cannot handle {:?} in quit_workspace_or_window", t)
-            }
+            t => Err(format!(
+                "Cannot handle {:?} in quit_workspace_or_window",
+                t
+            )
+            .into()),
         }
+    } else {
+        Ok(CmdOutput::default())
     }
 }
 
-pub fn quit_window(fdata: &FocusData, kill: bool) {
-    let root = ipc::get_root_node(true);
+pub fn quit_window(fdata: &FocusData, kill: bool) -> CmdResult {
+    let root = ipc::get_root_node(true)?;
     let tree = t::get_tree(&root);
-    select_and_quit("Quit window", &tree.get_windows(fdata), kill);
+    select_and_quit("Quit window", &tree.get_windows(fdata), kill)
 }
 
-pub fn quit_workspace_or_window(fdata: &FocusData) {
-    let root = ipc::get_root_node(true);
+pub fn quit_workspace_or_window(fdata: &FocusData) -> CmdResult {
+    let root = ipc::get_root_node(true)?;
     let tree = t::get_tree(&root);
     select_and_quit(
         "Quit workspace or window",
         &tree.get_workspaces_and_windows(fdata),
         false,
-    );
+    )
 }
 
-pub fn quit_workspace_container_or_window(fdata: &FocusData) {
-    let root = ipc::get_root_node(true);
+pub fn quit_workspace_container_or_window(fdata: &FocusData) -> CmdResult {
+    let root = ipc::get_root_node(true)?;
     let tree = t::get_tree(&root);
     select_and_quit(
         "Quit workspace, container or window",
         &tree.get_workspaces_containers_and_windows(fdata),
         false,
-    );
+    )
 }
 
-fn move_focused_to_workspace_1(ws_name: &str) {
+fn move_focused_to_workspace_1(ws_name: &str) -> Result<(), String> {
     if DIGIT_AND_NAME.is_match(ws_name) {
         run_sway_command(&[
             "move",
@@ -1054,75 +1743,85 @@ fn move_focused_to_workspace_1(ws_name: &str) {
             "workspace",
             "number",
             ws_name,
-        ]);
+        ])
     } else {
-        run_sway_command(&["move", "container", "to", "workspace", ws_name]);
+        run_sway_command(&["move", "container", "to", "workspace", ws_name])
     }
 }
 
-fn move_focused_to_container_or_window(id: i64) {
+fn move_focused_to_container_or_window(id: i64) -> Result<(), String> {
     run_sway_command(&[
         &format!("[con_id=\"{}\"]", id),
         "mark",
         "--add",
         "__SWAYR_MOVE_TARGET__",
-    ]);
-    run_sway_command(&["move", "to", "mark", "__SWAYR_MOVE_TARGET__"]);
-    run_sway_command(&["unmark", "__SWAYR_MOVE_TARGET__"]);
+    ])?;
+    let result =
+        run_sway_command(&["move", "to", "mark", "__SWAYR_MOVE_TARGET__"]);
+    run_sway_command(&["unmark", "__SWAYR_MOVE_TARGET__"])?;
+    result
 }
 
-fn select_and_move_focused_to(prompt: &str, choices: &[t::DisplayNode]) {
+fn select_and_move_focused_to(
+    prompt: &str,
+    choices: &[t::DisplayNode],
+) -> CmdResult {
     match util::select_from_menu(prompt, choices) {
-        Ok(tn) => match tn.node.get_type() {
-            ipc::Type::Output => {
-                if tn.node.is_scratchpad() {
-                    run_sway_command_1("move container to scratchpad")
-                } else {
-                    run_sway_command(&[
-                        "move container to output",
-                        tn.node.get_name(),
-                    ])
+        Ok(tn) => {
+            match tn.node.get_type() {
+                ipc::Type::Output => {
+                    if tn.node.is_scratchpad() {
+                        run_sway_command_1("move container to scratchpad")
+                    } else {
+                        run_sway_command(&[
+                            "move container to output",
+                            tn.node.get_name(),
+                        ])
+                    }
                 }
-            }
-            ipc::Type::Workspace => {
-                if tn.node.is_scratchpad() {
-                    run_sway_command_1("move container to scratchpad")
-                } else {
-                    move_focused_to_workspace_1(tn.node.get_name())
+                ipc::Type::Workspace => {
+                    if tn.node.is_scratchpad() {
+                        run_sway_command_1("move container to scratchpad")
+                    } else {
+                        move_focused_to_workspace_1(tn.node.get_name())
+                    }
                 }
-            }
-            ipc::Type::Container | ipc::Type::Window => {
-                move_focused_to_container_or_window(tn.node.id)
-            }
-            t => log::error!("Cannot move focused to {:?}", t),
-        },
+                ipc::Type::Container | ipc::Type::Window => {
+                    move_focused_to_container_or_window(tn.node.id)
+                }
+                t => Err(format!("Cannot move focused to {:?}", t)),
+            }?;
+            Ok(vec![tn.node.id].into())
+        }
+        Err(input) if input.is_empty() => Ok(CmdOutput::default()),
         Err(input) => {
             let ws_name = chop_workspace_shortcut(&input);
-            move_focused_to_workspace_1(ws_name);
+            move_focused_to_workspace_1(ws_name)?;
+            Ok(CmdOutput::default())
         }
     }
 }
 
-pub fn move_focused_to_workspace(fdata: &FocusData) {
-    let root = ipc::get_root_node(true);
+pub fn move_focused_to_workspace(fdata: &FocusData) -> CmdResult {
+    let root = ipc::get_root_node(true)?;
     let tree = t::get_tree(&root);
     select_and_move_focused_to(
         "Move focused container to workspace",
         &tree.get_workspaces(fdata),
-    );
+    )
 }
 
-pub fn move_focused_to(fdata: &FocusData) {
-    let root = ipc::get_root_node(true);
+pub fn move_focused_to(fdata: &FocusData) -> CmdResult {
+    let root = ipc::get_root_node(true)?;
     let tree = t::get_tree(&root);
     select_and_move_focused_to(
         "Move focused container to workspace or container",
         &tree.get_outputs_workspaces_containers_and_windows(fdata),
-    );
+    )
 }
 
-pub fn swap_focused_with(fdata: &FocusData) {
-    let root = ipc::get_root_node(true);
+pub fn swap_focused_with(fdata: &FocusData) -> CmdResult {
+    let root = ipc::get_root_node(true)?;
     let tree = t::get_tree(&root);
     match util::select_from_menu(
         "Swap focused with",
@@ -1136,13 +1835,16 @@ pub fn swap_focused_with(fdata: &FocusData) {
                     "with",
                     "con_id",
                     &format!("{}", tn.node.id),
-                ])
+                ])?;
+                Ok(vec![tn.node.id].into())
             }
-            t => log::error!("Cannot move focused to {:?}", t),
+            t => Err(format!("Cannot move focused to {:?}", t).into()),
         },
+        Err(input) if input.is_empty() => Ok(CmdOutput::default()),
         Err(input) => {
             let ws_name = chop_workspace_shortcut(&input);
-            move_focused_to_workspace_1(ws_name);
+            move_focused_to_workspace_1(ws_name)?;
+            Ok(CmdOutput::default())
         }
     }
 }
@@ -1156,24 +1858,25 @@ fn focus_window_in_direction_1(
     wins: &[t::DisplayNode],
     dir: Direction,
     fdata: &FocusData,
+    scope: &LruScope,
     pred: impl Fn(&t::DisplayNode) -> bool,
-) {
+) -> CmdResult {
     let mut wins: Vec<&t::DisplayNode> =
         wins.iter().filter(|w| pred(w)).collect();
 
     if wins.is_empty() {
-        return;
+        return Err("No matching window.".into());
     }
 
     wins.sort_by(|a, b| {
-        let lru_a = fdata.last_focus_tick(a.node.id);
-        let lru_b = fdata.last_focus_tick(b.node.id);
+        let lru_a = fdata.lru_key(a.node.id, scope);
+        let lru_b = fdata.lru_key(b.node.id, scope);
         lru_a.cmp(&lru_b).reverse()
     });
 
     let is_focused_window: Box<dyn Fn(&t::DisplayNode) -> bool> =
         if !wins.iter().any(|w| w.node.focused) {
-            let last_focused_win_id = wins.get(0).unwrap().node.id;
+            let last_focused_win_id = wins.first().unwrap().node.id;
             Box::new(move |dn| dn.node.id == last_focused_win_id)
         } else {
             Box::new(|dn| dn.node.focused)
@@ -1188,8 +1891,7 @@ fn focus_window_in_direction_1(
         let win = iter.next().unwrap();
         if is_focused_window(win) {
             let win = iter.next().unwrap();
-            focus_window_by_id(win.node.id);
-            return;
+            return focus_window_by_id(win.node.id);
         }
     }
 }
@@ -1198,14 +1900,126 @@ fn focus_matching_window_in_direction(
     dir: Direction,
     criteria: &str,
     fdata: &FocusData,
-) {
-    let root = ipc::get_root_node(false);
+) -> CmdResult {
+    let root = ipc::get_root_node(false)?;
+    let tree = t::get_tree(&root);
+    let wins = tree.get_windows(fdata);
+
+    let crits = criteria::parse_criteria(criteria)?;
+    let pred = criteria::criterion_to_predicate(&crits, &wins, fdata);
+    focus_window_in_direction_1(&wins, dir, fdata, &LruScope::Global, pred)
+}
+
+/// The windows matching the criteria query.  In case of a dry run, they are
+/// returned as data like `get-windows` does.  Otherwise, the sway commands
+/// returned by `cmds_for` for each window are run at once.
+fn for_each_matching<F>(
+    criteria: &str,
+    dry_run: bool,
+    fdata: &FocusData,
+    mut cmds_for: F,
+) -> CmdResult
+where
+    F: FnMut(&s::Node) -> Result<Vec<String>, String>,
+{
+    let root = ipc::get_root_node(false)?;
     let tree = t::get_tree(&root);
     let wins = tree.get_windows(fdata);
 
-    if let Some(crits) = criteria::parse_criteria(criteria) {
-        let pred = criteria::criterion_to_predicate(&crits, &wins);
-        focus_window_in_direction_1(&wins, dir, fdata, pred);
+    let crits = criteria::parse_criteria(criteria)?;
+    let pred = criteria::criterion_to_predicate(&crits, &wins, fdata);
+    let matching: Vec<&t::DisplayNode> =
+        wins.iter().filter(|w| pred(w)).collect();
+    if matching.is_empty() {
+        return Err("No matching window.".into());
+    }
+    let ids: Vec<i64> = matching.iter().map(|w| w.node.id).collect();
+    if dry_run {
+        let infos: Vec<query::WindowInfo> = matching
+            .iter()
+            .map(|w| query::window_info(w, fdata))
+            .collect();
+        return Ok(CmdOutput {
+            con_ids: ids,
+            data: Some(
+                serde_json::to_value(infos).map_err(|err| err.to_string())?,
+            ),
+        });
+    }
+    let mut cmds = vec![];
+    for win in &matching {
+        cmds.append(&mut cmds_for(win.node)?);
+    }
+    ipc::run_commands(&cmds)?;
+    Ok(ids.into())
+}
+
+fn quit_matching(
+    criteria: &str,
+    kill: bool,
+    dry_run: bool,
+    fdata: &FocusData,
+) -> CmdResult {
+    if !kill {
+        return for_each_matching(criteria, dry_run, fdata, |win| {
+            Ok(vec![format!("[con_id={}] kill", win.id)])
+        });
+    }
+    // Windows of the same process are killed just once, and only after all
+    // matching windows have been collected.
+    let mut pids = vec![];
+    let output = for_each_matching(criteria, dry_run, fdata, |win| {
+        if !pids.contains(&win.pid) {
+            pids.push(win.pid);
+        }
+        Ok(vec![])
+    })?;
+    let errors: Vec<String> = pids
+        .into_iter()
+        .filter_map(|pid| kill_process_by_pid(pid).err())
+        .collect();
+    if errors.is_empty() {
+        Ok(output)
+    } else {
+        Err(
+            format!("Could not kill all processes: {}", errors.join(" "))
+                .into(),
+        )
+    }
+}
+
+fn run_or_raise(
+    criteria: &str,
+    command: &str,
+    placement: launcher::Placement,
+    timeout: Duration,
+    fdata: &FocusData,
+) -> CmdResult {
+    let root = ipc::get_root_node(false)?;
+    let tree = t::get_tree(&root);
+    let wins = tree.get_windows(fdata);
+
+    let crits = criteria::parse_criteria(criteria)?;
+    let pred = criteria::criterion_to_predicate(&crits, &wins, fdata);
+    if wins.iter().any(|w| w.node.focused && pred(w)) {
+        return focus_window_in_direction_1(
+            &wins,
+            Direction::Forward,
+            fdata,
+            &LruScope::Global,
+            pred,
+        );
+    }
+    match wins
+        .iter()
+        .filter(|w| pred(w))
+        .max_by_key(|w| fdata.lru_key(w.node.id, &LruScope::Global))
+    {
+        Some(win) => focus_window_by_id(win.node.id),
+        None => {
+            launcher::launch_and_place(command, criteria, placement, timeout)?;
+            Ok(CmdOutput::default())
+        }
     }
 }
 
@@ -1214,30 +2028,20 @@ pub fn focus_window_in_direction(
     consider_wins: &ConsiderWindows,
     fdata: &FocusData,
     pred: impl Fn(&t::DisplayNode) -> bool,
-) {
-    let root = ipc::get_root_node(false);
+) -> CmdResult {
+    let root = ipc::get_root_node(false)?;
     let tree = t::get_tree(&root);
     let mut wins = tree.get_windows(fdata);
-
-    if consider_wins == &ConsiderWindows::CurrentWorkspace {
-        let cur_ws = tree.get_current_workspace();
-        wins.retain(|w| {
-            tree.get_parent_node_of_type(w.node.id, ipc::Type::Workspace)
-                .unwrap()
-                .id
-                == cur_ws.id
-        });
-    }
-
-    focus_window_in_direction_1(&wins, dir, fdata, pred);
+    let scope = retain_considered_windows(&tree, &mut wins, consider_wins);
+    focus_window_in_direction_1(&wins, dir, fdata, &scope, pred)
 }
 
 pub fn focus_window_of_same_layout_in_direction(
     dir: Direction,
     consider_wins: &ConsiderWindows,
     fdata: &FocusData,
-) {
-    let root = ipc::get_root_node(false);
+) -> CmdResult {
+    let root = ipc::get_root_node(false)?;
     let tree = t::get_tree(&root);
     let wins = tree.get_windows(fdata);
     let cur_win = wins.iter().find(|w| w.node.focused);
@@ -1271,15 +2075,20 @@ pub fn focus_window_of_same_layout_in_direction(
                 always_true
             },
         )
+    } else {
+        Err("No focused window.".into())
     }
 }
 
-fn tile_current_workspace(floating: &ConsiderFloating, shuffle: bool) {
-    match layout::relayout_current_workspace(
+fn tile_current_workspace(
+    floating: &ConsiderFloating,
+    shuffle: bool,
+) -> CmdResult {
+    layout::relayout_current_workspace(
         floating == &ConsiderFloating::IncludeFloating,
-        move |wins, con: &mut s::Connection| {
-            con.run_command("focus parent")?;
-            con.run_command("layout splith")?;
+        move |wins| {
+            let mut cmds =
+                vec!["focus parent".to_owned(), "layout splith".to_owned()];
 
             let mut placed_wins = vec![];
             let mut rng = rand::thread_rng();
@@ -1290,72 +2099,58 @@ fn tile_current_workspace(floating: &ConsiderFloating, shuffle: bool) {
             }
             for win in wins {
                 if win.is_floating() {
-                    con.run_command(format!(
-                        "[con_id={}] floating disable",
-                        win.id
-                    ))?;
+                    cmds.push(format!("[con_id={}] floating disable", win.id));
                 }
-                std::thread::sleep(std::time::Duration::from_millis(25));
-                con.run_command(format!(
+                cmds.push(format!(
                     "[con_id={}] move to workspace current",
                     win.id
-                ))?;
+                ));
                 placed_wins.push(win);
                 if shuffle {
-                    std::thread::sleep(std::time::Duration::from_millis(25));
                     if let Some(win) = placed_wins.choose(&mut rng) {
-                        con.run_command(format!("[con_id={}] focus", win.id))?;
+                        cmds.push(format!("[con_id={}] focus", win.id));
                     }
                 }
             }
-            Ok(())
+            cmds
         },
-    ) {
-        Ok(_) => (),
-        Err(err) => log::error!("Error retiling workspace: {:?}", err),
-    }
+    )
+    .map(|_| CmdOutput::default())
+    .map_err(|err| format!("Error retiling workspace: {}", err).into())
 }
 
-fn tab_current_workspace(floating: &ConsiderFloating) {
-    match layout::relayout_current_workspace(
+fn tab_current_workspace(floating: &ConsiderFloating) -> CmdResult {
+    layout::relayout_current_workspace(
         floating == &ConsiderFloating::IncludeFloating,
-        move |wins, con: &mut s::Connection| {
-            con.run_command("focus parent")?;
-            con.run_command("layout tabbed")?;
+        move |wins| {
+            let mut cmds =
+                vec!["focus parent".to_owned(), "layout tabbed".to_owned()];
 
-            let mut placed_wins = vec![];
             wins.reverse();
             for win in wins {
                 if win.is_floating() {
-                    con.run_command(format!(
-                        "[con_id={}] floating disable",
-                        win.id
-                    ))?;
+                    cmds.push(format!("[con_id={}] floating disable", win.id));
                 }
-
-                std::thread::sleep(std::time::Duration::from_millis(25));
-                con.run_command(format!(
+                cmds.push(format!(
                     "[con_id={}] move to workspace current",
                     win.id
-                ))?;
-                placed_wins.push(win);
+                ));
             }
-            Ok(())
+            cmds
         },
-    ) {
-        Ok(_) => (),
-        Err(err) => log::error!("Error retiling workspace: {:?}", err),
-    }
+    )
+    .map(|_| CmdOutput::default())
+    .map_err(|err| format!("Error retiling workspace: {}", err).into())
 }
 
-fn toggle_tab_tile_current_workspace(floating: &ConsiderFloating) {
-    let tree = ipc::get_root_node(false);
+fn toggle_tab_tile_current_workspace(floating: &ConsiderFloating) -> CmdResult {
+    let tree = ipc::get_root_node(false)?;
     let workspaces = tree.nodes_of_type(ipc::Type::Workspace);
     let cur_ws = workspaces.iter().find(|w| w.is_current()).unwrap();
     if cur_ws.layout == s::NodeLayout::Tabbed {
-        tile_current_workspace(floating, true);
+        tile_current_workspace(floating, true)
     } else {
-        tab_current_workspace(floating);
+        tab_current_workspace(floating)
     }
 }
 
@@ -1462,21 +2257,23 @@ impl DisplayFormat for SwaymsgCmd {
     }
 }
 
-pub fn exec_swaymsg_command() {
+pub fn exec_swaymsg_command() -> CmdResult {
     let cmds = get_swaymsg_commands();
     let cmd = util::select_from_menu("Execute swaymsg command", &cmds);
     match cmd {
-        Ok(cmd) => run_sway_command_1(&cmd.cmd),
+        Ok(cmd) => run_sway_command_1(&cmd.cmd)?,
         Err(cmd) if !cmd.is_empty() => {
             let cmd = chop_sway_shortcut(&cmd);
-            run_sway_command_1(cmd);
+            run_sway_command_1(cmd)?;
         }
         Err(_) => (),
     }
+    Ok(CmdOutput::default())
 }
 
-pub fn configure_outputs() {
-    let outputs = get_outputs();
+pub fn configure_outputs() -> CmdResult {
+    let outputs = ipc::get_outputs()
+        .map_err(|err| format!("Could not get outputs: {}", err))?;
 
     let mut cmds = vec![];
     for o in outputs {
@@ -1526,6 +2323,9 @@ pub fn configure_outputs() {
         cmds.into_iter().map(|c| SwaymsgCmd { cmd: c }).collect();
 
     while let Ok(cmd) = util::select_from_menu("Output command", &cmds) {
-        run_sway_command_1(&cmd.cmd);
+        if let Err(err) = run_sway_command_1(&cmd.cmd) {
+            log::error!("{}", err);
+        }
     }
+    Ok(CmdOutput::default())
 }
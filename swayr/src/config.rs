@@ -15,10 +15,15 @@
 
 //! TOML configuration for swayr.
 
+use crate::criteria;
+use crate::dynamic_layout::DynamicLayout;
+use crate::rules;
 use crate::shared::cfg;
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::time::Duration;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
@@ -26,7 +31,14 @@ pub struct Config {
     format: Option<Format>,
     layout: Option<Layout>,
     focus: Option<Focus>,
+    slots: Option<Slots>,
     misc: Option<Misc>,
+    rules: Option<Vec<Rule>>,
+    /// Named criteria queries which can be referenced as `@name`.
+    criteria: Option<HashMap<String, String>>,
+    /// The rules parsed when the config is loaded.
+    #[serde(skip)]
+    compiled_rules: Vec<rules::CompiledRule>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -41,6 +53,8 @@ pub struct Format {
     workspace_format: Option<String>,
     container_format: Option<String>,
     window_format: Option<String>,
+    /// The format of applications in the `launch-app` menu.
+    app_format: Option<String>,
     indent: Option<String>,
     urgency_start: Option<String>,
     urgency_end: Option<String>,
@@ -53,11 +67,31 @@ pub struct Format {
 pub struct Layout {
     auto_tile: Option<bool>,
     auto_tile_min_window_width_per_output_width: Option<Vec<[i32; 2]>>,
+    /// The dynamic layout of workspaces without a more specific setting.
+    dynamic_layout: Option<DynamicLayout>,
+    /// The initial number of windows in the master area.
+    master_count: Option<usize>,
+    /// Dynamic layouts by workspace name.
+    dynamic_layout_per_workspace: Option<HashMap<String, DynamicLayout>>,
+    /// Dynamic layouts by output name.
+    dynamic_layout_per_output: Option<HashMap<String, DynamicLayout>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Focus {
     lockin_delay: Option<u64>,
+    /// Save the LRU focus history in the state directory and restore it when
+    /// swayrd starts.
+    persist_history: Option<bool>,
+    /// Interval in seconds in which the focus history is saved.
+    persist_interval: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Slots {
+    /// Save the slot assignments in the state directory and restore them when
+    /// swayrd starts.
+    persist: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -67,6 +101,22 @@ pub struct Misc {
 
     /// Inhibit LRU updates during sequences of window cycling commands
     seq_inhibit: Option<bool>,
+
+    /// The terminal command which runs applications with `Terminal=true` in
+    /// their desktop entries.  The application's command is appended.
+    terminal: Option<String>,
+}
+
+/// A window rule applied by swayrd when a new window appears.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rule {
+    /// The swayr criteria query the window has to match.
+    pub criteria: String,
+    /// Sway commands which are run for the matching window, or swayr commands
+    /// prefixed with `swayr`.
+    pub actions: Vec<String>,
+    /// Also apply the rule when the title of a window changes.
+    pub on_title_change: Option<bool>,
 }
 
 fn tilde_expand_file_names(file_names: Vec<String>) -> Vec<String> {
@@ -134,6 +184,14 @@ impl Config {
             .expect("No format.window_format defined.")
     }
 
+    pub fn get_format_app_format(&self) -> String {
+        self.format
+            .as_ref()
+            .and_then(|f| f.app_format.clone())
+            .or_else(|| Format::default().app_format)
+            .expect("No format.app_format defined.")
+    }
+
     pub fn get_format_indent(&self) -> String {
         self.format
             .as_ref()
@@ -199,6 +257,51 @@ impl Config {
             .expect("No layout.auto_tile_min_window_width_per_output_width defined.")
     }
 
+    /// The dynamic layout configured for the given workspace on the given
+    /// output.
+    pub fn get_layout_dynamic_layout(
+        &self,
+        workspace: &str,
+        output: &str,
+    ) -> DynamicLayout {
+        let layout = self.layout.as_ref();
+        layout
+            .and_then(|l| l.dynamic_layout_per_workspace.as_ref())
+            .and_then(|m| m.get(workspace).copied())
+            .or_else(|| {
+                layout
+                    .and_then(|l| l.dynamic_layout_per_output.as_ref())
+                    .and_then(|m| m.get(output).copied())
+            })
+            .or_else(|| layout.and_then(|l| l.dynamic_layout))
+            .or_else(|| Layout::default().dynamic_layout)
+            .expect("No layout.dynamic_layout defined.")
+    }
+
+    /// True if some workspace might have a dynamic layout.
+    pub fn has_layout_dynamic_layouts(&self) -> bool {
+        let layout = match self.layout.as_ref() {
+            Some(layout) => layout,
+            None => return false,
+        };
+        let per_ws = layout.dynamic_layout_per_workspace.iter();
+        let per_output = layout.dynamic_layout_per_output.iter();
+        layout.dynamic_layout.unwrap_or(DynamicLayout::None)
+            != DynamicLayout::None
+            || per_ws
+                .chain(per_output)
+                .flat_map(|m| m.values())
+                .any(|l| *l != DynamicLayout::None)
+    }
+
+    pub fn get_layout_master_count(&self) -> usize {
+        self.layout
+            .as_ref()
+            .and_then(|l| l.master_count)
+            .or_else(|| Layout::default().master_count)
+            .expect("No layout.master_count defined.")
+    }
+
     pub fn get_focus_lockin_delay(&self) -> Duration {
         Duration::from_millis(
             self.focus
@@ -209,6 +312,32 @@ impl Config {
         )
     }
 
+    pub fn get_focus_persist_history(&self) -> bool {
+        self.focus
+            .as_ref()
+            .and_then(|f| f.persist_history)
+            .or_else(|| Focus::default().persist_history)
+            .expect("No focus.persist_history defined.")
+    }
+
+    pub fn get_focus_persist_interval(&self) -> Duration {
+        Duration::from_secs(
+            self.focus
+                .as_ref()
+                .and_then(|f| f.persist_interval)
+                .or_else(|| Focus::default().persist_interval)
+                .expect("No focus.persist_interval defined."),
+        )
+    }
+
+    pub fn get_slots_persist(&self) -> bool {
+        self.slots
+            .as_ref()
+            .and_then(|s| s.persist)
+            .or_else(|| Slots::default().persist)
+            .expect("No slots.persist defined.")
+    }
+
     pub fn get_misc_auto_nop_delay(&self) -> Option<Duration> {
         self.misc
             .as_ref()
@@ -223,6 +352,49 @@ impl Config {
             .or_else(|| Misc::default().seq_inhibit)
             .expect("No misc.seq_inhibit defined.")
     }
+
+    pub fn get_misc_terminal(&self) -> String {
+        self.misc
+            .as_ref()
+            .and_then(|m| m.terminal.clone())
+            .or_else(|| Misc::default().terminal)
+            .expect("No misc.terminal defined.")
+    }
+
+    pub fn get_rules(&self) -> Vec<Rule> {
+        self.rules.clone().unwrap_or_default()
+    }
+
+    pub fn get_compiled_rules(&self) -> &[rules::CompiledRule] {
+        &self.compiled_rules
+    }
+
+    /// Validates the named criteria and compiles the rules.
+    fn prepare(&mut self) -> Result<(), String> {
+        let named = self.get_criteria();
+        criteria::validate_named_criteria(&named)
+            .map_err(|err| err.to_string())?;
+        self.compiled_rules = rules::compile_rules(&self.get_rules(), &named)?;
+        Ok(())
+    }
+
+    /// Like `prepare` but if the named criteria or the rules are invalid,
+    /// they are dropped so that the rest of the config can still be used.
+    fn prepare_or_disable(&mut self) {
+        if let Err(err) = self.prepare() {
+            log::error!(
+                "Invalid config, named criteria and rules are disabled: {}",
+                err
+            );
+            self.criteria = None;
+            self.rules = None;
+            self.compiled_rules = vec![];
+        }
+    }
+
+    pub fn get_criteria(&self) -> HashMap<String, String> {
+        self.criteria.clone().unwrap_or_default()
+    }
 }
 
 impl Layout {
@@ -288,6 +460,10 @@ impl Default for Format {
                  <span alpha=\"20000\">({id})</span>"
                     .to_string(),
             ),
+            app_format: Some(
+                "img:{app_icon}:text:<b>{name}</b> — <i>{comment}</i>"
+                    .to_string(),
+            ),
             indent: Some("    ".to_string()),
             html_escape: Some(true),
             urgency_start: Some(
@@ -328,6 +504,10 @@ impl Default for Layout {
             auto_tile_min_window_width_per_output_width: Some(
                 resolution_min_width_vec,
             ),
+            dynamic_layout: Some(DynamicLayout::None),
+            master_count: Some(1),
+            dynamic_layout_per_workspace: None,
+            dynamic_layout_per_output: None,
         }
     }
 }
@@ -336,6 +516,16 @@ impl Default for Focus {
     fn default() -> Self {
         Self {
             lockin_delay: Some(750),
+            persist_history: Some(true),
+            persist_interval: Some(60),
+        }
+    }
+}
+
+impl Default for Slots {
+    fn default() -> Self {
+        Self {
+            persist: Some(false),
         }
     }
 }
@@ -345,6 +535,10 @@ impl Default for Misc {
         Self {
             auto_nop_delay: None,
             seq_inhibit: Some(false),
+            terminal: Some(
+                std::env::var("TERMINAL")
+                    .unwrap_or_else(|_| "foot".to_string()),
+            ),
         }
     }
 }
@@ -356,13 +550,49 @@ impl Default for Config {
             format: Some(Format::default()),
             layout: Some(Layout::default()),
             focus: Some(Focus::default()),
+            slots: Some(Slots::default()),
             misc: Some(Misc::default()),
+            rules: None,
+            criteria: None,
+            compiled_rules: vec![],
         }
     }
 }
 
 pub fn load_config() -> Config {
-    cfg::load_config::<Config>("swayr")
+    let mut config = cfg::load_config::<Config>("swayr");
+    config.prepare_or_disable();
+    config
+}
+
+static CONFIG: Lazy<RwLock<Arc<Config>>> =
+    Lazy::new(|| RwLock::new(Arc::new(load_config())));
+
+/// Returns the current config.  Callers should not hold on to it for long so
+/// that reloads take effect.
+pub fn get_config() -> Arc<Config> {
+    CONFIG.read().expect("Could not lock config").clone()
+}
+
+/// Re-reads the config file.  If it can't be read or is invalid, the current
+/// config is kept.
+pub fn reload_config() -> Result<(), String> {
+    let path = cfg::get_config_file_path("swayr");
+    let mut config = cfg::try_load_config_file::<Config>(&path)?;
+    config
+        .prepare()
+        .map_err(|err| format!("Invalid config: {}", err))?;
+    *CONFIG.write().expect("Could not lock config") = Arc::new(config);
+    log::info!("Reloaded config from {}.", path.to_string_lossy());
+    Ok(())
+}
+
+/// The modification time of the config file, used for reloading it when it
+/// has been changed.
+pub fn config_file_mtime() -> Option<SystemTime> {
+    std::fs::metadata(cfg::get_config_file_path("swayr"))
+        .and_then(|m| m.modified())
+        .ok()
 }
 
 #[test]
@@ -370,3 +600,31 @@ fn test_load_swayr_config() {
     let cfg = cfg::load_config::<Config>("swayr");
     println!("{:?}", cfg);
 }
+
+#[test]
+fn test_try_load_invalid_config() {
+    let path = std::env::temp_dir()
+        .join(format!("swayr-invalid-config-{}.toml", std::process::id()));
+    std::fs::write(&path, "[focus]\nlockin_delay = \"soon\"\n").unwrap();
+    let result = cfg::try_load_config_file::<Config>(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert!(result.unwrap_err().starts_with("Invalid config"));
+}
+
+#[test]
+fn test_prepare_or_disable() {
+    let path = std::env::temp_dir()
+        .join(format!("swayr-cyclic-config-{}.toml", std::process::id()));
+    std::fs::write(
+        &path,
+        "[criteria]\na = '@b'\nb = '@a'\n\n\
+         [[rules]]\ncriteria = '@a'\nactions = ['floating enable']\n",
+    )
+    .unwrap();
+    let mut config = cfg::try_load_config_file::<Config>(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    config.prepare_or_disable();
+    assert!(config.get_criteria().is_empty());
+    assert!(config.get_rules().is_empty());
+    assert!(config.get_compiled_rules().is_empty());
+}
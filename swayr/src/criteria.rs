@@ -15,29 +15,101 @@
 
 //! Implementation of sway's criteria API.
 
+use crate::config;
+use crate::focus::{FocusData, LruScope};
 use crate::{shared::ipc, shared::ipc::NodeMethods, tree as t};
 use regex::Regex;
+use serde::{Serialize, Serializer};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
 use swayipc as s;
 
-#[derive(Debug)]
+fn serialize_regex<S: Serializer>(
+    rx: &Regex,
+    ser: S,
+) -> Result<S::Ok, S::Error> {
+    ser.serialize_str(rx.as_str())
+}
+
+fn serialize_shell_type<S: Serializer>(
+    shell: &s::ShellType,
+    ser: S,
+) -> Result<S::Ok, S::Error> {
+    ser.serialize_str(match shell {
+        s::ShellType::XdgShell => "xdg_shell",
+        s::ShellType::Xwayland => "xwayland",
+        _ => "unknown",
+    })
+}
+
+fn serialize_secs<S: Serializer>(
+    d: &Duration,
+    ser: S,
+) -> Result<S::Ok, S::Error> {
+    ser.serialize_u64(d.as_secs())
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RegexOrFocused {
-    Regex(Regex),
+    Regex(#[serde(serialize_with = "serialize_regex")] Regex),
     Focused,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum I64OrFocused {
     I64(i64),
     Focused,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ShellTypeOrFocused {
-    ShellType(s::ShellType),
+    ShellType(#[serde(serialize_with = "serialize_shell_type")] s::ShellType),
     Focused,
 }
 
-#[derive(Debug)]
+/// A window dimension which can be compared numerically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Geometry {
+    X,
+    Y,
+    Width,
+    Height,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn compare(&self, a: i32, b: i32) -> bool {
+        match self {
+            Comparison::Eq => a == b,
+            Comparison::Ne => a != b,
+            Comparison::Lt => a < b,
+            Comparison::Le => a <= b,
+            Comparison::Gt => a > b,
+            Comparison::Ge => a >= b,
+        }
+    }
+}
+
+/// A parsed criteria query.  It's serialized as objects with the criterion's
+/// `type` and its `args`, if any.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", content = "args", rename_all = "snake_case")]
 pub enum Criterion {
     // And/Or/Not aren't specified by sway.
     And(Vec<Criterion>),
@@ -50,28 +122,195 @@ pub enum Criterion {
     /// depending on if the window is a wayland or X11 window.
     AppName(RegexOrFocused),
     Title(RegexOrFocused),
-    ConMark(Regex),
+    ConMark(#[serde(serialize_with = "serialize_regex")] Regex),
+    /// Not specified by sway: matches windows with any mark.
+    HasMark,
     ConId(I64OrFocused),
+    /// The X11 window id of Xwayland windows.
+    Id(i64),
     Pid(i32),
+    WindowRole(RegexOrFocused),
     Workspace(RegexOrFocused),
     Shell(ShellTypeOrFocused),
     Floating,
     Tiling,
-    // TODO: There are more...
+    Urgent,
+    // The following aren't specified by sway.
+    Output(RegexOrFocused),
+    Fullscreen,
+    Sticky,
+    Visible,
+    Geometry(Geometry, Comparison, i32),
+    /// Compares the position in the LRU order where 0 is the most recently
+    /// used window.
+    Lru(Comparison, i32),
+    /// Serialized as seconds.
+    FocusedWithin(#[serde(serialize_with = "serialize_secs")] Duration),
+    NeverFocused,
+    /// The window which has been created last.
+    Newest,
+}
+
+/// Why a criteria query is invalid.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CriteriaErrorKind {
+    /// The query doesn't conform to the grammar.
+    Syntax { expected: Vec<String> },
+    /// A string literal isn't a valid regex.
+    Regex { regex: String, error: String },
+    /// There is no named criteria query with the referenced name.
+    UnknownName { name: String },
+    /// The named criteria query references itself, possibly indirectly.  The
+    /// names are the chain of references leading back to the first one.
+    Cycle { names: Vec<String> },
+    /// The referenced named criteria query is invalid.
+    Named {
+        name: String,
+        error: Box<CriteriaError>,
+    },
+}
+
+/// An invalid criteria query.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CriteriaError {
+    pub query: String,
+    /// The byte offset in `query` where the error occurred.
+    pub offset: usize,
+    pub kind: CriteriaErrorKind,
+}
+
+impl fmt::Display for CriteriaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            CriteriaErrorKind::Syntax { expected } => write!(
+                f,
+                "Invalid criteria query at offset {}: expected {}.",
+                self.offset,
+                expected.join(", ")
+            )?,
+            CriteriaErrorKind::Regex { regex, error } => write!(
+                f,
+                "Invalid regex {:?} at offset {} of criteria query: {}",
+                regex, self.offset, error
+            )?,
+            CriteriaErrorKind::UnknownName { name } => write!(
+                f,
+                "Unknown named criteria @{} at offset {} of criteria query.",
+                name, self.offset
+            )?,
+            CriteriaErrorKind::Cycle { names } => write!(
+                f,
+                "Cyclic named criteria at offset {} of criteria query: @{}",
+                self.offset,
+                names.join(" -> @")
+            )?,
+            CriteriaErrorKind::Named { name, .. } => write!(
+                f,
+                "Invalid named criteria @{} at offset {} of criteria query:",
+                name, self.offset
+            )?,
+        }
+        // Point at the error below the query.
+        let column = self.query[..self.offset].chars().count();
+        write!(f, "\n  {}\n  {}^", self.query, " ".repeat(column))?;
+        // The error in the named criteria query follows.
+        if let CriteriaErrorKind::Named { error, .. } = &self.kind {
+            write!(f, "\n{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// The errors found while parsing which don't stop the parser, e.g., invalid
+/// regexes, as pairs of the offset and the error.
+type ParseErrors = RefCell<Vec<(usize, CriteriaErrorKind)>>;
+
+/// The named criteria queries which can be referenced as `@name`.
+struct NamedCriteria<'a> {
+    queries: &'a HashMap<String, String>,
+    /// The names of the queries currently being expanded.
+    expanding: RefCell<Vec<String>>,
 }
 
-fn regex_from_str(s: &str) -> Regex {
+/// Compiles the regex `s` which starts at `offset`.  Errors are recorded in
+/// `errors` and a regex which never matches is returned instead.
+fn regex_from_str(s: &str, offset: usize, errors: &ParseErrors) -> Regex {
     match Regex::new(s) {
         Ok(rx) => rx,
         Err(err) => {
-            log::error!("Invalid regex {:?}: {}", s, err);
+            errors.borrow_mut().push((
+                offset,
+                CriteriaErrorKind::Regex {
+                    regex: s.to_owned(),
+                    error: err.to_string(),
+                },
+            ));
             Regex::new("^__I_WONT_MATCH_A_💩__$").unwrap()
         }
     }
 }
 
+/// Parses the named criteria query `name` referenced at `offset`.  Errors are
+/// recorded in `errors` and a criterion which never matches is returned
+/// instead.
+fn expand_named(
+    name: &str,
+    offset: usize,
+    named: &NamedCriteria,
+    errors: &ParseErrors,
+) -> Criterion {
+    let never = Criterion::Or(vec![]);
+    let cycle_start = named.expanding.borrow().iter().position(|n| n == name);
+    if let Some(idx) = cycle_start {
+        let mut names = named.expanding.borrow()[idx..].to_vec();
+        names.push(name.to_owned());
+        errors
+            .borrow_mut()
+            .push((offset, CriteriaErrorKind::Cycle { names }));
+        return never;
+    }
+    let query = match named.queries.get(name) {
+        Some(query) => query,
+        None => {
+            errors.borrow_mut().push((
+                offset,
+                CriteriaErrorKind::UnknownName {
+                    name: name.to_owned(),
+                },
+            ));
+            return never;
+        }
+    };
+    named.expanding.borrow_mut().push(name.to_owned());
+    let result = parse(query, named);
+    named.expanding.borrow_mut().pop();
+    match result {
+        Ok(crit) => crit,
+        // Cycles are reported at the outermost reference rather than wrapped
+        // for each named query on the cycle.
+        Err(err) if matches!(err.kind, CriteriaErrorKind::Cycle { .. }) => {
+            errors.borrow_mut().push((offset, err.kind));
+            never
+        }
+        Err(err) => {
+            errors.borrow_mut().push((
+                offset,
+                CriteriaErrorKind::Named {
+                    name: name.to_owned(),
+                    error: Box::new(err),
+                },
+            ));
+            never
+        }
+    }
+}
+
 peg::parser! {
-    grammar criteria_parser() for str {
+    grammar criteria_parser(
+        named: &NamedCriteria,
+        errors: &ParseErrors,
+    ) for str {
         rule space() -> () = [' ' | '\t']* {}
         rule i32_literal() -> i32 =
             n:$(['-']?['0'..='9']+) {? n.parse().or(Err("i32")) }
@@ -82,14 +321,29 @@ peg::parser! {
 
         rule regex_or_focused() -> RegexOrFocused =
             "__focused__" {RegexOrFocused::Focused}
-        / s:string_literal() {RegexOrFocused::Regex(regex_from_str(&s))}
+        / p:position!() s:string_literal()
+        {RegexOrFocused::Regex(regex_from_str(&s, p, errors))}
 
         rule i64_focused() -> I64OrFocused = "__focused__" {I64OrFocused::Focused}
         rule i64_or_focused() -> I64OrFocused =
             i64_focused() / n:i64_literal() {I64OrFocused::I64(n)}
 
-        rule tiling() -> Criterion = "tiling" {Criterion::Tiling}
-        rule floating() -> Criterion = "floating" {Criterion::Floating}
+        // Makes sure keywords aren't just a prefix of some longer word.
+        rule word_end() = !['a'..='z' | 'A'..='Z' | '0'..='9' | '_']
+
+        rule tiling() -> Criterion = "tiling" word_end() {Criterion::Tiling}
+        rule floating() -> Criterion =
+            "floating" word_end() {Criterion::Floating}
+        rule urgent() -> Criterion = "urgent" word_end() {Criterion::Urgent}
+        rule fullscreen() -> Criterion =
+            "fullscreen" word_end() {Criterion::Fullscreen}
+        rule sticky() -> Criterion = "sticky" word_end() {Criterion::Sticky}
+        rule visible() -> Criterion = "visible" word_end() {Criterion::Visible}
+        rule has_mark() -> Criterion =
+            "con_mark" word_end() {Criterion::HasMark}
+        rule never_focused() -> Criterion =
+            "never_focused" word_end() {Criterion::NeverFocused}
+        rule newest() -> Criterion = "newest" word_end() {Criterion::Newest}
         rule app_id() -> Criterion = "app_id" space() "=" space()
             rof:regex_or_focused() {Criterion::AppId(rof)}
         rule app_name() -> Criterion = "app_name" space() "=" space()
@@ -101,13 +355,54 @@ peg::parser! {
         rule title() -> Criterion = "title" space() "=" space()
             rof:regex_or_focused() {Criterion::Title(rof)}
         rule con_mark() -> Criterion = "con_mark" space() "=" space()
-            s:string_literal() {Criterion::ConMark(regex_from_str(&s))}
+            p:position!() s:string_literal()
+        {Criterion::ConMark(regex_from_str(&s, p, errors))}
         rule con_id() -> Criterion = "con_id" space() "=" space()
             i:i64_or_focused() {Criterion::ConId(i)}
+        rule id() -> Criterion = "id" space() "=" space()
+            n:i64_literal() {Criterion::Id(n)}
         rule pid() -> Criterion = "pid" space() "=" space()
             n:i32_literal() {Criterion::Pid(n)}
+        rule window_role() -> Criterion = "window_role" space() "=" space()
+            rof:regex_or_focused() {Criterion::WindowRole(rof)}
         rule workspace() -> Criterion = "workspace" space() "=" space()
             rof:regex_or_focused() {Criterion::Workspace(rof)}
+        rule output() -> Criterion = "output" space() "=" space()
+            rof:regex_or_focused() {Criterion::Output(rof)}
+        rule geometry_field() -> Geometry =
+            "x" {Geometry::X}
+        / "y" {Geometry::Y}
+        / "width" {Geometry::Width}
+        / "height" {Geometry::Height}
+        rule comparison() -> Comparison =
+            "<=" {Comparison::Le}
+        / ">=" {Comparison::Ge}
+        / "!=" {Comparison::Ne}
+        / "<" {Comparison::Lt}
+        / ">" {Comparison::Gt}
+        / "=" {Comparison::Eq}
+        rule geometry() -> Criterion =
+            g:geometry_field() space() c:comparison() space() n:i32_literal()
+        {Criterion::Geometry(g, c, n)}
+        rule lru() -> Criterion =
+            "lru" space() c:comparison() space() n:i32_literal()
+        {Criterion::Lru(c, n)}
+        rule duration() -> Duration =
+            n:$(['0'..='9']+) unit:$(['s' | 'm' | 'h' | 'd'])? {?
+                let n: u64 = n.parse().or(Err("u64"))?;
+                let secs = match unit {
+                    Some("m") => 60,
+                    Some("h") => 60 * 60,
+                    Some("d") => 24 * 60 * 60,
+                    _ => 1,
+                };
+                n.checked_mul(secs)
+                    .map(Duration::from_secs)
+                    .ok_or("duration")
+            }
+        rule focused_within() -> Criterion =
+            "focused_within" space() "=" space() d:duration()
+        {Criterion::FocusedWithin(d)}
         rule shell_type_or_focused() -> ShellTypeOrFocused =
             "\"xdg_shell\"" {ShellTypeOrFocused::ShellType(s::ShellType::XdgShell)}
         / "\"xwayland\"" {ShellTypeOrFocused::ShellType(s::ShellType::Xwayland)}
@@ -127,18 +422,27 @@ peg::parser! {
                 space() "]" space()
         { Criterion::Or(l) }
 
+        rule named() -> Criterion =
+            p:position!() "@"
+            n:$(['a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-']+) space()
+        { expand_named(n, p, named, errors) }
+
         rule not() -> Criterion =
             ("NOT" / "not" / "!") space() c:criterion() space()
         { Criterion::Not(Box::new(c)) }
 
         rule criterion() -> Criterion =
-            and() / or() / not()
-            / tiling() / floating()
+            and() / or() / not() / named()
+            / tiling() / floating() / urgent()
+            / fullscreen() / sticky() / visible()
+            / never_focused() / newest() / focused_within() / lru()
             / app_id() / class() / instance() / app_name() / title() / shell()
-            / workspace()
-            / con_mark()
-            / con_id()
+            / window_role()
+            / workspace() / output()
+            / con_mark() / has_mark()
+            / con_id() / id()
             / pid()
+            / geometry()
 
         pub rule parse() -> Criterion =
             space() c:criterion()
@@ -146,12 +450,78 @@ peg::parser! {
   }
 }
 
-pub fn parse_criteria(criteria: &str) -> Option<Criterion> {
-    match criteria_parser::parse(criteria) {
-        Ok(c) => Some(c),
+/// Parses the criteria query where `@name` references the named criteria
+/// queries of the `[criteria]` config table.
+pub fn parse_criteria(criteria: &str) -> Result<Criterion, CriteriaError> {
+    parse_criteria_with(criteria, &config::get_config().get_criteria())
+}
+
+/// Parses the criteria query where `@name` references the given named
+/// criteria queries, e.g., those of a config which is being loaded.
+pub fn parse_criteria_with(
+    criteria: &str,
+    queries: &HashMap<String, String>,
+) -> Result<Criterion, CriteriaError> {
+    parse(
+        criteria,
+        &NamedCriteria {
+            queries,
+            expanding: RefCell::new(vec![]),
+        },
+    )
+}
+
+/// Checks that all named criteria queries are valid and non-cyclic.
+pub fn validate_named_criteria(
+    queries: &HashMap<String, String>,
+) -> Result<(), CriteriaError> {
+    let mut names: Vec<&String> = queries.keys().collect();
+    names.sort();
+    for name in names {
+        let named = NamedCriteria {
+            queries,
+            expanding: RefCell::new(vec![name.clone()]),
+        };
+        parse(&queries[name], &named).map_err(|err| match err.kind {
+            CriteriaErrorKind::Cycle { .. } => err,
+            _ => CriteriaError {
+                query: format!("@{}", name),
+                offset: 0,
+                kind: CriteriaErrorKind::Named {
+                    name: name.clone(),
+                    error: Box::new(err),
+                },
+            },
+        })?;
+    }
+    Ok(())
+}
+
+fn parse(
+    criteria: &str,
+    named: &NamedCriteria,
+) -> Result<Criterion, CriteriaError> {
+    let parse_errors = ParseErrors::default();
+    let result = criteria_parser::parse(criteria, named, &parse_errors);
+    let error = |offset, kind| CriteriaError {
+        query: criteria.to_owned(),
+        offset,
+        kind,
+    };
+    match result {
+        Ok(crit) => match parse_errors.into_inner().into_iter().next() {
+            Some((offset, kind)) => Err(error(offset, kind)),
+            None => Ok(crit),
+        },
         Err(err) => {
-            log::error!("Could not parse criteria query {}: {}", criteria, err);
-            None
+            let mut expected: Vec<String> =
+                err.expected.tokens().map(String::from).collect();
+            expected.sort();
+            expected.dedup();
+            Err(error(
+                err.location.offset,
+                CriteriaErrorKind::Syntax { expected },
+            ))
         }
     }
 }
@@ -167,19 +537,30 @@ fn are_some_and_equal<T: std::cmp::PartialEq>(
     a.is_some() && b.is_some() && a.unwrap() == b.unwrap()
 }
 
-fn eval_criterion<'a>(
-    criterion: &'a Criterion,
-    w: &'a t::DisplayNode,
-    focused: Option<&'a t::DisplayNode>,
+/// The data about all windows needed for evaluating criteria.
+struct Context<'a> {
+    focused: Option<&'a t::DisplayNode<'a>>,
+    fdata: &'a FocusData,
+    /// The positions of the windows in the LRU order.
+    lru_ranks: HashMap<i64, usize>,
+    newest: Option<i64>,
+    now: Instant,
+}
+
+fn eval_criterion(
+    criterion: &Criterion,
+    w: &t::DisplayNode,
+    ctx: &Context,
 ) -> bool {
+    let focused = ctx.focused;
     match criterion {
         Criterion::And(criteria) => {
-            criteria.iter().all(|crit| eval_criterion(crit, w, focused))
+            criteria.iter().all(|crit| eval_criterion(crit, w, ctx))
         }
         Criterion::Or(criteria) => {
-            criteria.iter().any(|crit| eval_criterion(crit, w, focused))
+            criteria.iter().any(|crit| eval_criterion(crit, w, ctx))
         }
-        Criterion::Not(crit) => !eval_criterion(crit, w, focused),
+        Criterion::Not(crit) => !eval_criterion(crit, w, ctx),
         Criterion::AppId(val) => match val {
             RegexOrFocused::Regex(rx) => {
                 is_some_and_rx_matches(w.node.app_id.as_ref(), rx)
@@ -285,6 +666,69 @@ fn eval_criterion<'a>(
         },
         Criterion::Floating => w.node.is_floating(),
         Criterion::Tiling => !w.node.is_floating(),
+        Criterion::Urgent => w.node.urgent,
+        Criterion::HasMark => !w.node.marks.is_empty(),
+        Criterion::Id(id) => w.node.window == Some(*id),
+        Criterion::WindowRole(val) => {
+            let role = |n: &s::Node| {
+                n.window_properties
+                    .as_ref()
+                    .and_then(|p| p.window_role.as_ref())
+                    .cloned()
+            };
+            match val {
+                RegexOrFocused::Regex(rx) => {
+                    is_some_and_rx_matches(role(w.node).as_ref(), rx)
+                }
+                RegexOrFocused::Focused => match focused {
+                    Some(win) => {
+                        are_some_and_equal(role(w.node), role(win.node))
+                    }
+                    None => false,
+                },
+            }
+        }
+        Criterion::Output(val) => {
+            let output = |n: &t::DisplayNode| {
+                n.tree
+                    .get_parent_node_of_type(n.node.id, ipc::Type::Output)
+                    .map(|o| o.get_name().to_owned())
+            };
+            match val {
+                RegexOrFocused::Regex(rx) => {
+                    is_some_and_rx_matches(output(w).as_ref(), rx)
+                }
+                RegexOrFocused::Focused => match focused {
+                    Some(win) => are_some_and_equal(output(w), output(win)),
+                    None => false,
+                },
+            }
+        }
+        Criterion::Fullscreen => w.node.fullscreen_mode.unwrap_or(0) > 0,
+        Criterion::Sticky => w.node.sticky,
+        Criterion::Visible => w.node.visible == Some(true),
+        Criterion::Geometry(field, cmp, n) => {
+            let rect = &w.node.rect;
+            let val = match field {
+                Geometry::X => rect.x,
+                Geometry::Y => rect.y,
+                Geometry::Width => rect.width,
+                Geometry::Height => rect.height,
+            };
+            cmp.compare(val, *n)
+        }
+        Criterion::Lru(cmp, n) => match ctx.lru_ranks.get(&w.node.id) {
+            Some(rank) => cmp.compare(*rank as i32, *n),
+            None => false,
+        },
+        Criterion::FocusedWithin(duration) => {
+            match ctx.fdata.last_focus_time(w.node.id) {
+                Some(time) => ctx.now.duration_since(time) <= *duration,
+                None => false,
+            }
+        }
+        Criterion::NeverFocused => ctx.fdata.last_focus_tick(w.node.id) == 0,
+        Criterion::Newest => ctx.newest == Some(w.node.id),
         Criterion::Title(val) => match val {
             RegexOrFocused::Regex(rx) => {
                 is_some_and_rx_matches(w.node.name.as_ref(), rx)
@@ -303,20 +747,37 @@ fn eval_criterion<'a>(
 pub fn criterion_to_predicate<'a>(
     criterion: &'a Criterion,
     all_windows: &'a [t::DisplayNode],
+    fdata: &'a FocusData,
 ) -> impl Fn(&t::DisplayNode) -> bool + 'a {
-    let focused = all_windows.iter().find(|x| x.node.focused);
+    let mut lru: Vec<i64> = all_windows.iter().map(|w| w.node.id).collect();
+    lru.sort_by_key(|id| {
+        std::cmp::Reverse(fdata.lru_key(*id, &LruScope::Global))
+    });
+    // Sway assigns increasing ids to new containers.
+    let newest = lru.iter().copied().max();
+    let ctx = Context {
+        focused: all_windows.iter().find(|x| x.node.focused),
+        fdata,
+        lru_ranks: lru
+            .into_iter()
+            .enumerate()
+            .map(|(rank, id)| (id, rank))
+            .collect(),
+        newest,
+        now: Instant::now(),
+    };
 
-    move |w: &t::DisplayNode| eval_criterion(criterion, w, focused)
+    move |w: &t::DisplayNode| eval_criterion(criterion, w, &ctx)
 }
 
 #[test]
 fn test_criteria_parser() {
-    match criteria_parser::parse(
-        "[tiling floating app_id=__focused__ app_id=\"foot\" class=\"emacs\" instance = \"the.instance\" title=\"something with :;&$\" con_mark=\"^.*foo$\"\tapp_name=\"Hugo\" con_id = __focused__ con_id=17 pid=23223 shell=\"xdg_shell\" shell=\"xwayland\" shell=__focused__ workspace=\"test\" workspace=__focused__]",
+    match parse_criteria(
+        "[tiling floating app_id=__focused__ app_id=\"foot\" class=\"emacs\" instance = \"the.instance\" title=\"something with :;&$\" con_mark=\"^.*foo$\"\tapp_name=\"Hugo\" con_id = __focused__ con_id=17 pid=23223 shell=\"xdg_shell\" shell=\"xwayland\" shell=__focused__ workspace=\"test\" workspace=__focused__ urgent con_mark fullscreen sticky visible id=4194307 window_role=\"pop-up\" window_role=__focused__ output=\"eDP-1\" output=__focused__ width>800 height <= 600 x=0 y!=-10 lru<5 focused_within=10m focused_within = 30 never_focused newest]",
     ) {
         Ok(c) => assert!(matches!(c, Criterion::And(..))),
         Err(err) => {
-            assert!(false, "Could not parse: {}", err);
+            panic!("Could not parse: {}", err);
         },
     }
 }
@@ -324,7 +785,7 @@ fn test_criteria_parser() {
 #[test]
 fn test_criteria_parser_and() {
     for c in ["[]", "[and]", "[AND]", "[&&]"] {
-        match criteria_parser::parse(c) {
+        match parse_criteria(c) {
             Ok(c) => {
                 println!("Criteria: {:?}", c);
                 assert!(match c {
@@ -333,7 +794,7 @@ fn test_criteria_parser_and() {
                 })
             }
             Err(err) => {
-                assert!(false, "Could not parse: {}", err);
+                panic!("Could not parse: {}", err);
             }
         }
     }
@@ -342,7 +803,7 @@ fn test_criteria_parser_and() {
 #[test]
 fn test_criteria_parser_or() {
     for c in ["[or]", "[OR]", "[||]"] {
-        match criteria_parser::parse(c) {
+        match parse_criteria(c) {
             Ok(c) => {
                 println!("Criteria: {:?}", c);
                 assert!(match c {
@@ -351,7 +812,7 @@ fn test_criteria_parser_or() {
                 })
             }
             Err(err) => {
-                assert!(false, "Could not parse: {}", err);
+                panic!("Could not parse: {}", err);
             }
         }
     }
@@ -360,7 +821,7 @@ fn test_criteria_parser_or() {
 #[test]
 fn test_criteria_parser_not() {
     for c in ["not tiling", "NOT tiling", "!tiling", "! tiling"] {
-        match criteria_parser::parse(c) {
+        match parse_criteria(c) {
             Ok(c) => {
                 println!("Criteria: {:?}", c);
                 assert!(match c {
@@ -370,8 +831,132 @@ fn test_criteria_parser_not() {
                 })
             }
             Err(err) => {
-                assert!(false, "Could not parse: {}", err);
+                panic!("Could not parse: {}", err);
             }
         }
     }
 }
+
+#[test]
+fn test_criteria_parser_keywords() {
+    // Keywords must not be prefixes of longer words.
+    assert!(parse_criteria("[floatingx]").is_err());
+    assert!(matches!(parse_criteria("con_mark"), Ok(Criterion::HasMark)));
+    assert!(matches!(
+        parse_criteria("width >= 800"),
+        Ok(Criterion::Geometry(Geometry::Width, Comparison::Ge, 800))
+    ));
+    assert!(matches!(
+        parse_criteria("focused_within=2h"),
+        Ok(Criterion::FocusedWithin(d)) if d == Duration::from_secs(7200)
+    ));
+    // Overflowing durations are rejected.
+    assert!(parse_criteria("focused_within=99999999999999999d").is_err());
+}
+
+#[test]
+fn test_criteria_errors() {
+    let err = parse_criteria("[app_id=\"foo\" bar]").unwrap_err();
+    assert_eq!(err.offset, 14);
+    match &err.kind {
+        CriteriaErrorKind::Syntax { expected } => {
+            assert!(expected.contains(&"\"]\"".to_owned()))
+        }
+        kind => panic!("Unexpected error {:?}", kind),
+    }
+    assert!(err
+        .to_string()
+        .ends_with("\n  [app_id=\"foo\" bar]\n                ^"));
+
+    let err = parse_criteria("[tiling title=\"(foo\"]").unwrap_err();
+    assert_eq!(err.offset, 14);
+    assert!(matches!(
+        err.kind,
+        CriteriaErrorKind::Regex { ref regex, .. } if regex == "(foo"
+    ));
+}
+
+#[test]
+fn test_named_criteria() {
+    let queries: HashMap<String, String> = [
+        (
+            "browsers",
+            "[or app_name=\"firefox\" app_name=\"chromium\"]",
+        ),
+        ("tiled-browsers", "[@browsers tiling]"),
+        ("broken", "[title=\"(\"]"),
+        ("a", "[@b]"),
+        ("b", "[floating @a]"),
+    ]
+    .into_iter()
+    .map(|(n, q)| (n.to_owned(), q.to_owned()))
+    .collect();
+    let parse_named = |query: &str| {
+        parse(
+            query,
+            &NamedCriteria {
+                queries: &queries,
+                expanding: RefCell::new(vec![]),
+            },
+        )
+    };
+
+    match parse_named("[@tiled-browsers urgent]") {
+        Ok(Criterion::And(v)) => assert!(matches!(
+            v.as_slice(),
+            [Criterion::And(inner), Criterion::Urgent]
+                if matches!(
+                    inner.as_slice(),
+                    [Criterion::Or(or), Criterion::Tiling] if or.len() == 2
+                )
+        )),
+        other => panic!("Unexpected parse result {:?}", other),
+    }
+
+    let err = parse_named("[tiling @nope]").unwrap_err();
+    assert_eq!(err.offset, 8);
+    assert_eq!(
+        err.kind,
+        CriteriaErrorKind::UnknownName {
+            name: "nope".to_owned()
+        }
+    );
+
+    let err = parse_named("[urgent @a]").unwrap_err();
+    assert_eq!(err.offset, 8);
+    assert_eq!(
+        err.kind,
+        CriteriaErrorKind::Cycle {
+            names: vec!["a".to_owned(), "b".to_owned(), "a".to_owned()]
+        }
+    );
+
+    let err = parse_named("@broken").unwrap_err();
+    assert!(matches!(
+        err.kind,
+        CriteriaErrorKind::Named { ref name, ref error }
+            if name == "broken" && error.offset == 7
+    ));
+    assert!(err.to_string().contains("Invalid regex \"(\""));
+
+    let err = validate_named_criteria(&queries).unwrap_err();
+    assert!(matches!(err.kind, CriteriaErrorKind::Cycle { .. }));
+}
+
+#[test]
+fn test_serialize_criteria() {
+    let crit = parse_criteria(
+        "[or floating workspace=__focused__ not width >= 800 \
+         focused_within=2m shell=\"xwayland\"]",
+    )
+    .unwrap();
+    assert_eq!(
+        serde_json::to_string(&crit).unwrap(),
+        r#"{"type":"or","args":[{"type":"floating"},"#.to_owned()
+            + r#"{"type":"workspace","args":"focused"},"#
+            + r#"{"type":"not","args":{"type":"geometry","#
+            + r#""args":["width","ge",800]}},"#
+            + r#"{"type":"focused_within","args":120},"#
+            + r#"{"type":"shell","args":{"shell_type":"xwayland"}}]}"#
+    );
+}
@@ -16,133 +16,413 @@
 //! Functions and data structures of the swayrd daemon.
 
 use crate::cmds;
-use crate::config::{self, Config};
+use crate::config;
+use crate::dynamic_layout;
+use crate::events::{self, Event};
 use crate::focus::FocusData;
 use crate::focus::FocusEvent;
 use crate::focus::FocusMessage;
+use crate::focus::{FocusHistory, FocusScope, ScopedFocusTicks};
+use crate::launcher;
 use crate::layout;
+use crate::persist;
+use crate::protocol;
+use crate::rules;
+use crate::session;
+use crate::shared::ipc;
+use crate::shared::ipc::NodeMethods;
+use crate::slots;
 use crate::util;
-use std::collections::HashMap;
-use std::io::Read;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use signal_hook::consts::SIGHUP;
+use signal_hook::iterator::Signals;
+use std::collections::{HashMap, HashSet};
 use std::os::unix::net::{UnixListener, UnixStream};
 use std::sync::RwLock;
 use std::sync::{mpsc, Condvar};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use swayipc as s;
 
+static START_TIME: Lazy<Instant> = Lazy::new(Instant::now);
+
 pub fn run_daemon() {
+    Lazy::force(&START_TIME);
     let (focus_tx, focus_rx) = mpsc::channel();
     let fdata = FocusData {
         focus_tick_by_id: Arc::new(RwLock::new(HashMap::new())),
+        focus_time_by_id: Arc::new(RwLock::new(HashMap::new())),
+        scoped_focus_ticks: Arc::new(RwLock::new(ScopedFocusTicks::default())),
+        focus_history: Arc::new(RwLock::new(FocusHistory::default())),
         focus_chan: focus_tx,
     };
 
-    let config = config::load_config();
-    let lockin_delay = config.get_focus_lockin_delay();
-    let auto_nop_delay = &config.get_misc_auto_nop_delay();
-    let seq_inhibit = config.get_misc_seq_inhibit();
+    if config::get_config().get_focus_persist_history() {
+        persist::restore_focus_history(&fdata);
+    }
+    if config::get_config().get_slots_persist() {
+        slots::restore_slots();
+    }
+
+    {
+        let fdata = fdata.clone();
+        thread::spawn(move || {
+            save_focus_history_periodically(fdata);
+        });
+    }
 
     {
         let fdata = fdata.clone();
         thread::spawn(move || {
-            monitor_sway_events(fdata, &config);
+            monitor_sway_events(fdata);
+            // Sway has shut down, so there's nothing left to do.
+            log::debug!("Swayr daemon shutting down.");
+            let _ = std::fs::remove_file(util::get_swayr_socket_path());
+            std::process::exit(0);
         });
     }
 
     {
         let fdata = fdata.clone();
         thread::spawn(move || {
-            focus_lock_in_handler(focus_rx, fdata, lockin_delay, seq_inhibit);
+            focus_lock_in_handler(focus_rx, fdata);
         });
     }
 
-    serve_client_requests(fdata, auto_nop_delay);
+    thread::spawn(reload_config_on_sighup);
+    thread::spawn(reload_config_on_change);
+
+    serve_client_requests(fdata);
+}
+
+fn reload_config(reason: &str) {
+    log::debug!("Reloading config because {}.", reason);
+    match config::reload_config() {
+        Ok(()) => events::broadcast(&Event::ConfigReloaded),
+        Err(err) => log::error!("Could not reload config: {}", err),
+    }
+}
+
+fn reload_config_on_sighup() {
+    match Signals::new([SIGHUP]) {
+        Ok(mut signals) => {
+            for _ in signals.forever() {
+                reload_config("of SIGHUP");
+            }
+        }
+        Err(err) => log::error!("Could not register SIGHUP handler: {}", err),
+    }
+}
+
+/// Polls the modification time of the config file and reloads it when it has
+/// been changed.
+fn reload_config_on_change() {
+    let mut last_mtime = config::config_file_mtime();
+    loop {
+        thread::sleep(Duration::from_secs(2));
+        let mtime = config::config_file_mtime();
+        if mtime.is_some() && mtime != last_mtime {
+            reload_config("the config file has changed");
+        }
+        last_mtime = mtime;
+    }
 }
 
 fn connect_and_subscribe() -> s::Fallible<s::EventStream> {
-    s::Connection::new()?.subscribe(&[
+    s::Connection::new()?.subscribe([
         s::EventType::Window,
         s::EventType::Workspace,
         s::EventType::Shutdown,
     ])
 }
 
-pub fn monitor_sway_events(fdata: FocusData, config: &Config) {
-    let mut focus_counter = 0;
-    let mut resets = 0;
-    let max_resets = 10;
+fn save_focus_history_periodically(fdata: FocusData) {
+    let mut last_ticks = HashMap::new();
+    loop {
+        let config = config::get_config();
+        thread::sleep(config.get_focus_persist_interval());
+        if !config::get_config().get_focus_persist_history() {
+            continue;
+        }
+        let ticks = fdata.focus_tick_by_id.read().unwrap().clone();
+        if ticks != last_ticks {
+            persist::save_focus_history(&fdata);
+            last_ticks = ticks;
+        }
+    }
+}
+
+/// The state of swayrd's connection to sway.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum Health {
+    /// The initial connection hasn't been established yet.
+    Connecting,
+    /// Subscribed to sway's events, so the LRU data is up to date.
+    Connected,
+    /// The connection has been lost and swayrd tries to re-establish it.
+    /// Until then, the LRU data might be stale.
+    Reconnecting { attempt: u32, last_error: String },
+    /// Sway has shut down.
+    SwayShutDown,
+}
+
+static HEALTH: Lazy<Mutex<Health>> =
+    Lazy::new(|| Mutex::new(Health::Connecting));
+
+pub fn get_health() -> Health {
+    HEALTH.lock().expect("Could not lock mutex").clone()
+}
 
-    'reset: loop {
-        if resets >= max_resets {
-            break;
+fn set_health(health: Health) {
+    let mut cur = HEALTH.lock().expect("Could not lock mutex");
+    if *cur != health {
+        log::info!("Daemon health: {:?}", health);
+        *cur = health.clone();
+        events::broadcast(&Event::HealthChanged { health });
+    }
+}
+
+/// The time since swayrd has been started.
+pub fn uptime() -> Duration {
+    START_TIME.elapsed()
+}
+
+/// A focus event which hasn't been locked in yet, either because the lock-in
+/// delay hasn't expired or because a sequence of cycling commands is active.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingLockIn {
+    pub id: i64,
+    pub tick: u64,
+}
+
+/// The last command (other than a query) received from a client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LastCommand {
+    pub cmd: cmds::SwayrCommand,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// Internal state of swayrd reported by the `status` command.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaemonState {
+    pub pending_lock_in: Option<PendingLockIn>,
+    pub sequence_inhibited: bool,
+    pub last_command: Option<LastCommand>,
+}
+
+static DAEMON_STATE: Lazy<Mutex<DaemonState>> =
+    Lazy::new(|| Mutex::new(DaemonState::default()));
+
+pub fn get_daemon_state() -> DaemonState {
+    DAEMON_STATE.lock().expect("Could not lock mutex").clone()
+}
+
+fn update_daemon_state<F: FnOnce(&mut DaemonState)>(f: F) {
+    f(&mut DAEMON_STATE.lock().expect("Could not lock mutex"))
+}
+
+fn set_pending_lock_in(fev: Option<&FocusEvent>) {
+    update_daemon_state(|state| {
+        state.pending_lock_in = fev.map(|fev| PendingLockIn {
+            id: fev.node_id,
+            tick: fev.ev_focus_ctr,
+        })
+    });
+}
+
+const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(250);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// The delay before the given reconnection attempt which doubles with each
+/// attempt.
+fn reconnect_delay(attempt: u32) -> Duration {
+    MIN_RECONNECT_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_RECONNECT_DELAY)
+}
+
+/// The focused workspace and the outputs of the workspaces as far as known
+/// from sway's events so that the scope of a focus event can be determined
+/// without querying the tree.  When the focus moves to a window on another
+/// workspace, sway sends the workspace focus event before the window one.
+#[derive(Default)]
+struct WorkspaceTracker {
+    focused: Option<i64>,
+    outputs: HashMap<i64, String>,
+}
+
+impl WorkspaceTracker {
+    fn reset(&mut self, root: &s::Node) {
+        self.focused = None;
+        self.outputs.clear();
+        for output in root.nodes_of_type(ipc::Type::Output) {
+            if output.is_scratchpad() {
+                continue;
+            }
+            for ws in output.nodes_of_type(ipc::Type::Workspace) {
+                if ws.iter().any(|n| n.focused) {
+                    self.focused = Some(ws.id);
+                }
+                self.outputs.insert(ws.id, output.get_name().to_owned());
+            }
+        }
+    }
+
+    /// The output of the given workspace.  Unknown workspaces, e.g., ones
+    /// which have just been created or moved, are looked up.
+    fn output(&mut self, ws_id: i64) -> Option<String> {
+        if !self.outputs.contains_key(&ws_id) {
+            match ipc::get_workspaces() {
+                Ok(wss) => {
+                    self.outputs =
+                        wss.into_iter().map(|ws| (ws.id, ws.output)).collect()
+                }
+                Err(err) => log::warn!("Could not get workspaces: {}", err),
+            }
+        }
+        self.outputs.get(&ws_id).cloned()
+    }
+
+    fn window_scope(&mut self) -> Option<FocusScope> {
+        let ws_id = self.focused?;
+        Some(FocusScope {
+            workspace: Some(ws_id),
+            output: self.output(ws_id)?,
+        })
+    }
+
+    fn workspace_scope(&mut self, ws_id: i64) -> Option<FocusScope> {
+        Some(FocusScope {
+            workspace: None,
+            output: self.output(ws_id)?,
+        })
+    }
+}
+
+/// Brings the focus data in line with the tree after (re-)connecting, i.e.,
+/// windows and workspaces which have vanished in the meantime are removed and
+/// new ones are added.
+fn resync_focus_data(
+    fdata: &FocusData,
+    workspaces: &mut WorkspaceTracker,
+) -> s::Fallible<()> {
+    let root = ipc::get_tree(true)?;
+    workspaces.reset(&root);
+    let live_ids: HashSet<i64> = root
+        .iter()
+        .filter(|n| {
+            matches!(n.get_type(), ipc::Type::Window | ipc::Type::Workspace)
+        })
+        .map(|n| n.id)
+        .collect();
+    let (removed, added) = fdata.resync(&live_ids);
+    slots::retain_windows(&live_ids);
+    log::debug!(
+        "Resynced focus data: removed {} and added {} ids.",
+        removed,
+        added
+    );
+    Ok(())
+}
+
+pub fn monitor_sway_events(fdata: FocusData) {
+    // Continue after the ticks of a restored focus history.
+    let mut focus_counter = fdata.max_focus_tick();
+    let mut attempt: u32 = 0;
+    let mut workspaces = WorkspaceTracker::default();
+
+    loop {
+        if attempt > 0 {
+            let delay = reconnect_delay(attempt);
+            log::debug!("Reconnecting in {:?}.", delay);
+            thread::sleep(delay);
         }
-        resets += 1;
 
         log::debug!("Connecting to sway for subscribing to events...");
-        match connect_and_subscribe() {
+        // Subscribe before resyncing so that no event gets lost in between.
+        let iter = match connect_and_subscribe().and_then(|iter| {
+            resync_focus_data(&fdata, &mut workspaces)?;
+            Ok(iter)
+        }) {
+            Ok(iter) => iter,
             Err(err) => {
                 log::warn!("Could not connect and subscribe: {}", err);
-                std::thread::sleep(std::time::Duration::from_secs(3));
+                attempt += 1;
+                set_health(Health::Reconnecting {
+                    attempt,
+                    last_error: err.to_string(),
+                });
+                continue;
             }
-            Ok(iter) => {
-                for ev_result in iter {
-                    let show_extra_props_state;
-                    resets = 0;
-                    match ev_result {
-                        Ok(ev) => match ev {
-                            s::Event::Window(win_ev) => {
-                                focus_counter += 1;
-                                show_extra_props_state = handle_window_event(
-                                    win_ev,
-                                    &fdata,
-                                    config,
-                                    focus_counter,
-                                );
-                            }
-                            s::Event::Workspace(ws_ev) => {
-                                focus_counter += 1;
-                                show_extra_props_state = handle_workspace_event(
-                                    ws_ev,
-                                    &fdata,
-                                    focus_counter,
-                                );
-                            }
-                            s::Event::Shutdown(sd_ev) => {
-                                log::debug!(
-                                    "Sway shuts down with reason '{:?}'.",
-                                    sd_ev.change
-                                );
-                                break 'reset;
-                            }
-                            _ => show_extra_props_state = false,
-                        },
-                        Err(e) => {
-                            log::warn!("Error while receiving events: {}", e);
-                            std::thread::sleep(std::time::Duration::from_secs(
-                                3,
-                            ));
-                            show_extra_props_state = false;
-                            log::warn!("Resetting!");
-                        }
+        };
+        attempt = 0;
+        set_health(Health::Connected);
+
+        for ev_result in iter {
+            let show_extra_props_state;
+            match ev_result {
+                Ok(ev) => match ev {
+                    s::Event::Window(win_ev) => {
+                        focus_counter += 1;
+                        show_extra_props_state = handle_window_event(
+                            win_ev,
+                            &fdata,
+                            &mut workspaces,
+                            &config::get_config(),
+                            focus_counter,
+                        );
+                    }
+                    s::Event::Workspace(ws_ev) => {
+                        focus_counter += 1;
+                        show_extra_props_state = handle_workspace_event(
+                            ws_ev,
+                            &fdata,
+                            &mut workspaces,
+                            &config::get_config(),
+                            focus_counter,
+                        );
                     }
-                    if show_extra_props_state {
-                        log::trace!(
-                            "New extra_props state:\n{:#?}",
-                            *fdata.focus_tick_by_id.read().unwrap()
+                    s::Event::Shutdown(sd_ev) => {
+                        log::debug!(
+                            "Sway shuts down with reason '{:?}'.",
+                            sd_ev.change
                         );
+                        if config::get_config().get_focus_persist_history() {
+                            persist::save_focus_history(&fdata);
+                        }
+                        set_health(Health::SwayShutDown);
+                        return;
                     }
+                    _ => show_extra_props_state = false,
+                },
+                Err(e) => {
+                    log::warn!("Error while receiving events: {}", e);
+                    attempt += 1;
+                    set_health(Health::Reconnecting {
+                        attempt,
+                        last_error: e.to_string(),
+                    });
+                    break;
                 }
             }
+            if show_extra_props_state {
+                log::trace!(
+                    "New extra_props state:\n{:#?}",
+                    *fdata.focus_tick_by_id.read().unwrap()
+                );
+            }
         }
     }
-    log::debug!("Swayr daemon shutting down.")
 }
 
 fn handle_window_event(
     ev: Box<s::WindowEvent>,
     fdata: &FocusData,
+    workspaces: &mut WorkspaceTracker,
     config: &config::Config,
     focus_val: u64,
 ) -> bool {
@@ -155,24 +435,45 @@ fn handle_window_event(
             fdata.send(FocusMessage::FocusEvent(FocusEvent {
                 node_id: container.id,
                 ev_focus_ctr: focus_val,
+                scope: workspaces.window_scope(),
             }));
             log::debug!("Handled window event type {:?}", change);
             true
         }
         s::WindowChange::New => {
             layout::maybe_auto_tile(config);
+            dynamic_layout::maybe_arrange(config);
             fdata.ensure_id(container.id);
+            session::place_new_window(&container);
+            launcher::place_new_window(container.id, fdata);
+            if rules::has_rules(config, rules::Trigger::New) {
+                spawn_apply_rules(container.id, rules::Trigger::New, fdata);
+            }
             log::debug!("Handled window event type {:?}", change);
             true
         }
+        s::WindowChange::Title => {
+            if rules::has_rules(config, rules::Trigger::TitleChange) {
+                spawn_apply_rules(
+                    container.id,
+                    rules::Trigger::TitleChange,
+                    fdata,
+                );
+            }
+            log::debug!("Handled window event type {:?}", change);
+            false
+        }
         s::WindowChange::Close => {
             fdata.remove_focus_data(container.id);
+            slots::remove_window(container.id);
             layout::maybe_auto_tile(config);
+            dynamic_layout::maybe_arrange(config);
             log::debug!("Handled window event type {:?}", change);
             true
         }
         s::WindowChange::Move | s::WindowChange::Floating => {
             layout::maybe_auto_tile(config);
+            dynamic_layout::maybe_arrange(config);
             log::debug!("Handled window event type {:?}", change);
             false // We don't affect the extra_props state here.
         }
@@ -183,9 +484,18 @@ fn handle_window_event(
     }
 }
 
+/// Applies the window rules in a separate thread so that actions showing a
+/// menu don't block the handling of further events.
+fn spawn_apply_rules(id: i64, trigger: rules::Trigger, fdata: &FocusData) {
+    let fdata = fdata.clone();
+    thread::spawn(move || rules::apply_rules(id, trigger, &fdata));
+}
+
 fn handle_workspace_event(
     ev: Box<s::WorkspaceEvent>,
     fdata: &FocusData,
+    workspaces: &mut WorkspaceTracker,
+    config: &config::Config,
     focus_val: u64,
 ) -> bool {
     let s::WorkspaceEvent {
@@ -199,28 +509,39 @@ fn handle_workspace_event(
             let id = current
                 .expect("No current in Init or Focus workspace event")
                 .id;
+            // Workspaces are only arranged while they are visible.
+            if change == s::WorkspaceChange::Focus {
+                workspaces.focused = Some(id);
+                dynamic_layout::maybe_arrange(config);
+            }
             fdata.send(FocusMessage::FocusEvent(FocusEvent {
                 node_id: id,
                 ev_focus_ctr: focus_val,
+                scope: workspaces.workspace_scope(id),
             }));
             log::debug!("Handled workspace event type {:?}", change);
             true
         }
         s::WorkspaceChange::Empty => {
-            fdata.remove_focus_data(
-                current.expect("No current in Empty workspace event").id,
-            );
+            let id = current.expect("No current in Empty workspace event").id;
+            workspaces.outputs.remove(&id);
+            fdata.remove_focus_data(id);
             log::debug!("Handled workspace event type {:?}", change);
             true
         }
+        s::WorkspaceChange::Move => {
+            // The new output is looked up when it's needed.
+            if let Some(ws) = current {
+                workspaces.outputs.remove(&ws.id);
+            }
+            log::debug!("Handled workspace event type {:?}", change);
+            false
+        }
         _ => false,
     }
 }
 
-pub fn serve_client_requests(
-    fdata: FocusData,
-    auto_nop_delay: &Option<Duration>,
-) {
+pub fn serve_client_requests(fdata: FocusData) {
     match std::fs::remove_file(util::get_swayr_socket_path()) {
         Ok(()) => log::debug!("Deleted stale socket from previous run."),
         Err(e) => log::error!("Could not delete socket:\n{:?}", e),
@@ -229,29 +550,43 @@ pub fn serve_client_requests(
     let pair = Arc::new((Mutex::new(()), Condvar::new()));
     let pair2 = pair.clone();
 
-    if let Some(delay) = auto_nop_delay {
-        let delay = *delay;
+    {
         let fdata = fdata.clone();
         thread::spawn(move || {
             let mut inhibit = false;
             loop {
                 let (lock, cvar) = &*pair2;
                 let guard = lock.lock().unwrap();
-                let result = cvar.wait_timeout(guard, delay);
-
-                if let Ok(r) = result {
-                    if r.1.timed_out() {
-                        if !inhibit {
-                            log::debug!("Executing auto-nop.");
-                            cmds::exec_swayr_cmd(cmds::ExecSwayrCmdArgs {
-                                cmd: &cmds::SwayrCommand::Nop,
-                                focus_data: &fdata,
-                            });
-                            inhibit = true;
+                // The delay is read anew after each command so that config
+                // reloads take effect.  Without delay, just wait for the next
+                // command.
+                let timed_out =
+                    match config::get_config().get_misc_auto_nop_delay() {
+                        Some(delay) => cvar
+                            .wait_timeout(guard, delay)
+                            .map(|r| r.1.timed_out())
+                            .unwrap_or(false),
+                        None => {
+                            drop(cvar.wait(guard));
+                            false
                         }
-                    } else {
-                        inhibit = false;
+                    };
+
+                if timed_out {
+                    // Executing the auto-nop would cancel an open menu, so
+                    // retry after the next delay.
+                    if util::is_menu_open() {
+                        log::debug!("Skipping auto-nop while a menu is open.");
+                    } else if !inhibit {
+                        log::debug!("Executing auto-nop.");
+                        let _ = cmds::exec_swayr_cmd(cmds::ExecSwayrCmdArgs {
+                            cmd: &cmds::SwayrCommand::Nop,
+                            focus_data: &fdata,
+                        });
+                        inhibit = true;
                     }
+                } else {
+                    inhibit = false;
                 }
             }
         });
@@ -262,12 +597,20 @@ pub fn serve_client_requests(
             for stream in listener.incoming() {
                 match stream {
                     Ok(stream) => {
-                        handle_client_request(stream, &fdata);
-                        if auto_nop_delay.is_some() {
-                            let (lock, cvar) = &*pair;
-                            let _guard = lock.lock().unwrap();
-                            cvar.notify_one();
-                        }
+                        // Each request is handled in its own thread so that,
+                        // e.g., a command showing a menu doesn't block queries
+                        // or window cycling commands.
+                        let fdata = fdata.clone();
+                        let pair = pair.clone();
+                        thread::spawn(move || {
+                            let was_query =
+                                handle_client_request(stream, &fdata);
+                            if !was_query {
+                                let (lock, cvar) = &*pair;
+                                let _guard = lock.lock().unwrap();
+                                cvar.notify_one();
+                            }
+                        });
                     }
                     Err(err) => {
                         log::error!("Error handling client request: {}", err);
@@ -282,23 +625,73 @@ pub fn serve_client_requests(
     }
 }
 
-fn handle_client_request(mut stream: UnixStream, fdata: &FocusData) {
-    let mut cmd_str = String::new();
-    if stream.read_to_string(&mut cmd_str).is_ok() {
-        if let Ok(cmd) = serde_json::from_str::<cmds::SwayrCommand>(&cmd_str) {
-            cmds::exec_swayr_cmd(cmds::ExecSwayrCmdArgs {
-                cmd: &cmd,
-                focus_data: fdata,
-            });
-        } else {
-            log::error!(
-                "Could not serialize following string to SwayrCommand.\n{}",
-                cmd_str
-            );
+/// Handles a single client request and returns true if the request was a query
+/// or subscription which doesn't influence command sequences.
+fn handle_client_request(mut stream: UnixStream, fdata: &FocusData) -> bool {
+    let mut was_query = false;
+    let response = match protocol::read_frame(&mut stream) {
+        Ok(frame) => match protocol::decode_request(&frame) {
+            Ok(req) if req.cmd == cmds::SwayrCommand::Subscribe => {
+                let response = protocol::Response::from_result(Ok(
+                    cmds::CmdOutput::default(),
+                ));
+                match protocol::write_frame(&mut stream, &response) {
+                    Ok(()) => events::add_subscriber(stream),
+                    Err(err) => {
+                        log::error!(
+                            "Could not send response to client: {}",
+                            err
+                        )
+                    }
+                }
+                return true;
+            }
+            Ok(req) => {
+                was_query = req.cmd.is_query();
+                let result = cmds::exec_swayr_cmd(cmds::ExecSwayrCmdArgs {
+                    cmd: &req.cmd,
+                    focus_data: fdata,
+                });
+                if let Err(err) = &result {
+                    log::error!("Error executing {:?}: {}", req.cmd, err);
+                }
+                if !was_query {
+                    update_daemon_state(|state| {
+                        state.last_command = Some(LastCommand {
+                            cmd: req.cmd.clone(),
+                            success: result.is_ok(),
+                            error: result
+                                .as_ref()
+                                .err()
+                                .map(|err| err.message.clone()),
+                        })
+                    });
+                    events::broadcast(&Event::CommandExecuted {
+                        cmd: req.cmd,
+                        success: result.is_ok(),
+                        error: result
+                            .as_ref()
+                            .err()
+                            .map(|err| err.message.clone()),
+                    });
+                }
+                protocol::Response::from_result(result)
+            }
+            Err(err) => {
+                log::error!("{}", err);
+                protocol::Response::error(err)
+            }
+        },
+        Err(err) => {
+            log::error!("Could not read request from client: {}", err);
+            return false;
         }
-    } else {
-        log::error!("Could not read command from client.");
+    };
+
+    if let Err(err) = protocol::write_frame(&mut stream, &response) {
+        log::error!("Could not send response to client: {}", err);
     }
+    was_query
 }
 
 #[derive(Debug)]
@@ -312,6 +705,8 @@ impl InhibitState {
         if let InhibitState::FocusActive = self {
             log::debug!("Inhibiting tick focus updates");
             *self = InhibitState::FocusInhibit;
+            update_daemon_state(|state| state.sequence_inhibited = true);
+            events::broadcast(&Event::SequenceStarted);
         }
     }
 
@@ -319,6 +714,8 @@ impl InhibitState {
         if let InhibitState::FocusInhibit = self {
             log::debug!("Activating tick focus updates");
             *self = InhibitState::FocusActive;
+            update_daemon_state(|state| state.sequence_inhibited = false);
+            events::broadcast(&Event::SequenceEnded);
         }
     }
 }
@@ -326,8 +723,6 @@ impl InhibitState {
 fn focus_lock_in_handler(
     focus_chan: mpsc::Receiver<FocusMessage>,
     fdata: FocusData,
-    lockin_delay: Duration,
-    seq_inhibit: bool,
 ) {
     // Focus event that has not yet been locked-in to the LRU order
     let mut pending_fev: Option<FocusEvent> = None;
@@ -336,9 +731,30 @@ fn focus_lock_in_handler(
     let mut inhibit = InhibitState::FocusActive;
 
     let update_focus = |fev: Option<FocusEvent>| {
+        set_pending_lock_in(None);
         if let Some(fev) = fev {
             log::debug!("Locking-in focus on {}", fev.node_id);
-            fdata.update_last_focus_tick(fev.node_id, fev.ev_focus_ctr)
+            // The global tick is updated last so that the scoped ticks and
+            // the focus history are up to date once the LRU order reflects
+            // the lock-in.
+            if let Some(scope) = &fev.scope {
+                fdata.update_scoped_focus_tick(
+                    fev.node_id,
+                    fev.ev_focus_ctr,
+                    scope,
+                );
+                // Only windows have a workspace scope and only those make up
+                // the focus history.
+                if scope.workspace.is_some() {
+                    fdata.focus_history.write().unwrap().record(fev.node_id);
+                }
+            }
+            fdata.update_last_focus_tick(fev.node_id, fev.ev_focus_ctr);
+            fdata.update_last_focus_time(fev.node_id);
+            events::broadcast(&Event::FocusLockedIn {
+                id: fev.node_id,
+                tick: fev.ev_focus_ctr,
+            });
         }
     };
 
@@ -352,7 +768,7 @@ fn focus_lock_in_handler(
         let mut fev = match fmsg {
             FocusMessage::TickUpdateInhibit
             | FocusMessage::TickUpdateActivate
-                if !seq_inhibit =>
+                if !config::get_config().get_misc_seq_inhibit() =>
             {
                 continue
             }
@@ -366,6 +782,7 @@ fn focus_lock_in_handler(
                 continue;
             }
             FocusMessage::FocusEvent(fev) => {
+                set_pending_lock_in(Some(&fev));
                 if let InhibitState::FocusInhibit = inhibit {
                     // update the pending event but take no further action
                     pending_fev = Some(fev);
@@ -377,7 +794,9 @@ fn focus_lock_in_handler(
 
         // Inner loop, waiting for the lock-in delay to expire
         loop {
-            let fmsg = match focus_chan.recv_timeout(lockin_delay) {
+            let fmsg = match focus_chan
+                .recv_timeout(config::get_config().get_focus_lockin_delay())
+            {
                 Ok(fmsg) => fmsg,
                 Err(mpsc::RecvTimeoutError::Timeout) => {
                     update_focus(Some(fev));
@@ -389,7 +808,7 @@ fn focus_lock_in_handler(
             match fmsg {
                 FocusMessage::TickUpdateInhibit
                 | FocusMessage::TickUpdateActivate
-                    if !seq_inhibit =>
+                    if !config::get_config().get_misc_seq_inhibit() =>
                 {
                     continue
                 }
@@ -411,9 +830,18 @@ fn focus_lock_in_handler(
                 FocusMessage::FocusEvent(new_fev) => {
                     // start a new wait (inner) loop with the most recent
                     // focus event
+                    set_pending_lock_in(Some(&new_fev));
                     fev = new_fev;
                 }
             }
         }
     }
 }
+
+#[test]
+fn test_reconnect_delay() {
+    assert_eq!(reconnect_delay(1), MIN_RECONNECT_DELAY);
+    assert_eq!(reconnect_delay(2), MIN_RECONNECT_DELAY * 2);
+    assert_eq!(reconnect_delay(4), MIN_RECONNECT_DELAY * 8);
+    assert_eq!(reconnect_delay(100), MAX_RECONNECT_DELAY);
+}
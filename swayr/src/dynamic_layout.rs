@@ -0,0 +1,481 @@
+// Copyright (C) 2022  Tassilo Horn <tsdh@gnu.org>
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Dynamic tiling layouts such as master-stack which swayrd keeps up to date
+//! when windows appear on or vanish from a workspace.
+//!
+//! A workspace is arranged by moving its tiled windows to a temporary
+//! workspace and moving them back one after the other using marks so that
+//! the intended tree of split containers results.  Only visible workspaces
+//! are arranged because sway destroys invisible ones when they become empty.
+//! Other workspaces are arranged as soon as they are shown.
+
+use crate::cmds::CmdResult;
+use crate::config;
+use crate::layout::{self, Arrangement, ContainerLayout, SWAYR_TMP_WORKSPACE};
+use crate::shared::ipc;
+use crate::shared::ipc::NodeMethods;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+use swayipc as s;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DynamicLayout {
+    /// Windows stay where sway puts them.
+    None,
+    /// The master windows on the left, the others stacked on the right.
+    MasterLeft,
+    /// The master windows on the right, the others stacked on the left.
+    MasterRight,
+    /// The master windows at the top, the others side by side below.
+    MasterTop,
+    /// All windows side by side.
+    Columns,
+    /// Each window takes half of the space left by its predecessors which
+    /// spiral inwards clockwise.
+    Spiral,
+    /// Like spiral but the remaining space is always at the right or bottom.
+    Dwindle,
+}
+
+/// The order in which `cycle-layout` switches layouts.
+const LAYOUT_CYCLE: [DynamicLayout; 7] = [
+    DynamicLayout::MasterLeft,
+    DynamicLayout::MasterRight,
+    DynamicLayout::MasterTop,
+    DynamicLayout::Columns,
+    DynamicLayout::Spiral,
+    DynamicLayout::Dwindle,
+    DynamicLayout::None,
+];
+
+impl DynamicLayout {
+    fn next(self) -> DynamicLayout {
+        let idx = LAYOUT_CYCLE.iter().position(|l| *l == self).unwrap_or(0);
+        LAYOUT_CYCLE[(idx + 1) % LAYOUT_CYCLE.len()]
+    }
+
+    /// The arrangement of the given windows, masters first.
+    fn arrange(self, ids: &[i64], master_count: usize) -> Option<Arrangement> {
+        match self {
+            DynamicLayout::None => None,
+            DynamicLayout::MasterLeft => {
+                master_stack(ids, master_count, ContainerLayout::SplitH, true)
+            }
+            DynamicLayout::MasterRight => {
+                master_stack(ids, master_count, ContainerLayout::SplitH, false)
+            }
+            DynamicLayout::MasterTop => {
+                master_stack(ids, master_count, ContainerLayout::SplitV, true)
+            }
+            DynamicLayout::Columns => {
+                layout::container(ContainerLayout::SplitH, windows(ids))
+            }
+            DynamicLayout::Spiral => {
+                dwindle(ids, ContainerLayout::SplitH, false, true)
+            }
+            DynamicLayout::Dwindle => {
+                dwindle(ids, ContainerLayout::SplitH, false, false)
+            }
+        }
+    }
+}
+
+fn windows(ids: &[i64]) -> Vec<Arrangement> {
+    ids.iter().map(|id| Arrangement::Window(*id)).collect()
+}
+
+/// The other one of `splith` and `splitv`.
+fn other(layout: ContainerLayout) -> ContainerLayout {
+    match layout {
+        ContainerLayout::SplitH => ContainerLayout::SplitV,
+        _ => ContainerLayout::SplitH,
+    }
+}
+
+fn master_stack(
+    ids: &[i64],
+    master_count: usize,
+    dir: ContainerLayout,
+    masters_first: bool,
+) -> Option<Arrangement> {
+    let (masters, stack) = ids.split_at(master_count.min(ids.len()));
+    let masters = layout::container(other(dir), windows(masters));
+    let stack = layout::container(other(dir), windows(stack));
+    let parts = if masters_first {
+        [masters, stack]
+    } else {
+        [stack, masters]
+    };
+    layout::container(dir, parts.into_iter().flatten().collect())
+}
+
+/// Splits off one window after the other alternating between `splith` and
+/// `splitv` starting with `dir`.  With `spiral`, the order of the window and
+/// the rest is reversed after every second split so that they circle inwards.
+fn dwindle(
+    ids: &[i64],
+    dir: ContainerLayout,
+    reversed: bool,
+    spiral: bool,
+) -> Option<Arrangement> {
+    let (first, rest) = ids.split_first()?;
+    let first = Arrangement::Window(*first);
+    let reverse_next = spiral && dir == ContainerLayout::SplitV;
+    let rest = match dwindle(rest, other(dir), reversed != reverse_next, spiral)
+    {
+        Some(rest) => rest,
+        None => return Some(first),
+    };
+    let children = if reversed {
+        vec![rest, first]
+    } else {
+        vec![first, rest]
+    };
+    Some(Arrangement::Container(dir, children))
+}
+
+/// The sway commands arranging the windows of the given workspace.
+fn arrange_commands(
+    ws_name: &str,
+    arrangement: &Arrangement,
+    focused: Option<i64>,
+) -> Vec<String> {
+    let mut cmds: Vec<String> = arrangement
+        .window_ids()
+        .iter()
+        .map(|id| {
+            format!("[con_id={}] move to workspace {}", id, SWAYR_TMP_WORKSPACE)
+        })
+        .collect();
+    cmds.extend(layout::build_commands(ws_name, arrangement));
+    if let Some(id) = focused {
+        cmds.push(format!("[con_id={}] focus", id));
+    }
+    cmds
+}
+
+#[derive(Debug, Default)]
+struct WorkspaceState {
+    /// Set by `cycle-layout`, otherwise the configured layout applies.
+    layout: Option<DynamicLayout>,
+    /// Set by `increase-master-count` and `decrease-master-count`.
+    master_count: Option<usize>,
+    /// The tiled windows, masters first.
+    order: Vec<i64>,
+}
+
+/// The states of workspaces by name.
+static WORKSPACES: Lazy<Mutex<HashMap<String, WorkspaceState>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn lock_workspaces() -> MutexGuard<'static, HashMap<String, WorkspaceState>> {
+    WORKSPACES.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+fn get_layout(
+    config: &config::Config,
+    state: Option<&WorkspaceState>,
+    ws: &str,
+    output: &str,
+) -> DynamicLayout {
+    state
+        .and_then(|s| s.layout)
+        .unwrap_or_else(|| config.get_layout_dynamic_layout(ws, output))
+}
+
+/// True if the given workspace on the given output has a dynamic layout.
+pub fn is_managed(config: &config::Config, ws: &str, output: &str) -> bool {
+    let workspaces = lock_workspaces();
+    get_layout(config, workspaces.get(ws), ws, output) != DynamicLayout::None
+}
+
+/// Updates `order` to consist of the given windows and returns true if it
+/// has changed.  Windows not in `order` are put in front so that new windows
+/// become masters.
+fn sync_order(order: &mut Vec<i64>, ids: &[i64]) -> bool {
+    let live: HashSet<i64> = ids.iter().copied().collect();
+    let known: HashSet<i64> = order.iter().copied().collect();
+    let mut new_order: Vec<i64> = ids
+        .iter()
+        .filter(|id| !known.contains(id))
+        .copied()
+        .collect();
+    new_order.extend(order.iter().filter(|id| live.contains(id)));
+    let changed = new_order != *order;
+    *order = new_order;
+    changed
+}
+
+/// The tiled windows of `node` in tree order.
+fn tiled_windows(node: &s::Node) -> Vec<i64> {
+    node.nodes
+        .iter()
+        .flat_map(|n| {
+            if n.get_type() == ipc::Type::Window {
+                vec![n.id]
+            } else {
+                tiled_windows(n)
+            }
+        })
+        .collect()
+}
+
+/// The visible workspaces together with their outputs.
+fn visible_workspaces(root: &s::Node) -> Vec<(&s::Node, &s::Node)> {
+    root.nodes
+        .iter()
+        .filter(|output| !output.is_scratchpad())
+        .flat_map(|output| {
+            output
+                .nodes
+                .iter()
+                .filter(|ws| {
+                    ws.get_name() != SWAYR_TMP_WORKSPACE
+                        && (ws.is_current()
+                            || output.focus.first() == Some(&ws.id))
+                })
+                .map(move |ws| (output, ws))
+        })
+        .collect()
+}
+
+fn arrange(
+    ws: &s::Node,
+    layout: DynamicLayout,
+    master_count: usize,
+    order: &[i64],
+) -> Result<(), String> {
+    match layout.arrange(order, master_count) {
+        Some(arrangement @ Arrangement::Container(..)) => {
+            let focused = ws.iter().find(|n| n.focused).map(|n| n.id);
+            log::debug!(
+                "Arranging workspace {} with layout {:?}.",
+                ws.get_name(),
+                layout
+            );
+            ipc::run_commands(&arrange_commands(
+                ws.get_name(),
+                &arrangement,
+                focused,
+            ))
+        }
+        // Nothing to arrange for a single window.
+        _ => Ok(()),
+    }
+}
+
+/// Arranges the visible workspaces with a dynamic layout whose tiled windows
+/// have changed since they were arranged the last time.
+pub fn maybe_arrange(config: &config::Config) {
+    let mut workspaces = lock_workspaces();
+    let overridden = workspaces
+        .values()
+        .any(|s| s.layout.is_some_and(|l| l != DynamicLayout::None));
+    if !overridden && !config.has_layout_dynamic_layouts() {
+        return;
+    }
+
+    let root = match ipc::get_tree(false) {
+        Ok(root) => root,
+        Err(err) => {
+            log::error!("Couldn't get tree for arranging workspaces: {}", err);
+            return;
+        }
+    };
+    let names: HashSet<&str> = root
+        .nodes_of_type(ipc::Type::Workspace)
+        .iter()
+        .map(|ws| ws.get_name())
+        .collect();
+    workspaces.retain(|name, _| names.contains(name.as_str()));
+
+    for (output, ws) in visible_workspaces(&root) {
+        let state = workspaces.entry(ws.get_name().to_owned()).or_default();
+        let layout =
+            get_layout(config, Some(state), ws.get_name(), output.get_name());
+        let changed = sync_order(&mut state.order, &tiled_windows(ws));
+        if changed && layout != DynamicLayout::None {
+            let master_count = state
+                .master_count
+                .unwrap_or_else(|| config.get_layout_master_count());
+            if let Err(err) = arrange(ws, layout, master_count, &state.order) {
+                log::error!("Couldn't arrange workspace: {}", err);
+            }
+        }
+    }
+}
+
+/// Lets `f` modify the state of the current workspace given its layout and
+/// focused window, and then arranges it.
+fn update_current_workspace<F>(f: F) -> CmdResult
+where
+    F: FnOnce(
+        &mut WorkspaceState,
+        DynamicLayout,
+        Option<i64>,
+    ) -> Result<(), String>,
+{
+    let config = config::get_config();
+    let root = ipc::get_tree(false)
+        .map_err(|err| format!("Couldn't get tree: {}", err))?;
+    let (output, ws) = visible_workspaces(&root)
+        .into_iter()
+        .find(|(_, ws)| ws.is_current())
+        .ok_or_else(|| "No workspace is focused.".to_owned())?;
+    let mut workspaces = lock_workspaces();
+    let state = workspaces.entry(ws.get_name().to_owned()).or_default();
+    sync_order(&mut state.order, &tiled_windows(ws));
+    let layout =
+        get_layout(&config, Some(state), ws.get_name(), output.get_name());
+    let focused = ws
+        .iter()
+        .find(|n| n.focused && n.get_type() == ipc::Type::Window)
+        .map(|n| n.id);
+    f(state, layout, focused)?;
+
+    let layout =
+        get_layout(&config, Some(state), ws.get_name(), output.get_name());
+    let master_count = state
+        .master_count
+        .unwrap_or_else(|| config.get_layout_master_count());
+    arrange(ws, layout, master_count, &state.order)?;
+    Ok(vec![ws.id].into())
+}
+
+fn ensure_managed(layout: DynamicLayout) -> Result<(), String> {
+    if layout == DynamicLayout::None {
+        Err("The current workspace has no dynamic layout.".to_owned())
+    } else {
+        Ok(())
+    }
+}
+
+/// Swaps the focused window with the first master window, or with the first
+/// stack window if it is the first master window itself.
+pub fn swap_with_master() -> CmdResult {
+    update_current_workspace(|state, layout, focused| {
+        ensure_managed(layout)?;
+        let focused = focused.ok_or_else(|| "No focused window.".to_owned())?;
+        let idx = state
+            .order
+            .iter()
+            .position(|id| *id == focused)
+            .ok_or_else(|| "The focused window isn't tiled.".to_owned())?;
+        let other = if idx == 0 { 1 } else { 0 };
+        if other >= state.order.len() {
+            return Err("No other window to swap with.".to_owned());
+        }
+        state.order.swap(idx, other);
+        Ok(())
+    })
+}
+
+/// Increases or decreases the number of windows in the master area.
+pub fn change_master_count(increase: bool) -> CmdResult {
+    let default = config::get_config().get_layout_master_count();
+    update_current_workspace(|state, layout, _| {
+        ensure_managed(layout)?;
+        let count = state.master_count.unwrap_or(default);
+        state.master_count = Some(if increase {
+            (count + 1).min(state.order.len())
+        } else {
+            count.saturating_sub(1)
+        });
+        Ok(())
+    })
+}
+
+/// Switches the current workspace to the next dynamic layout.
+pub fn cycle_layout() -> CmdResult {
+    update_current_workspace(|state, layout, _| {
+        state.layout = Some(layout.next());
+        log::info!("Switched to layout {:?}.", layout.next());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_arrangements() {
+    use Arrangement::Window as W;
+    use ContainerLayout::{SplitH as H, SplitV as V};
+    let s = Arrangement::Container;
+
+    assert_eq!(DynamicLayout::MasterLeft.arrange(&[1], 1), Some(W(1)));
+    assert_eq!(
+        DynamicLayout::MasterLeft.arrange(&[1, 2, 3], 1),
+        Some(s(H, vec![W(1), s(V, vec![W(2), W(3)])]))
+    );
+    assert_eq!(
+        DynamicLayout::MasterRight.arrange(&[1, 2, 3], 2),
+        Some(s(H, vec![W(3), s(V, vec![W(1), W(2)])]))
+    );
+    assert_eq!(
+        DynamicLayout::MasterTop.arrange(&[1, 2], 0),
+        Some(s(H, vec![W(1), W(2)]))
+    );
+    assert_eq!(
+        DynamicLayout::Spiral.arrange(&[1, 2, 3, 4, 5], 1),
+        Some(s(
+            H,
+            vec![
+                W(1),
+                s(V, vec![W(2), s(H, vec![s(V, vec![W(5), W(4)]), W(3)])])
+            ]
+        ))
+    );
+    assert_eq!(
+        DynamicLayout::Dwindle.arrange(&[1, 2, 3], 1),
+        Some(s(H, vec![W(1), s(V, vec![W(2), W(3)])]))
+    );
+    assert_eq!(DynamicLayout::None.arrange(&[1, 2, 3], 1), None);
+}
+
+#[test]
+fn test_arrange_commands() {
+    let arrangement = DynamicLayout::MasterLeft.arrange(&[1, 2, 3], 1).unwrap();
+    assert_eq!(
+        arrange_commands("1", &arrangement, Some(3)),
+        vec![
+            "[con_id=1] move to workspace ✨",
+            "[con_id=2] move to workspace ✨",
+            "[con_id=3] move to workspace ✨",
+            "[con_id=1] move to workspace \"1\"",
+            "[con_id=1] layout splith",
+            "[con_id=1] mark --add _swayr_layout",
+            "[con_id=2] move to mark _swayr_layout",
+            "[con_id=2] splitv",
+            "[con_id=2] mark --add _swayr_layout",
+            "[con_id=3] move to mark _swayr_layout",
+            "unmark _swayr_layout",
+            "[con_id=3] focus",
+        ]
+    );
+}
+
+#[test]
+fn test_sync_order() {
+    let mut order = vec![];
+    assert!(sync_order(&mut order, &[1, 2]));
+    assert_eq!(order, vec![1, 2]);
+    assert!(sync_order(&mut order, &[2, 1, 3]));
+    assert_eq!(order, vec![3, 1, 2]);
+    assert!(!sync_order(&mut order, &[1, 2, 3]));
+    assert!(sync_order(&mut order, &[3, 2]));
+    assert_eq!(order, vec![3, 2]);
+}
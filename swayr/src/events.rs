@@ -0,0 +1,135 @@
+// Copyright (C) 2022  Tassilo Horn <tsdh@gnu.org>
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! High-level swayrd events streamed to subscribed clients.
+//!
+//! A client subscribes by sending a `subscribe` request.  After the successful
+//! response, swayrd keeps the connection open and sends one [`Event`] frame
+//! per event until the client disconnects.
+
+use crate::cmds::SwayrCommand;
+use crate::daemon::Health;
+use crate::protocol;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Subscribers which don't read their events within that time are dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Subscribers with that many events not yet written are dropped, too.
+const MAX_PENDING_EVENTS: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// The window or workspace with the given id has kept the focus for the
+    /// lock-in delay and is now the most recently used one.
+    FocusLockedIn { id: i64, tick: u64 },
+    /// A sequence of window cycling commands has started, i.e., LRU updates
+    /// are inhibited.
+    SequenceStarted,
+    /// A sequence of window cycling commands has ended.
+    SequenceEnded,
+    /// A command sent by a client has been executed.
+    CommandExecuted {
+        cmd: SwayrCommand,
+        success: bool,
+        error: Option<String>,
+    },
+    /// The config has been reloaded.
+    ConfigReloaded,
+    /// The state of swayrd's connection to sway has changed.
+    HealthChanged { health: Health },
+}
+
+/// The queues of the subscribers' writer threads.
+static SUBSCRIBERS: Lazy<Mutex<Vec<SyncSender<Event>>>> =
+    Lazy::new(|| Mutex::new(vec![]));
+
+pub fn add_subscriber(mut stream: UnixStream) {
+    if let Err(err) = stream.set_write_timeout(Some(WRITE_TIMEOUT)) {
+        log::error!("Could not set write timeout for subscriber: {}", err);
+        return;
+    }
+    // Each subscriber has its own writer thread so that a slow one doesn't
+    // block the threads broadcasting events.
+    let (sender, receiver) = mpsc::sync_channel::<Event>(MAX_PENDING_EVENTS);
+    std::thread::spawn(move || {
+        for event in receiver {
+            if let Err(err) = protocol::write_frame(&mut stream, &event) {
+                log::debug!("Dropping subscriber: {}", err);
+                return;
+            }
+        }
+    });
+    let mut subscribers = SUBSCRIBERS.lock().expect("Could not lock mutex");
+    subscribers.push(sender);
+    log::debug!("New subscriber, now {} subscribers.", subscribers.len());
+}
+
+/// Sends `event` to all subscribers dropping the ones which have disconnected
+/// or are too slow.
+pub fn broadcast(event: &Event) {
+    let mut subscribers = SUBSCRIBERS.lock().expect("Could not lock mutex");
+    if subscribers.is_empty() {
+        return;
+    }
+    log::trace!("Broadcasting {:?}", event);
+    subscribers.retain(|sender| match sender.try_send(event.clone()) {
+        Ok(()) => true,
+        Err(TrySendError::Full(_)) => {
+            log::debug!("Dropping subscriber which doesn't read its events.");
+            false
+        }
+        // The writer thread has already logged why.
+        Err(TrySendError::Disconnected(_)) => false,
+    });
+}
+
+#[test]
+fn test_broadcast() {
+    let (ours, mut theirs) = UnixStream::pair().unwrap();
+    add_subscriber(ours);
+    let ev = Event::CommandExecuted {
+        cmd: SwayrCommand::Nop,
+        success: false,
+        error: Some("No window to switch to.".to_owned()),
+    };
+    broadcast(&ev);
+    broadcast(&Event::FocusLockedIn { id: 17, tick: 4 });
+
+    let frame = protocol::read_frame(&mut theirs).unwrap();
+    assert_eq!(serde_json::from_slice::<Event>(&frame).unwrap(), ev);
+    let frame = protocol::read_frame(&mut theirs).unwrap();
+    assert_eq!(
+        std::str::from_utf8(&frame).unwrap(),
+        r#"{"event":"focus_locked_in","id":17,"tick":4}"#
+    );
+
+    // Disconnected subscribers are dropped once their writer thread noticed.
+    drop(theirs);
+    for _ in 0..100 {
+        broadcast(&Event::SequenceStarted);
+        if SUBSCRIBERS.lock().unwrap().is_empty() {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    panic!("The disconnected subscriber hasn't been dropped.");
+}
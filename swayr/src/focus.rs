@@ -15,18 +15,129 @@
 
 //! Structure to hold window focus timestamps used by swayrd
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::mpsc;
 use std::sync::Arc;
 use std::sync::RwLock;
+use std::time::Instant;
 
 /// Data tracking most recent focus events for Sway windows/containers
 #[derive(Clone)]
 pub struct FocusData {
     pub focus_tick_by_id: Arc<RwLock<HashMap<i64, u64>>>,
+    /// When the focus of a window was locked in the last time.
+    pub focus_time_by_id: Arc<RwLock<HashMap<i64, Instant>>>,
+    pub scoped_focus_ticks: Arc<RwLock<ScopedFocusTicks>>,
+    pub focus_history: Arc<RwLock<FocusHistory>>,
     pub focus_chan: mpsc::Sender<FocusMessage>,
 }
 
+/// The maximum number of entries in the [`FocusHistory`].
+const MAX_FOCUS_HISTORY_LEN: usize = 100;
+
+/// A linear history of focused windows with a cursor like the history of a
+/// web browser or the jump list of an editor.  Going back and forward moves
+/// the cursor without recording the jumps.  Focusing a window by other means
+/// drops the entries after the cursor and appends the window.
+#[derive(Debug, Default)]
+pub struct FocusHistory {
+    entries: Vec<i64>,
+    cursor: usize,
+    /// The window which is being focused by going back or forward.
+    pending_jump: Option<i64>,
+}
+
+impl FocusHistory {
+    pub fn entries(&self) -> &[i64] {
+        &self.entries
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Records that the window with the given id has been focused unless
+    /// that's the result of going back or forward.
+    pub fn record(&mut self, id: i64) {
+        if self.pending_jump.take() == Some(id) {
+            return;
+        }
+        if self.entries.get(self.cursor) == Some(&id) {
+            return;
+        }
+        self.entries.truncate(self.cursor + 1);
+        self.entries.push(id);
+        if self.entries.len() > MAX_FOCUS_HISTORY_LEN {
+            self.entries.remove(0);
+        }
+        self.cursor = self.entries.len() - 1;
+    }
+
+    /// Moves the cursor one step back and returns the window there.
+    pub fn back(&mut self) -> Option<i64> {
+        if self.cursor == 0 || self.entries.is_empty() {
+            return None;
+        }
+        self.cursor -= 1;
+        self.pending_jump = Some(self.entries[self.cursor]);
+        self.pending_jump
+    }
+
+    /// Moves the cursor one step forward and returns the window there.
+    pub fn forward(&mut self) -> Option<i64> {
+        if self.cursor + 1 >= self.entries.len() {
+            return None;
+        }
+        self.cursor += 1;
+        self.pending_jump = Some(self.entries[self.cursor]);
+        self.pending_jump
+    }
+
+    /// Removes all entries for which `keep` returns false, e.g., closed
+    /// windows.  The cursor stays at the same entry or, if that's removed,
+    /// moves to the entry before it.
+    pub fn retain<P: Fn(i64) -> bool>(&mut self, keep: P) {
+        let mut cursor = self.cursor;
+        let mut entries: Vec<i64> = vec![];
+        for (i, id) in self.entries.iter().enumerate() {
+            // Consecutive duplicates arise when the entries in between have
+            // been removed.
+            if keep(*id) && entries.last() != Some(id) {
+                entries.push(*id);
+            } else if i <= self.cursor {
+                cursor = cursor.saturating_sub(1);
+            }
+        }
+        self.entries = entries;
+        self.cursor = cursor.min(self.entries.len().saturating_sub(1));
+    }
+}
+
+/// The focus ticks of windows per workspace and of windows and workspaces per
+/// output.  A window which is moved to another workspace or output has no
+/// tick there until it is focused there.
+#[derive(Debug, Default)]
+pub struct ScopedFocusTicks {
+    pub by_workspace: HashMap<i64, HashMap<i64, u64>>,
+    pub by_output: HashMap<String, HashMap<i64, u64>>,
+}
+
+/// The workspace and output a window or workspace was on when its focus was
+/// locked in.  For workspaces, `workspace` is `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FocusScope {
+    pub workspace: Option<i64>,
+    pub output: String,
+}
+
+/// The scope of an LRU order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LruScope {
+    Global,
+    Workspace(i64),
+    Output(String),
+}
+
 impl FocusData {
     pub fn last_focus_tick(&self, id: i64) -> u64 {
         *self.focus_tick_by_id.read().unwrap().get(&id).unwrap_or(&0)
@@ -40,8 +151,123 @@ impl FocusData {
         // else the node has since been closed before this focus event got locked in
     }
 
+    pub fn update_scoped_focus_tick(
+        &self,
+        id: i64,
+        focus_val: u64,
+        scope: &FocusScope,
+    ) {
+        let mut write_lock = self.scoped_focus_ticks.write().unwrap();
+        if let Some(ws_id) = scope.workspace {
+            write_lock
+                .by_workspace
+                .entry(ws_id)
+                .or_default()
+                .insert(id, focus_val);
+        }
+        write_lock
+            .by_output
+            .entry(scope.output.clone())
+            .or_default()
+            .insert(id, focus_val);
+    }
+
+    /// The focus tick of the node with the given id within `scope`, 0 if it
+    /// has never been focused there.
+    pub fn last_focus_tick_in(&self, id: i64, scope: &LruScope) -> u64 {
+        let read_lock = self.scoped_focus_ticks.read().unwrap();
+        let ticks = match scope {
+            LruScope::Global => return self.last_focus_tick(id),
+            LruScope::Workspace(ws_id) => read_lock.by_workspace.get(ws_id),
+            LruScope::Output(output) => read_lock.by_output.get(output),
+        };
+        ticks.and_then(|t| t.get(&id)).copied().unwrap_or(0)
+    }
+
+    /// The key for sorting nodes by recency within `scope` where the global
+    /// focus tick breaks ties, e.g., between nodes which haven't been focused
+    /// in `scope` yet.
+    pub fn lru_key(&self, id: i64, scope: &LruScope) -> (u64, u64) {
+        (self.last_focus_tick_in(id, scope), self.last_focus_tick(id))
+    }
+
+    /// The time when the focus of the node with the given id was locked in
+    /// the last time since swayrd started.
+    pub fn last_focus_time(&self, id: i64) -> Option<Instant> {
+        self.focus_time_by_id.read().unwrap().get(&id).copied()
+    }
+
+    /// Records that the focus of the node with the given id has been locked
+    /// in just now.
+    pub fn update_last_focus_time(&self, id: i64) {
+        if self.focus_tick_by_id.read().unwrap().contains_key(&id) {
+            self.focus_time_by_id
+                .write()
+                .unwrap()
+                .insert(id, Instant::now());
+        }
+    }
+
+    /// The largest focus tick of all nodes, e.g., after the focus history has
+    /// been restored.
+    pub fn max_focus_tick(&self) -> u64 {
+        self.focus_tick_by_id
+            .read()
+            .unwrap()
+            .values()
+            .copied()
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Removes all ids which are not in `live_ids` and adds the missing ones.
+    /// Returns the number of removed and added ids.
+    pub fn resync(&self, live_ids: &HashSet<i64>) -> (usize, usize) {
+        let mut write_lock = self.focus_tick_by_id.write().unwrap();
+        let before = write_lock.len();
+        write_lock.retain(|id, _| live_ids.contains(id));
+        let removed = before - write_lock.len();
+        let mut added = 0;
+        for id in live_ids {
+            if !write_lock.contains_key(id) {
+                write_lock.insert(*id, 0);
+                added += 1;
+            }
+        }
+
+        self.focus_time_by_id
+            .write()
+            .unwrap()
+            .retain(|id, _| live_ids.contains(id));
+        let mut scoped = self.scoped_focus_ticks.write().unwrap();
+        scoped
+            .by_workspace
+            .retain(|ws_id, _| live_ids.contains(ws_id));
+        for ticks in scoped.by_workspace.values_mut() {
+            ticks.retain(|id, _| live_ids.contains(id));
+        }
+        for ticks in scoped.by_output.values_mut() {
+            ticks.retain(|id, _| live_ids.contains(id));
+        }
+        self.focus_history
+            .write()
+            .unwrap()
+            .retain(|id| live_ids.contains(&id));
+        (removed, added)
+    }
+
     pub fn remove_focus_data(&self, id: i64) {
         self.focus_tick_by_id.write().unwrap().remove(&id);
+        self.focus_time_by_id.write().unwrap().remove(&id);
+        self.focus_history.write().unwrap().retain(|i| i != id);
+        let mut scoped = self.scoped_focus_ticks.write().unwrap();
+        scoped.by_workspace.remove(&id);
+        for ticks in scoped.by_workspace.values_mut() {
+            ticks.remove(&id);
+        }
+        for ticks in scoped.by_output.values_mut() {
+            ticks.remove(&id);
+        }
     }
 
     /// Ensures that a given node_id is present in the ExtraProps map, this
@@ -68,6 +294,8 @@ impl FocusData {
 pub struct FocusEvent {
     pub node_id: i64,      // node receiving the focus
     pub ev_focus_ctr: u64, // Counter for this specific focus event
+    /// Where the node was when it received the focus, if known.
+    pub scope: Option<FocusScope>,
 }
 
 pub enum FocusMessage {
@@ -75,3 +303,121 @@ pub enum FocusMessage {
     TickUpdateActivate,
     FocusEvent(FocusEvent),
 }
+
+#[test]
+fn test_resync() {
+    let (focus_chan, _rx) = mpsc::channel();
+    let fdata = FocusData {
+        focus_tick_by_id: Arc::new(RwLock::new(HashMap::from([
+            (1, 10),
+            (2, 20),
+            (3, 0),
+        ]))),
+        focus_time_by_id: Arc::new(RwLock::new(HashMap::new())),
+        scoped_focus_ticks: Arc::new(RwLock::new(ScopedFocusTicks::default())),
+        focus_history: Arc::new(RwLock::new(FocusHistory::default())),
+        focus_chan,
+    };
+    let scope = FocusScope {
+        workspace: Some(3),
+        output: "DP-1".to_owned(),
+    };
+    fdata.update_scoped_focus_tick(1, 10, &scope);
+    fdata.update_scoped_focus_tick(2, 20, &scope);
+    assert_eq!(fdata.resync(&HashSet::from([2, 3, 4, 5])), (1, 2));
+    assert_eq!(fdata.last_focus_tick(1), 0);
+    assert_eq!(fdata.last_focus_tick(2), 20);
+    assert_eq!(fdata.focus_tick_by_id.read().unwrap().len(), 4);
+    let output = LruScope::Output("DP-1".to_owned());
+    assert_eq!(fdata.last_focus_tick_in(1, &output), 0);
+    assert_eq!(fdata.last_focus_tick_in(2, &output), 20);
+}
+
+#[test]
+fn test_scoped_focus_ticks() {
+    let (focus_chan, _rx) = mpsc::channel();
+    let fdata = FocusData {
+        focus_tick_by_id: Arc::new(RwLock::new(HashMap::from([
+            (1, 0),
+            (2, 0),
+        ]))),
+        focus_time_by_id: Arc::new(RwLock::new(HashMap::new())),
+        scoped_focus_ticks: Arc::new(RwLock::new(ScopedFocusTicks::default())),
+        focus_history: Arc::new(RwLock::new(FocusHistory::default())),
+        focus_chan,
+    };
+    let on_ws = |ws_id, output: &str| FocusScope {
+        workspace: Some(ws_id),
+        output: output.to_owned(),
+    };
+    fdata.update_last_focus_tick(1, 1);
+    fdata.update_scoped_focus_tick(1, 1, &on_ws(10, "DP-1"));
+    fdata.update_last_focus_tick(2, 2);
+    fdata.update_scoped_focus_tick(2, 2, &on_ws(20, "DP-2"));
+    // Window 1 has been moved to workspace 20 and focused there.
+    fdata.update_last_focus_tick(1, 3);
+    fdata.update_scoped_focus_tick(1, 3, &on_ws(20, "DP-2"));
+
+    assert_eq!(fdata.last_focus_tick_in(1, &LruScope::Workspace(10)), 1);
+    assert_eq!(fdata.last_focus_tick_in(1, &LruScope::Workspace(20)), 3);
+    assert_eq!(fdata.last_focus_tick_in(2, &LruScope::Workspace(10)), 0);
+    let dp1 = LruScope::Output("DP-1".to_owned());
+    assert_eq!(fdata.lru_key(1, &dp1), (1, 3));
+    assert_eq!(fdata.lru_key(2, &dp1), (0, 2));
+    assert_eq!(fdata.lru_key(2, &LruScope::Global), (2, 2));
+
+    fdata.remove_focus_data(1);
+    assert_eq!(fdata.last_focus_tick_in(1, &LruScope::Workspace(20)), 0);
+    fdata.remove_focus_data(20);
+    assert!(
+        fdata.scoped_focus_ticks.read().unwrap().by_workspace[&10].is_empty()
+    );
+    assert!(!fdata
+        .scoped_focus_ticks
+        .read()
+        .unwrap()
+        .by_workspace
+        .contains_key(&20));
+}
+
+#[test]
+fn test_focus_history() {
+    let mut h = FocusHistory::default();
+    assert_eq!(h.back(), None);
+    for id in [1, 2, 2, 3, 4] {
+        h.record(id);
+    }
+    assert_eq!(h.entries(), [1, 2, 3, 4]);
+
+    // Going back and forth doesn't record the jumps.
+    assert_eq!(h.back(), Some(3));
+    h.record(3);
+    assert_eq!(h.back(), Some(2));
+    h.record(2);
+    assert_eq!(h.forward(), Some(3));
+    h.record(3);
+    assert_eq!(h.entries(), [1, 2, 3, 4]);
+    assert_eq!(h.cursor(), 2);
+
+    // Closing windows removes them, also around the cursor.
+    h.retain(|id| id != 2);
+    assert_eq!((h.entries(), h.cursor()), (&[1, 3, 4][..], 1));
+    h.retain(|id| id != 3);
+    assert_eq!((h.entries(), h.cursor()), (&[1, 4][..], 0));
+    assert_eq!(h.forward(), Some(4));
+    assert_eq!(h.forward(), None);
+
+    // Focusing a window normally drops the forward history.
+    h.record(4);
+    assert_eq!(h.back(), Some(1));
+    h.record(1);
+    h.record(5);
+    assert_eq!(h.entries(), [1, 5]);
+
+    // Consecutive duplicates are merged on removal.
+    for id in [6, 5, 6] {
+        h.record(id);
+    }
+    h.retain(|id| id != 5);
+    assert_eq!((h.entries(), h.cursor()), (&[1, 6][..], 1));
+}
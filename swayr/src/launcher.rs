@@ -0,0 +1,422 @@
+// Copyright (C) 2022  Tassilo Horn <tsdh@gnu.org>
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Launching applications from their desktop entries, ordered by frecency.
+
+use crate::cmds::{CmdOutput, CmdResult};
+use crate::config as cfg;
+use crate::criteria;
+use crate::focus::FocusData;
+use crate::persist;
+use crate::shared::fmt::subst_placeholders;
+use crate::shared::ipc;
+use crate::tree as t;
+use crate::util::{self, DisplayFormat};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process as proc;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+
+const FRECENCY_FILE: &str = "frecency.json";
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+/// How often and when an application was launched last.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+struct Usage {
+    count: u32,
+    /// Seconds since the epoch.
+    last_used: u64,
+}
+
+impl Usage {
+    /// The number of launches weighted by the age of the last launch.
+    fn score(&self, now: u64) -> f64 {
+        let weight = match now.saturating_sub(self.last_used) {
+            age if age < HOUR => 4.0,
+            age if age < DAY => 2.0,
+            age if age < WEEK => 1.0,
+            age if age < 4 * WEEK => 0.5,
+            _ => 0.25,
+        };
+        f64::from(self.count) * weight
+    }
+}
+
+/// The usages of applications by desktop file id.
+static USAGES: Lazy<Mutex<HashMap<String, Usage>>> = Lazy::new(|| {
+    Mutex::new(persist::load_state(FRECENCY_FILE).unwrap_or_default())
+});
+
+fn lock_usages() -> MutexGuard<'static, HashMap<String, Usage>> {
+    USAGES.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn record_launch(id: &str) {
+    let mut usages = lock_usages();
+    let usage = usages.entry(id.to_owned()).or_default();
+    usage.count += 1;
+    usage.last_used = now_secs();
+    persist::save_state(FRECENCY_FILE, &*usages);
+}
+
+/// An application as described by a desktop entry.
+#[derive(Debug, Clone)]
+pub struct AppEntry {
+    /// The desktop file id, e.g., `firefox.desktop`.
+    pub id: String,
+    /// The desktop entry file.
+    pub file: PathBuf,
+    pub name: String,
+    pub comment: String,
+    pub icon: Option<String>,
+    pub exec: String,
+    pub terminal: bool,
+    /// The working directory of the application.
+    pub path: Option<PathBuf>,
+    /// Hidden entries are not shown in menus.
+    pub hidden: bool,
+}
+
+impl AppEntry {
+    /// Reads the application from the given desktop entry file.  Returns
+    /// `None` if it's not an application or has no `Exec` key.
+    pub fn of_file(file: &Path) -> Option<AppEntry> {
+        let mut entry = util::read_desktop_entry(file);
+        if entry.get("Type").map(String::as_str) != Some("Application") {
+            return None;
+        }
+        let is_true = |v: Option<String>| v.as_deref() == Some("true");
+        Some(AppEntry {
+            id: file.file_name()?.to_string_lossy().into_owned(),
+            file: file.to_path_buf(),
+            exec: entry.remove("Exec")?,
+            name: entry.remove("Name")?,
+            comment: entry.remove("Comment").unwrap_or_default(),
+            icon: entry.remove("Icon"),
+            terminal: is_true(entry.remove("Terminal")),
+            path: entry.remove("Path").map(PathBuf::from),
+            hidden: is_true(entry.remove("NoDisplay"))
+                || is_true(entry.remove("Hidden")),
+        })
+    }
+
+    /// The program and arguments running the application.  Applications
+    /// which need a terminal are run in `terminal`.
+    pub fn command(&self, terminal: &str) -> Result<Vec<String>, String> {
+        let mut command = vec![];
+        if self.terminal {
+            command.extend(terminal.split_whitespace().map(String::from));
+        }
+        command.extend(util::parse_exec(self)?);
+        Ok(command)
+    }
+
+    /// Launches the application and returns the pid of its process.
+    pub fn launch(&self, terminal: &str) -> Result<u32, String> {
+        let command = self.command(terminal)?;
+        let (program, args) = command
+            .split_first()
+            .ok_or_else(|| format!("{} has an empty Exec key.", self.id))?;
+        let mut cmd = proc::Command::new(program);
+        cmd.args(args);
+        if let Some(dir) = &self.path {
+            cmd.current_dir(dir);
+        }
+        util::spawn_detached(&mut cmd)
+    }
+}
+
+impl DisplayFormat for AppEntry {
+    fn format_for_display(&self, cfg: &cfg::Config) -> String {
+        let icon_dirs = cfg.get_format_icon_dirs();
+        let app_icon = self
+            .icon
+            .as_ref()
+            .and_then(|i| util::find_icon(i, &icon_dirs))
+            .map(|i| i.to_string_lossy().into_owned())
+            .or_else(|| cfg.get_format_fallback_icon())
+            .unwrap_or_default();
+        let fmt = cfg
+            .get_format_app_format()
+            .replace("{app_icon}", app_icon.as_str());
+        subst_placeholders!(&fmt, cfg.get_format_html_escape(), {
+            "name" => self.name.as_str(),
+            "comment" => self.comment.as_str(),
+            "id" => self.id.as_str(),
+            "exec" => self.exec.as_str(),
+        })
+    }
+
+    fn get_indent_level(&self) -> usize {
+        0
+    }
+}
+
+/// The applications of all desktop entries which may be shown in menus.  If
+/// there are several entries with the same id, the first one wins.
+fn get_app_entries() -> Vec<AppEntry> {
+    let mut ids = HashSet::new();
+    util::desktop_entries()
+        .iter()
+        .filter_map(|e| AppEntry::of_file(e))
+        .filter(|a| ids.insert(a.id.clone()) && !a.hidden)
+        .collect()
+}
+
+/// Sorts the applications by descending frecency and then by name.
+fn sort_by_frecency(
+    apps: &mut [AppEntry],
+    usages: &HashMap<String, Usage>,
+    now: u64,
+) {
+    let score =
+        |a: &AppEntry| usages.get(&a.id).map(|u| u.score(now)).unwrap_or(0.0);
+    apps.sort_by(|a, b| {
+        score(b)
+            .total_cmp(&score(a))
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+    });
+}
+
+/// Shows a menu of all applications sorted by frecency and launches the
+/// selected one.
+pub fn launch_app() -> CmdResult {
+    let mut apps = get_app_entries();
+    sort_by_frecency(&mut apps, &lock_usages(), now_secs());
+    match util::select_from_menu("Launch application", &apps) {
+        Ok(app) => {
+            app.launch(&cfg::get_config().get_misc_terminal())?;
+            record_launch(&app.id);
+            Ok(CmdOutput::default())
+        }
+        Err(input) if input.is_empty() => Ok(CmdOutput::default()),
+        Err(input) => {
+            Err(format!("No application matches '{}'.", input).into())
+        }
+    }
+}
+
+/// Where the first window of a launched application is put.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Placement {
+    pub workspace: Option<String>,
+    pub floating: bool,
+    pub mark: Option<String>,
+}
+
+impl Placement {
+    fn is_empty(&self) -> bool {
+        self.workspace.is_none() && !self.floating && self.mark.is_none()
+    }
+
+    fn commands(&self, id: i64) -> Vec<String> {
+        let mut cmds = vec![];
+        if let Some(ws) = &self.workspace {
+            cmds.push(format!(
+                "[con_id={}] move to workspace {}",
+                id,
+                ipc::quote(ws)
+            ));
+        }
+        if self.floating {
+            cmds.push(format!("[con_id={}] floating enable", id));
+        }
+        if let Some(mark) = &self.mark {
+            cmds.push(format!(
+                "[con_id={}] mark --add {}",
+                id,
+                ipc::quote(mark)
+            ));
+        }
+        cmds
+    }
+}
+
+/// A launched application whose first window hasn't appeared yet.
+#[derive(Debug)]
+struct PendingPlacement {
+    pid: u32,
+    criteria: String,
+    placement: Placement,
+    deadline: Instant,
+}
+
+static PENDING_PLACEMENTS: Lazy<Mutex<Vec<PendingPlacement>>> =
+    Lazy::new(|| Mutex::new(vec![]));
+
+fn lock_pending_placements() -> MutexGuard<'static, Vec<PendingPlacement>> {
+    PENDING_PLACEMENTS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+}
+
+/// Runs the shell command `command`.  The first new window which belongs to
+/// its process or matches `criteria` within `timeout` is placed according to
+/// `placement`.
+pub fn launch_and_place(
+    command: &str,
+    criteria: &str,
+    placement: Placement,
+    timeout: Duration,
+) -> Result<(), String> {
+    let pid = util::spawn_detached(
+        proc::Command::new("sh")
+            .arg("-c")
+            .arg(format!("exec {}", command)),
+    )?;
+    log::debug!("Launched '{}' with pid {}.", command, pid);
+    if !placement.is_empty() {
+        lock_pending_placements().push(PendingPlacement {
+            pid,
+            criteria: criteria.to_owned(),
+            placement,
+            deadline: Instant::now() + timeout,
+        });
+    }
+    Ok(())
+}
+
+/// Places the new window with the given id if it belongs to an application
+/// launched by `launch_and_place`.
+pub fn place_new_window(id: i64, fdata: &FocusData) {
+    {
+        let mut pending = lock_pending_placements();
+        let now = Instant::now();
+        pending.retain(|p| p.deadline > now);
+        if pending.is_empty() {
+            return;
+        }
+    }
+
+    let root = match ipc::get_root_node(false) {
+        Ok(root) => root,
+        Err(err) => {
+            log::error!("Could not place window {}: {}", id, err);
+            return;
+        }
+    };
+    let tree = t::get_tree(&root);
+    let wins = tree.get_windows(fdata);
+    let win = match wins.iter().find(|w| w.node.id == id) {
+        Some(win) => win,
+        None => return,
+    };
+    let matches = |p: &PendingPlacement| {
+        win.node.pid.is_some_and(|pid| p.pid as i32 == pid)
+            || criteria::parse_criteria(&p.criteria).is_ok_and(|crit| {
+                criteria::criterion_to_predicate(&crit, &wins, fdata)(win)
+            })
+    };
+    let placement = {
+        let mut pending = lock_pending_placements();
+        match pending.iter().position(matches) {
+            Some(idx) => pending.remove(idx).placement,
+            None => return,
+        }
+    };
+    log::debug!("Placing new window {} like {:?}.", id, placement);
+    if let Err(err) = ipc::run_commands(&placement.commands(id)) {
+        log::error!("Could not place window {}: {}", id, err);
+    }
+}
+
+#[test]
+fn test_app_entry() {
+    let file = std::env::temp_dir()
+        .join(format!("swayr-test-{}.desktop", std::process::id()));
+    std::fs::write(
+        &file,
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=Htop\n\
+         Name[de]=Prozesse\n\
+         Exec=htop --tree %F\n\
+         Terminal=true\n\
+         \n\
+         [Desktop Action Foo]\n\
+         Exec=foo\n",
+    )
+    .unwrap();
+    let app = AppEntry::of_file(&file);
+    std::fs::remove_file(&file).unwrap();
+    let app = app.unwrap();
+    assert_eq!(app.name, "Htop");
+    assert!(!app.hidden);
+    assert_eq!(
+        app.command("alacritty -e"),
+        Ok(vec![
+            "alacritty".to_owned(),
+            "-e".to_owned(),
+            "htop".to_owned(),
+            "--tree".to_owned()
+        ])
+    );
+}
+
+#[test]
+fn test_sort_by_frecency() {
+    let app = |id: &str, name: &str| AppEntry {
+        id: id.to_owned(),
+        file: PathBuf::from(id),
+        name: name.to_owned(),
+        comment: String::new(),
+        icon: None,
+        exec: id.to_owned(),
+        terminal: false,
+        path: None,
+        hidden: false,
+    };
+    let now = 100 * WEEK;
+    let usages = HashMap::from([
+        // Used often but long ago.
+        (
+            "a".to_owned(),
+            Usage {
+                count: 10,
+                last_used: now - 8 * WEEK,
+            },
+        ),
+        // Used a few times recently.
+        (
+            "b".to_owned(),
+            Usage {
+                count: 3,
+                last_used: now - 10,
+            },
+        ),
+    ]);
+    let mut apps = vec![
+        app("c", "zed"),
+        app("d", "Alpha"),
+        app("a", "a"),
+        app("b", "b"),
+    ];
+    sort_by_frecency(&mut apps, &usages, now);
+    let ids: Vec<&str> = apps.iter().map(|a| a.id.as_str()).collect();
+    assert_eq!(ids, vec!["b", "a", "d", "c"]);
+}
@@ -13,17 +13,20 @@
 // You should have received a copy of the GNU General Public License along with
 // this program.  If not, see <https://www.gnu.org/licenses/>.
 
-//! Functions and data structures of the swayrd daemon.
+//! Functions for changing the layout of workspaces.
 
 use crate::config;
+use crate::dynamic_layout;
 use crate::shared::ipc;
 use crate::shared::ipc::NodeMethods;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use swayipc as s;
 
 pub fn auto_tile(res_to_min_width: &HashMap<i32, i32>) {
-    if let Ok(mut con) = s::Connection::new() {
-        if let Ok(tree) = con.get_tree() {
+    match ipc::get_tree(true) {
+        Ok(tree) => {
+            let mut cmds = vec![];
             for output in &tree.nodes {
                 log::debug!("output: {:?}", output.name);
 
@@ -40,10 +43,25 @@ pub fn auto_tile(res_to_min_width: &HashMap<i32, i32>) {
                 let min_window_width = &res_to_min_width.get(&output_width);
 
                 if let Some(min_window_width) = min_window_width {
-                    for container in output.iter().filter(|n| {
-                        let t = n.get_type();
-                        t == ipc::Type::Workspace || t == ipc::Type::Container
-                    }) {
+                    // Workspaces with a dynamic layout are arranged by it.
+                    let config = config::get_config();
+                    for container in output
+                        .nodes
+                        .iter()
+                        .filter(|ws| {
+                            !dynamic_layout::is_managed(
+                                &config,
+                                ws.get_name(),
+                                output.get_name(),
+                            )
+                        })
+                        .flat_map(|ws| ws.iter())
+                        .filter(|n| {
+                            let t = n.get_type();
+                            t == ipc::Type::Workspace
+                                || t == ipc::Type::Container
+                        })
+                    {
                         if container.is_scratchpad() {
                             log::debug!("  Skipping scratchpad");
                             continue;
@@ -90,18 +108,10 @@ pub fn auto_tile(res_to_min_width: &HashMap<i32, i32>) {
                                     estimated_width,
                                     min_window_width
                                 );
-                                match con.run_command(format!(
+                                cmds.push(format!(
                                     "[con_id={}] {}",
                                     child_win.id, split
-                                )) {
-                                    Ok(_) => (),
-                                    Err(e) => log::error!(
-                                        "Couldn't set {} on con {}: {:?}",
-                                        split,
-                                        child_win.id,
-                                        e
-                                    ),
-                                }
+                                ));
                             }
                         }
                     }
@@ -110,11 +120,13 @@ pub fn auto_tile(res_to_min_width: &HashMap<i32, i32>) {
                                setting for output_width {}", output_width);
                 }
             }
-        } else {
-            log::error!("Couldn't call get_tree during auto_tile.");
+            if let Err(err) = ipc::run_commands(&cmds) {
+                log::error!("Couldn't auto-tile: {}", err);
+            }
+        }
+        Err(err) => {
+            log::error!("Couldn't call get_tree during auto_tile: {}", err)
         }
-    } else {
-        log::error!("Couldn't get connection for auto_tile");
     }
 }
 
@@ -130,55 +142,191 @@ pub fn maybe_auto_tile(config: &config::Config) {
     }
 }
 
-const SWAYR_TMP_WORKSPACE: &str = "✨";
+pub const SWAYR_TMP_WORKSPACE: &str = "✨";
 
+/// Moves the windows of the current workspace to a temporary workspace and
+/// then runs the sway commands returned by `insert_win_fn` which should move
+/// them back in some layout.
 pub fn relayout_current_workspace<F>(
     include_floating: bool,
     insert_win_fn: F,
-) -> s::Fallible<()>
+) -> Result<(), String>
 where
-    F: Fn(&mut [&s::Node], &mut s::Connection) -> s::Fallible<()>,
+    F: Fn(&mut [&s::Node]) -> Vec<String>,
 {
-    let root = ipc::get_root_node(false);
+    let root = ipc::get_tree(false)
+        .map_err(|err| format!("Couldn't get tree: {}", err))?;
     let workspaces: Vec<&s::Node> = root
         .iter()
         .filter(|n| n.get_type() == ipc::Type::Workspace)
         .collect();
     if let Some(cur_ws) = workspaces.iter().find(|ws| ws.is_current()) {
-        if let Ok(mut con) = s::Connection::new() {
-            let mut moved_wins: Vec<&s::Node> = vec![];
-            let mut focused_win = None;
-            for win in
-                cur_ws.iter().filter(|n| n.get_type() == ipc::Type::Window)
-            {
-                if win.focused {
-                    focused_win = Some(win);
-                }
-                if !include_floating && win.is_floating() {
-                    continue;
-                }
-                moved_wins.push(win);
-                con.run_command(format!(
-                    "[con_id={}] move to workspace {}",
-                    win.id, SWAYR_TMP_WORKSPACE
-                ))?;
+        let mut moved_wins: Vec<&s::Node> = vec![];
+        let mut move_cmds = vec![];
+        let mut focused_win = None;
+        for win in cur_ws.iter().filter(|n| n.get_type() == ipc::Type::Window) {
+            if win.focused {
+                focused_win = Some(win);
+            }
+            if !include_floating && win.is_floating() {
+                continue;
             }
+            moved_wins.push(win);
+            move_cmds.push(format!(
+                "[con_id={}] move to workspace {}",
+                win.id, SWAYR_TMP_WORKSPACE
+            ));
+        }
+        ipc::run_commands(&move_cmds)?;
+
+        let mut insert_cmds = insert_win_fn(moved_wins.as_mut_slice());
+        if let Some(win) = focused_win {
+            insert_cmds.push(format!("[con_id={}] focus", win.id));
+        }
+        ipc::run_commands(&insert_cmds)
+    } else {
+        Err("No workspace is focused.".to_string())
+    }
+}
+
+/// The mark used for placing windows next to each other.
+const LAYOUT_MARK: &str = "_swayr_layout";
+
+/// The layout of a container in an [`Arrangement`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContainerLayout {
+    SplitH,
+    SplitV,
+    Tabbed,
+    Stacking,
+}
 
-            insert_win_fn(moved_wins.as_mut_slice(), &mut con)?;
-            std::thread::sleep(std::time::Duration::from_millis(25));
+impl ContainerLayout {
+    pub fn of_node(node: &s::Node) -> Option<ContainerLayout> {
+        match node.layout {
+            s::NodeLayout::SplitH => Some(ContainerLayout::SplitH),
+            s::NodeLayout::SplitV => Some(ContainerLayout::SplitV),
+            s::NodeLayout::Tabbed => Some(ContainerLayout::Tabbed),
+            s::NodeLayout::Stacked => Some(ContainerLayout::Stacking),
+            _ => None,
+        }
+    }
 
-            if let Some(win) = focused_win {
-                con.run_command(format!("[con_id={}] focus", win.id))?;
+    fn as_str(self) -> &'static str {
+        match self {
+            ContainerLayout::SplitH => "splith",
+            ContainerLayout::SplitV => "splitv",
+            ContainerLayout::Tabbed => "tabbed",
+            ContainerLayout::Stacking => "stacking",
+        }
+    }
+}
+
+/// A tree of containers with windows as leaves.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Arrangement {
+    Window(i64),
+    Container(ContainerLayout, Vec<Arrangement>),
+}
+
+/// A container of the given children, or the only child itself.  Sway
+/// doesn't create containers with only one child when splitting, so neither
+/// does swayr.
+pub fn container(
+    layout: ContainerLayout,
+    mut children: Vec<Arrangement>,
+) -> Option<Arrangement> {
+    match children.len() {
+        0 => None,
+        1 => children.pop(),
+        _ => Some(Arrangement::Container(layout, children)),
+    }
+}
+
+impl Arrangement {
+    pub fn first_window(&self) -> i64 {
+        match self {
+            Arrangement::Window(id) => *id,
+            Arrangement::Container(_, children) => children[0].first_window(),
+        }
+    }
+
+    pub fn window_ids(&self) -> Vec<i64> {
+        match self {
+            Arrangement::Window(id) => vec![*id],
+            Arrangement::Container(_, children) => {
+                children.iter().flat_map(|c| c.window_ids()).collect()
             }
-            Ok(())
-        } else {
-            Err(s::Error::CommandFailed(
-                "Cannot create connection.".to_string(),
-            ))
         }
-    } else {
-        Err(s::Error::CommandFailed(
-            "No workspace is focused.".to_string(),
-        ))
     }
+
+    /// The number of containers from this one down to its first window.
+    pub fn depth(&self) -> usize {
+        match self {
+            Arrangement::Window(_) => 0,
+            Arrangement::Container(_, children) => 1 + children[0].depth(),
+        }
+    }
+
+    /// Pushes the commands building this arrangement given that its first
+    /// window is already in place and the others are not.  The first
+    /// windows of all children are placed before the children's contents so
+    /// that splitting always wraps a window into a new container except at
+    /// the top where it's the only child of the workspace whose layout is
+    /// set.
+    fn push_commands(&self, top: bool, cmds: &mut Vec<String>) {
+        if let Arrangement::Container(layout, children) = self {
+            let mut prev = self.first_window();
+            if top {
+                cmds.push(format!(
+                    "[con_id={}] layout {}",
+                    prev,
+                    layout.as_str()
+                ));
+            } else {
+                match layout {
+                    ContainerLayout::SplitH | ContainerLayout::SplitV => cmds
+                        .push(format!("[con_id={}] {}", prev, layout.as_str())),
+                    _ => {
+                        cmds.push(format!("[con_id={}] splitv", prev));
+                        cmds.push(format!(
+                            "[con_id={}] layout {}",
+                            prev,
+                            layout.as_str()
+                        ));
+                    }
+                }
+            }
+            for child in &children[1..] {
+                let id = child.first_window();
+                cmds.push(format!(
+                    "[con_id={}] mark --add {}",
+                    prev, LAYOUT_MARK
+                ));
+                cmds.push(format!(
+                    "[con_id={}] move to mark {}",
+                    id, LAYOUT_MARK
+                ));
+                prev = id;
+            }
+            for child in children {
+                child.push_commands(false, cmds);
+            }
+        }
+    }
+}
+
+/// The sway commands building the arrangement on the given workspace.  Its
+/// windows must have been moved to [`SWAYR_TMP_WORKSPACE`] before, and the
+/// workspace must not contain other tiled windows.
+pub fn build_commands(ws_name: &str, arrangement: &Arrangement) -> Vec<String> {
+    let mut cmds = vec![format!(
+        "[con_id={}] move to workspace {}",
+        arrangement.first_window(),
+        ipc::quote(ws_name)
+    )];
+    arrangement.push_commands(true, &mut cmds);
+    cmds.push(format!("unmark {}", LAYOUT_MARK));
+    cmds
 }
@@ -23,8 +23,19 @@ pub mod cmds;
 pub mod config;
 pub mod criteria;
 pub mod daemon;
+pub mod dynamic_layout;
+pub mod events;
 pub mod focus;
+pub mod launcher;
 pub mod layout;
+pub mod persist;
+pub mod protocol;
+pub mod query;
+pub mod rules;
+pub mod saved_layout;
+pub mod scratchpad;
+pub mod session;
 pub mod shared;
+pub mod slots;
 pub mod tree;
 pub mod util;
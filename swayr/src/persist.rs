@@ -0,0 +1,333 @@
+// Copyright (C) 2022  Tassilo Horn <tsdh@gnu.org>
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Persistence of swayrd state across restarts in `$XDG_STATE_HOME/swayr/`.
+
+use crate::focus::FocusData;
+use crate::shared::ipc;
+use crate::shared::ipc::NodeMethods;
+use directories::ProjectDirs;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use swayipc as s;
+
+const FOCUS_HISTORY_FILE: &str = "focus-history.json";
+
+pub fn get_state_file_path(file_name: &str) -> Option<PathBuf> {
+    ProjectDirs::from("", "", "swayr")
+        .and_then(|pd| pd.state_dir().map(|d| d.join(file_name)))
+}
+
+/// Loads the state from the given file, or returns `None` if there is no such
+/// file or it can't be read.
+pub fn load_state<T: DeserializeOwned>(file_name: &str) -> Option<T> {
+    try_load_state(file_name).unwrap_or_else(|err| {
+        log::error!("{}", err);
+        None
+    })
+}
+
+/// Like [`load_state`] but returns an error if the file can't be read.
+pub fn try_load_state<T: DeserializeOwned>(
+    file_name: &str,
+) -> Result<Option<T>, String> {
+    let path = get_state_file_path(file_name)
+        .ok_or_else(|| "Could not determine the state directory.".to_owned())?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    std::fs::read_to_string(&path)
+        .map_err(|err| err.to_string())
+        .and_then(|s| serde_json::from_str(&s).map_err(|err| err.to_string()))
+        .map(Some)
+        .map_err(|err| {
+            format!("Could not load state from {}: {}", path.display(), err)
+        })
+}
+
+/// Saves the state to the given file.  The state is written to a temporary
+/// file first which is then renamed so that a crash can never leave a
+/// truncated state file behind.
+pub fn save_state<T: Serialize>(file_name: &str, state: &T) {
+    if let Err(err) = try_save_state(file_name, state) {
+        log::error!("{}", err);
+    }
+}
+
+/// Like [`save_state`] but returns an error if the file can't be written.
+pub fn try_save_state<T: Serialize>(
+    file_name: &str,
+    state: &T,
+) -> Result<(), String> {
+    let path = get_state_file_path(file_name)
+        .ok_or_else(|| "Could not determine the state directory.".to_owned())?;
+    path.parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| {
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, serde_json::to_vec(state)?)?;
+            std::fs::rename(&tmp, &path)
+        })
+        .map_err(|err| {
+            format!("Could not save state to {}: {}", path.display(), err)
+        })
+}
+
+/// Identifies a window or workspace independently of its con_id which changes
+/// when sway restarts or an application is restarted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Fingerprint {
+    Window {
+        app_name: String,
+        pid: Option<i32>,
+        title: String,
+    },
+    Workspace {
+        name: String,
+    },
+}
+
+impl Fingerprint {
+    pub fn of_node(node: &s::Node) -> Option<Fingerprint> {
+        match node.get_type() {
+            ipc::Type::Window => Some(Fingerprint::Window {
+                app_name: node.get_app_name().to_owned(),
+                pid: node.pid,
+                title: node.get_name().to_owned(),
+            }),
+            ipc::Type::Workspace => Some(Fingerprint::Workspace {
+                name: node.get_name().to_owned(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// How well `self` matches `other`.  Zero means no match at all.
+    /// Windows only match if their app names are equal; the same pid weighs
+    /// more than the same title.
+    pub fn similarity(&self, other: &Fingerprint) -> u32 {
+        match (self, other) {
+            (
+                Fingerprint::Window {
+                    app_name: a1,
+                    pid: p1,
+                    title: t1,
+                },
+                Fingerprint::Window {
+                    app_name: a2,
+                    pid: p2,
+                    title: t2,
+                },
+            ) if a1 == a2 => {
+                1 + if p1.is_some() && p1 == p2 { 2 } else { 0 }
+                    + if t1 == t2 { 1 } else { 0 }
+            }
+            (
+                Fingerprint::Workspace { name: n1 },
+                Fingerprint::Workspace { name: n2 },
+            ) if n1 == n2 => 4,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FocusHistoryEntry {
+    pub id: i64,
+    pub tick: u64,
+    pub fingerprint: Fingerprint,
+}
+
+/// Collects the focus ticks of all live windows and workspaces.
+pub fn snapshot_focus_history(
+    root: &s::Node,
+    fdata: &FocusData,
+) -> Vec<FocusHistoryEntry> {
+    let mut entries: Vec<FocusHistoryEntry> = root
+        .iter()
+        .filter_map(|n| {
+            let tick = fdata.last_focus_tick(n.id);
+            if tick == 0 {
+                return None;
+            }
+            Fingerprint::of_node(n).map(|fingerprint| FocusHistoryEntry {
+                id: n.id,
+                tick,
+                fingerprint,
+            })
+        })
+        .collect();
+    entries.sort_by_key(|e| std::cmp::Reverse(e.tick));
+    entries
+}
+
+/// Associates the entries of a saved focus history with live nodes given as
+/// `(con_id, fingerprint)` pairs.  An entry is associated with the live node
+/// having the same con_id if the fingerprints are similar.  Otherwise, it is
+/// associated with the most similar live node not associated yet where more
+/// recently focused entries pick first.  Returns `(con_id, tick)` pairs.
+pub fn reassociate(
+    entries: &[FocusHistoryEntry],
+    live: &[(i64, Fingerprint)],
+) -> Vec<(i64, u64)> {
+    let entries: Vec<(i64, &Fingerprint, u64)> = entries
+        .iter()
+        .map(|e| (e.id, &e.fingerprint, e.tick))
+        .collect();
+    reassociate_by_value(&entries, live)
+}
+
+/// Like [`reassociate`] but for arbitrary `(con_id, fingerprint, value)`
+/// entries where entries with greater values pick first.  Returns `(con_id,
+/// value)` pairs.
+pub fn reassociate_by_value<V: Copy + Ord>(
+    entries: &[(i64, &Fingerprint, V)],
+    live: &[(i64, Fingerprint)],
+) -> Vec<(i64, V)> {
+    let mut result = vec![];
+    let mut taken: HashSet<i64> = HashSet::new();
+    let mut unmatched = vec![];
+
+    for (e_id, e_fp, e_val) in entries {
+        match live.iter().find(|(id, _)| id == e_id) {
+            Some((id, fp)) if fp.similarity(e_fp) > 0 => {
+                taken.insert(*id);
+                result.push((*id, *e_val));
+            }
+            _ => unmatched.push((e_fp, *e_val)),
+        }
+    }
+
+    unmatched.sort_by_key(|(_, val)| std::cmp::Reverse(*val));
+    for (e_fp, e_val) in unmatched {
+        if let Some((id, _)) = live
+            .iter()
+            .filter(|(id, _)| !taken.contains(id))
+            .map(|(id, fp)| (id, fp.similarity(e_fp)))
+            .filter(|(_, score)| *score > 0)
+            // max_by_key returns the last maximum, so reverse to prefer the
+            // first live node in tree order.
+            .rev()
+            .max_by_key(|(_, score)| *score)
+        {
+            taken.insert(*id);
+            result.push((*id, e_val));
+        }
+    }
+
+    result
+}
+
+/// The `(con_id, fingerprint)` pairs of all windows and workspaces except the
+/// ones in the scratchpad.
+pub fn live_fingerprints(root: &s::Node) -> Vec<(i64, Fingerprint)> {
+    root.iter()
+        .filter(|n| !n.is_scratchpad())
+        .filter_map(|n| Fingerprint::of_node(n).map(|fp| (n.id, fp)))
+        .collect()
+}
+
+pub fn save_focus_history(fdata: &FocusData) {
+    match ipc::get_tree(true) {
+        Ok(root) => {
+            let entries = snapshot_focus_history(&root, fdata);
+            log::debug!("Saving focus history with {} entries.", entries.len());
+            save_state(FOCUS_HISTORY_FILE, &entries);
+        }
+        Err(err) => {
+            log::warn!("Could not get tree for saving focus history: {}", err)
+        }
+    }
+}
+
+/// Restores the focus history saved by a previous swayrd instance.
+pub fn restore_focus_history(fdata: &FocusData) {
+    let entries: Vec<FocusHistoryEntry> = match load_state(FOCUS_HISTORY_FILE) {
+        Some(entries) => entries,
+        None => return,
+    };
+
+    let root = match ipc::get_tree(true) {
+        Ok(root) => root,
+        Err(err) => {
+            log::warn!(
+                "Could not get tree for restoring focus history: {}",
+                err
+            );
+            return;
+        }
+    };
+    let live = live_fingerprints(&root);
+    let restored = reassociate(&entries, &live);
+    log::debug!(
+        "Restored {} of {} focus history entries.",
+        restored.len(),
+        entries.len()
+    );
+    for (id, tick) in restored {
+        fdata.ensure_id(id);
+        fdata.update_last_focus_tick(id, tick);
+    }
+}
+
+#[test]
+fn test_reassociate() {
+    let win = |app: &str, pid: i32, title: &str| Fingerprint::Window {
+        app_name: app.to_owned(),
+        pid: Some(pid),
+        title: title.to_owned(),
+    };
+    let entry =
+        |id: i64, tick: u64, fingerprint: Fingerprint| FocusHistoryEntry {
+            id,
+            tick,
+            fingerprint,
+        };
+
+    let entries = vec![
+        entry(1, 30, win("foot", 100, "~")),
+        entry(2, 20, win("firefox", 200, "News")),
+        entry(3, 10, win("foot", 101, "vim")),
+        entry(4, 5, Fingerprint::Workspace { name: "1".into() }),
+        entry(5, 3, win("emacs", 300, "*scratch*")),
+    ];
+
+    // swayrd restart: everything keeps its con_id except that con_id 5 has
+    // been reused by some other application.
+    let live = vec![
+        (1, win("foot", 100, "~/src")),
+        (2, win("firefox", 200, "News")),
+        (3, win("foot", 101, "vim")),
+        (4, Fingerprint::Workspace { name: "1".into() }),
+        (5, win("mpv", 400, "video")),
+    ];
+    let mut r = reassociate(&entries, &live);
+    r.sort();
+    assert_eq!(r, vec![(1, 30), (2, 20), (3, 10), (4, 5)]);
+
+    // sway restart: new con_ids and pids, so titles decide between the two
+    // foot windows.
+    let live = vec![
+        (11, win("foot", 501, "vim")),
+        (12, win("foot", 502, "~")),
+        (13, win("firefox", 503, "Other")),
+        (14, Fingerprint::Workspace { name: "1".into() }),
+    ];
+    let mut r = reassociate(&entries, &live);
+    r.sort();
+    assert_eq!(r, vec![(11, 10), (12, 30), (13, 20), (14, 5)]);
+}
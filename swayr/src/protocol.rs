@@ -0,0 +1,176 @@
+// Copyright (C) 2022  Tassilo Horn <tsdh@gnu.org>
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The request/response protocol spoken between swayr and swayrd.
+//!
+//! Every message is a frame consisting of the length of the JSON payload as
+//! 4-byte big-endian unsigned integer followed by the JSON payload itself.
+//! The client sends exactly one [`Request`] frame and the daemon answers with
+//! exactly one [`Response`] frame.
+
+use crate::cmds;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+
+/// The version of the protocol.  It must be bumped whenever [`Request`] or
+/// [`Response`] change incompatibly.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Frames larger than that are rejected.
+const MAX_FRAME_LEN: u32 = 16 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Request {
+    pub version: u32,
+    pub cmd: cmds::SwayrCommand,
+}
+
+impl Request {
+    pub fn new(cmd: cmds::SwayrCommand) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            cmd,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Response {
+    pub version: u32,
+    pub success: bool,
+    /// The error message if `success` is false.
+    pub error: Option<String>,
+    /// Structured details about the error, e.g., for invalid criteria
+    /// queries.
+    #[serde(default)]
+    pub error_details: Option<serde_json::Value>,
+    /// The con_ids of the windows, containers, or workspaces affected by the
+    /// command.
+    pub con_ids: Vec<i64>,
+    /// The result of query commands.
+    pub data: Option<serde_json::Value>,
+}
+
+impl Response {
+    pub fn from_result(result: cmds::CmdResult) -> Response {
+        match result {
+            Ok(output) => Response {
+                version: PROTOCOL_VERSION,
+                success: true,
+                error: None,
+                error_details: None,
+                con_ids: output.con_ids,
+                data: output.data,
+            },
+            Err(err) => Response {
+                error_details: err.details,
+                ..Response::error(err.message)
+            },
+        }
+    }
+
+    pub fn error(msg: String) -> Response {
+        Response {
+            version: PROTOCOL_VERSION,
+            success: false,
+            error: Some(msg),
+            error_details: None,
+            con_ids: vec![],
+            data: None,
+        }
+    }
+}
+
+pub fn write_frame<W, T>(w: &mut W, msg: &T) -> io::Result<()>
+where
+    W: Write,
+    T: Serialize,
+{
+    let payload = serde_json::to_vec(msg)?;
+    let len = u32::try_from(payload.len())
+        .ok()
+        .filter(|len| *len <= MAX_FRAME_LEN)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Frame too large ({} bytes).", payload.len()),
+            )
+        })?;
+    w.write_all(&len.to_be_bytes())?;
+    w.write_all(&payload)?;
+    w.flush()
+}
+
+pub fn read_frame<R: Read>(r: &mut R) -> io::Result<Vec<u8>> {
+    let mut len_buf = [0u8; 4];
+    r.read_exact(&mut len_buf)?;
+    let len = u32::from_be_bytes(len_buf);
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Frame too large ({} bytes).", len),
+        ));
+    }
+    let mut payload = vec![0u8; len as usize];
+    r.read_exact(&mut payload)?;
+    Ok(payload)
+}
+
+/// Decodes a request frame checking that the client speaks our protocol
+/// version before trying to interpret the command.
+pub fn decode_request(frame: &[u8]) -> Result<Request, String> {
+    let value: serde_json::Value = serde_json::from_slice(frame)
+        .map_err(|err| format!("Malformed request: {}", err))?;
+    match value.get("version").and_then(|v| v.as_u64()) {
+        Some(v) if v == PROTOCOL_VERSION as u64 => {
+            serde_json::from_value(value)
+                .map_err(|err| format!("Malformed request: {}", err))
+        }
+        Some(v) => Err(format!(
+            "Protocol version mismatch: swayr speaks version {} \
+             but swayrd speaks version {}.",
+            v, PROTOCOL_VERSION
+        )),
+        None => Err("Request has no protocol version.".to_string()),
+    }
+}
+
+#[test]
+fn test_frame_roundtrip() {
+    let mut buf: Vec<u8> = vec![];
+    write_frame(&mut buf, &Request::new(cmds::SwayrCommand::Nop)).unwrap();
+    let frame = read_frame(&mut buf.as_slice()).unwrap();
+    let req = decode_request(&frame).unwrap();
+    assert_eq!(req.version, PROTOCOL_VERSION);
+    assert_eq!(req.cmd, cmds::SwayrCommand::Nop);
+
+    let mut buf: Vec<u8> = vec![];
+    let resp = Response::from_result(Err("No window to switch to.".into()));
+    write_frame(&mut buf, &resp).unwrap();
+    let frame = read_frame(&mut buf.as_slice()).unwrap();
+    assert_eq!(serde_json::from_slice::<Response>(&frame).unwrap(), resp);
+}
+
+#[test]
+fn test_decode_request_errors() {
+    let frame = br#"{"version": 0, "cmd": "Nop"}"#;
+    assert!(decode_request(frame).unwrap_err().contains("mismatch"));
+    assert!(decode_request(br#""Nop""#).is_err());
+
+    // A raw, unframed command as sent by old clients must not make us
+    // allocate huge buffers.
+    let old_style = br#"{"SwitchWindow":null}"#;
+    assert!(read_frame(&mut old_style.as_slice()).is_err());
+}
@@ -0,0 +1,231 @@
+// Copyright (C) 2022  Tassilo Horn <tsdh@gnu.org>
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Query commands exposing swayrd's view of the tree as JSON.
+
+use crate::cmds::{CmdError, CmdOutput, CmdResult, SwayrCommand};
+use crate::criteria;
+use crate::daemon;
+use crate::focus::FocusData;
+use crate::shared::cfg;
+use crate::shared::ipc;
+use crate::shared::ipc::NodeMethods;
+use crate::tree as t;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WindowInfo {
+    pub id: i64,
+    pub app_name: String,
+    pub title: String,
+    pub workspace: Option<String>,
+    pub output: Option<String>,
+    pub marks: Vec<String>,
+    pub urgent: bool,
+    pub focused: bool,
+    pub floating: bool,
+    pub last_focus_tick: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkspaceInfo {
+    pub id: i64,
+    pub name: String,
+    pub output: Option<String>,
+    pub urgent: bool,
+    pub focused: bool,
+    pub last_focus_tick: u64,
+    pub windows: Vec<WindowInfo>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LruEntry {
+    pub id: i64,
+    pub last_focus_tick: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Status {
+    pub uptime_secs: u64,
+    pub config_file: String,
+    pub tracked_nodes: usize,
+    /// All tracked windows and workspaces, the most recently focused first.
+    pub lru: Vec<LruEntry>,
+    #[serde(flatten)]
+    pub daemon: daemon::DaemonState,
+    pub health: daemon::Health,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CriteriaCheck {
+    /// The parsed criteria query.
+    pub criteria: serde_json::Value,
+    /// The windows matching the query in the order of `get-windows`.
+    pub windows: Vec<WindowInfo>,
+}
+
+fn parent_name(dn: &t::DisplayNode, typ: ipc::Type) -> Option<String> {
+    dn.tree
+        .get_parent_node_of_type(dn.node.id, typ)
+        .map(|n| n.get_name().to_owned())
+}
+
+pub fn window_info(dn: &t::DisplayNode, fdata: &FocusData) -> WindowInfo {
+    WindowInfo {
+        id: dn.node.id,
+        app_name: dn.node.get_app_name().to_owned(),
+        title: dn.node.get_name().to_owned(),
+        workspace: parent_name(dn, ipc::Type::Workspace),
+        output: parent_name(dn, ipc::Type::Output),
+        marks: dn.node.marks.clone(),
+        urgent: dn.node.urgent,
+        focused: dn.node.focused,
+        floating: dn.node.is_floating(),
+        last_focus_tick: fdata.last_focus_tick(dn.node.id),
+    }
+}
+
+fn workspace_info(dn: &t::DisplayNode, fdata: &FocusData) -> WorkspaceInfo {
+    WorkspaceInfo {
+        id: dn.node.id,
+        name: dn.node.get_name().to_owned(),
+        output: parent_name(dn, ipc::Type::Output),
+        urgent: dn.node.urgent,
+        focused: dn.node.is_current(),
+        last_focus_tick: fdata.last_focus_tick(dn.node.id),
+        windows: vec![],
+    }
+}
+
+/// The windows in the order of the `switch-window` menu, i.e., urgent windows
+/// first, then in LRU order, and the focused window last.
+pub fn get_windows(fdata: &FocusData) -> Result<Vec<WindowInfo>, String> {
+    let root = ipc::get_root_node(true)?;
+    let tree = t::get_tree(&root);
+    Ok(tree
+        .get_windows(fdata)
+        .iter()
+        .map(|dn| window_info(dn, fdata))
+        .collect())
+}
+
+/// The workspaces and their windows in the order of the
+/// `switch-workspace-or-window` menu.
+pub fn get_workspaces(fdata: &FocusData) -> Result<Vec<WorkspaceInfo>, String> {
+    let root = ipc::get_root_node(true)?;
+    let tree = t::get_tree(&root);
+    let mut workspaces: Vec<WorkspaceInfo> = vec![];
+    for dn in tree.get_workspaces_and_windows(fdata) {
+        match dn.node.get_type() {
+            ipc::Type::Workspace => workspaces.push(workspace_info(&dn, fdata)),
+            ipc::Type::Window => {
+                if let Some(ws) = workspaces.last_mut() {
+                    ws.windows.push(window_info(&dn, fdata))
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(workspaces)
+}
+
+/// The windows in plain LRU order, i.e., the most recently focused window
+/// first regardless of urgency.
+pub fn get_lru(fdata: &FocusData) -> Result<Vec<WindowInfo>, String> {
+    let mut wins = get_windows(fdata)?;
+    // Windows which have never been focused so far all have tick 0, so use
+    // the id as a tie-breaker to get a stable order.
+    wins.sort_by(|a, b| {
+        b.last_focus_tick
+            .cmp(&a.last_focus_tick)
+            .then_with(|| b.id.cmp(&a.id))
+    });
+    Ok(wins)
+}
+
+/// Swayrd's internal state.  In contrast to the other queries, it doesn't
+/// need sway so that it also works when the connection is broken.
+pub fn get_status(fdata: &FocusData) -> Status {
+    let mut lru: Vec<LruEntry> = fdata
+        .focus_tick_by_id
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(id, tick)| LruEntry {
+            id: *id,
+            last_focus_tick: *tick,
+        })
+        .collect();
+    lru.sort_by(|a, b| {
+        b.last_focus_tick
+            .cmp(&a.last_focus_tick)
+            .then_with(|| b.id.cmp(&a.id))
+    });
+    Status {
+        uptime_secs: daemon::uptime().as_secs(),
+        config_file: cfg::get_config_file_path("swayr")
+            .to_string_lossy()
+            .into_owned(),
+        tracked_nodes: lru.len(),
+        lru,
+        daemon: daemon::get_daemon_state(),
+        health: daemon::get_health(),
+    }
+}
+
+/// The parsed criteria query and the windows it matches right now.
+pub fn check_criteria(
+    criteria: &str,
+    fdata: &FocusData,
+) -> Result<CriteriaCheck, CmdError> {
+    let crit = criteria::parse_criteria(criteria)?;
+    let root = ipc::get_root_node(true)?;
+    let tree = t::get_tree(&root);
+    let wins = tree.get_windows(fdata);
+    let pred = criteria::criterion_to_predicate(&crit, &wins, fdata);
+    Ok(CriteriaCheck {
+        criteria: serde_json::to_value(&crit).map_err(|err| err.to_string())?,
+        windows: wins
+            .iter()
+            .filter(|w| pred(w))
+            .map(|w| window_info(w, fdata))
+            .collect(),
+    })
+}
+
+fn to_output<T: Serialize>(val: T) -> CmdResult {
+    match serde_json::to_value(val) {
+        Ok(data) => Ok(CmdOutput {
+            con_ids: vec![],
+            data: Some(data),
+        }),
+        Err(err) => {
+            Err(format!("Could not serialize query result: {}", err).into())
+        }
+    }
+}
+
+pub fn exec_query(cmd: &SwayrCommand, fdata: &FocusData) -> CmdResult {
+    match cmd {
+        SwayrCommand::GetWindows => to_output(get_windows(fdata)?),
+        SwayrCommand::GetWorkspaces => to_output(get_workspaces(fdata)?),
+        SwayrCommand::GetLru => to_output(get_lru(fdata)?),
+        SwayrCommand::Status => to_output(get_status(fdata)),
+        SwayrCommand::CheckCriteria { criteria } => {
+            to_output(check_criteria(criteria, fdata)?)
+        }
+        _ => Err(format!("{:?} is no query.", cmd).into()),
+    }
+}
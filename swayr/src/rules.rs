@@ -0,0 +1,222 @@
+// Copyright (C) 2022  Tassilo Horn <tsdh@gnu.org>
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Window rules, i.e., sway's `for_window` with swayr's criteria.
+
+use crate::cmds::{self, SwayrCommand};
+use crate::config;
+use crate::criteria;
+use crate::focus::FocusData;
+use crate::shared::ipc;
+use crate::tree as t;
+use clap::Parser;
+use std::collections::HashMap;
+
+/// The window events rules are applied on.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Trigger {
+    New,
+    TitleChange,
+}
+
+#[derive(clap::Parser)]
+#[clap(no_binary_name = true)]
+struct RuleAction {
+    #[clap(subcommand)]
+    cmd: SwayrCommand,
+}
+
+/// Splits `s` at whitespace.  Single or double quotes group words
+/// containing whitespace like in a shell.
+fn split_args(s: &str) -> Result<Vec<String>, String> {
+    let mut args = vec![];
+    let mut arg: Option<String> = None;
+    let mut quote: Option<char> = None;
+    for c in s.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => arg.get_or_insert_with(String::new).push(c),
+            None if c == '\'' || c == '"' => {
+                quote = Some(c);
+                arg.get_or_insert_with(String::new);
+            }
+            None if c.is_whitespace() => args.extend(arg.take()),
+            None => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(format!("Unbalanced quotes in '{}'.", s));
+    }
+    args.extend(arg);
+    Ok(args)
+}
+
+fn parse_swayr_action(args: &str) -> Result<SwayrCommand, String> {
+    let cmd = RuleAction::try_parse_from(split_args(args)?)
+        .map(|a| a.cmd)
+        .map_err(|err| format!("Invalid swayr action '{}': {}", args, err))?;
+    if !cmds::can_target_window(&cmd) {
+        return Err(format!(
+            "Invalid swayr action '{}': it can't act on the matched window.",
+            args
+        ));
+    }
+    Ok(cmd)
+}
+
+/// An action of a rule.
+#[derive(Debug)]
+enum Action {
+    Sway(String),
+    Swayr(SwayrCommand),
+}
+
+impl Action {
+    fn parse(action: &str) -> Result<Action, String> {
+        match action.strip_prefix("swayr ") {
+            Some(args) => parse_swayr_action(args).map(Action::Swayr),
+            None if action.contains(';') => Err(format!(
+                "Invalid sway action '{}': {}",
+                action,
+                cmds::SEMICOLON_IN_COMMAND
+            )),
+            None => Ok(Action::Sway(action.to_owned())),
+        }
+    }
+
+    fn run(&self, id: i64) -> Result<(), String> {
+        match self {
+            Action::Sway(cmd) => {
+                ipc::run_command(&format!("[con_id={}] {}", id, cmd))
+            }
+            Action::Swayr(cmd) => cmds::exec_swayr_cmd_for_window(cmd, id)
+                .map(|_| ())
+                .map_err(|err| err.message),
+        }
+    }
+}
+
+/// A rule whose criteria and actions have been parsed when the config was
+/// loaded.
+#[derive(Debug)]
+pub struct CompiledRule {
+    rule: config::Rule,
+    criterion: criteria::Criterion,
+    actions: Vec<Action>,
+}
+
+impl CompiledRule {
+    fn applies_on(&self, trigger: Trigger) -> bool {
+        trigger == Trigger::New || self.rule.on_title_change.unwrap_or(false)
+    }
+}
+
+/// Parses the criteria and actions of the rules where the criteria may
+/// reference the given named criteria queries.
+pub fn compile_rules(
+    rules: &[config::Rule],
+    named: &HashMap<String, String>,
+) -> Result<Vec<CompiledRule>, String> {
+    rules
+        .iter()
+        .map(|rule| {
+            let criterion =
+                criteria::parse_criteria_with(&rule.criteria, named)
+                    .map_err(|err| format!("Invalid rule: {}", err))?;
+            let actions = rule
+                .actions
+                .iter()
+                .map(|a| Action::parse(a))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(CompiledRule {
+                rule: rule.clone(),
+                criterion,
+                actions,
+            })
+        })
+        .collect()
+}
+
+/// Whether any rule of the current config applies on `trigger`.
+pub fn has_rules(config: &config::Config, trigger: Trigger) -> bool {
+    config
+        .get_compiled_rules()
+        .iter()
+        .any(|r| r.applies_on(trigger))
+}
+
+/// Runs the actions of all rules whose criteria match the window with the
+/// given id.  The criteria are evaluated against the tree at the time of the
+/// event, so actions of one rule don't affect the matching of later ones.
+pub fn apply_rules(id: i64, trigger: Trigger, fdata: &FocusData) {
+    let config = config::get_config();
+    let rules: Vec<&CompiledRule> = config
+        .get_compiled_rules()
+        .iter()
+        .filter(|r| r.applies_on(trigger))
+        .collect();
+    if rules.is_empty() {
+        return;
+    }
+
+    let root = match ipc::get_tree(false) {
+        Ok(root) => root,
+        Err(err) => {
+            log::warn!("Could not get tree for applying rules: {}", err);
+            return;
+        }
+    };
+    let tree = t::get_tree(&root);
+    let wins = tree.get_windows(fdata);
+    for rule in rules {
+        let pred =
+            criteria::criterion_to_predicate(&rule.criterion, &wins, fdata);
+        if !wins.iter().any(|w| w.node.id == id && pred(w)) {
+            continue;
+        }
+        log::debug!("Window {} matches rule {}.", id, rule.rule.criteria);
+        for (action, text) in rule.actions.iter().zip(&rule.rule.actions) {
+            if let Err(err) = action.run(id) {
+                log::error!("Could not run rule action '{}': {}", text, err);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_split_args() {
+    assert_eq!(
+        split_args(" switch-to-matching-window  '[app_name=\"foo bar\"]'"),
+        Ok(vec![
+            "switch-to-matching-window".to_owned(),
+            "[app_name=\"foo bar\"]".to_owned()
+        ])
+    );
+    assert_eq!(
+        split_args("a \"\" b"),
+        Ok(vec!["a", "", "b"].into_iter().map(String::from).collect())
+    );
+    assert!(split_args("a 'b").is_err());
+    assert!(parse_swayr_action("assign-slot 3").is_ok());
+    assert!(parse_swayr_action("no-such-command").is_err());
+    // Actions must be able to act on the matched window.
+    assert!(parse_swayr_action("switch-window").is_err());
+    assert!(Action::parse("floating enable, resize set 800 600").is_ok());
+    assert_eq!(
+        Action::parse("floating enable; resize set 800 600").unwrap_err(),
+        "Invalid sway action 'floating enable; resize set 800 600': The \
+         command must not contain ';'."
+    );
+}
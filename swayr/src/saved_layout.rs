@@ -0,0 +1,326 @@
+// Copyright (C) 2022  Tassilo Horn <tsdh@gnu.org>
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Saving the container tree of a workspace to a file and restoring it.
+
+use crate::cmds::CmdResult;
+use crate::layout::{self, Arrangement, ContainerLayout, SWAYR_TMP_WORKSPACE};
+use crate::persist::{self, Fingerprint};
+use crate::shared::ipc;
+use crate::shared::ipc::NodeMethods;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use swayipc as s;
+
+/// A container or window of a saved layout.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SavedNode {
+    Container {
+        layout: ContainerLayout,
+        /// The fraction of the parent's width or height.
+        percent: Option<f64>,
+        nodes: Vec<SavedNode>,
+    },
+    Window {
+        id: i64,
+        percent: Option<f64>,
+        fingerprint: Fingerprint,
+    },
+}
+
+impl SavedNode {
+    /// The saved tiled windows and containers of `node` or `None` if there
+    /// are no tiled windows.
+    fn of_node(node: &s::Node) -> Option<SavedNode> {
+        if node.get_type() == ipc::Type::Window {
+            return Fingerprint::of_node(node).map(|fingerprint| {
+                SavedNode::Window {
+                    id: node.id,
+                    percent: node.percent,
+                    fingerprint,
+                }
+            });
+        }
+        let nodes: Vec<SavedNode> =
+            node.nodes.iter().filter_map(SavedNode::of_node).collect();
+        if nodes.is_empty() {
+            None
+        } else {
+            Some(SavedNode::Container {
+                layout: ContainerLayout::of_node(node)
+                    .unwrap_or(ContainerLayout::SplitH),
+                percent: node.percent,
+                nodes,
+            })
+        }
+    }
+
+    fn percent(&self) -> Option<f64> {
+        match self {
+            SavedNode::Container { percent, .. }
+            | SavedNode::Window { percent, .. } => *percent,
+        }
+    }
+
+    /// The saved windows in tree order.
+    fn windows(&self) -> Vec<(i64, &Fingerprint)> {
+        match self {
+            SavedNode::Container { nodes, .. } => {
+                nodes.iter().flat_map(|n| n.windows()).collect()
+            }
+            SavedNode::Window {
+                id, fingerprint, ..
+            } => vec![(*id, fingerprint)],
+        }
+    }
+
+    /// Converts the saved tree to an arrangement of the windows given by
+    /// their index in tree order.  Windows which have no match are left out.
+    /// The commands sizing the containers are pushed to `resize_cmds`.
+    fn to_arrangement(
+        &self,
+        next_idx: &mut usize,
+        ids: &HashMap<usize, i64>,
+        resize_cmds: &mut Vec<String>,
+    ) -> Option<Arrangement> {
+        match self {
+            SavedNode::Window { .. } => {
+                let idx = *next_idx;
+                *next_idx += 1;
+                ids.get(&idx).map(|id| Arrangement::Window(*id))
+            }
+            SavedNode::Container { layout, nodes, .. } => {
+                let children: Vec<(Arrangement, Option<f64>)> = nodes
+                    .iter()
+                    .filter_map(|n| {
+                        n.to_arrangement(next_idx, ids, resize_cmds)
+                            .map(|a| (a, n.percent()))
+                    })
+                    .collect();
+                let dimension = match layout {
+                    ContainerLayout::SplitH => Some("width"),
+                    ContainerLayout::SplitV => Some("height"),
+                    _ => None,
+                };
+                // The percentages of the left out children are distributed
+                // among the others.
+                let total: f64 = children.iter().filter_map(|(_, p)| *p).sum();
+                if let (Some(dimension), true) = (dimension, total > 0.0) {
+                    // Sizing the last child is redundant.
+                    for (child, percent) in
+                        children.iter().take(children.len().saturating_sub(1))
+                    {
+                        if let Some(percent) = percent {
+                            resize_cmds.extend(resize_commands(
+                                child,
+                                dimension,
+                                percent / total,
+                            ));
+                        }
+                    }
+                }
+                layout::container(
+                    *layout,
+                    children.into_iter().map(|(a, _)| a).collect(),
+                )
+            }
+        }
+    }
+}
+
+/// The commands resizing `child` to the given fraction of its parent.
+/// Containers can't be addressed by criteria, so they are focused starting
+/// from their first window.
+fn resize_commands(
+    child: &Arrangement,
+    dimension: &str,
+    fraction: f64,
+) -> Vec<String> {
+    let ppt = (fraction * 100.0).round() as i64;
+    match child {
+        Arrangement::Window(id) => {
+            vec![format!(
+                "[con_id={}] resize set {} {} ppt",
+                id, dimension, ppt
+            )]
+        }
+        Arrangement::Container(..) => {
+            let mut cmds =
+                vec![format!("[con_id={}] focus", child.first_window())];
+            cmds.extend((0..child.depth()).map(|_| "focus parent".to_owned()));
+            cmds.push(format!("resize set {} {} ppt", dimension, ppt));
+            cmds
+        }
+    }
+}
+
+fn layout_file(name: &str) -> Result<String, String> {
+    if name.is_empty() || name.contains('/') || name.starts_with('.') {
+        Err(format!("Invalid layout name '{}'.", name))
+    } else {
+        Ok(format!("layouts/{}.json", name))
+    }
+}
+
+fn get_current_workspace(root: &s::Node) -> Result<&s::Node, String> {
+    root.nodes_of_type(ipc::Type::Workspace)
+        .into_iter()
+        .find(|ws| ws.is_current())
+        .ok_or_else(|| "No workspace is focused.".to_owned())
+}
+
+/// Saves the tree of tiled containers and windows of the current workspace.
+pub fn save_layout(name: &str) -> CmdResult {
+    let file = layout_file(name)?;
+    let root = ipc::get_tree(false)
+        .map_err(|err| format!("Couldn't get tree: {}", err))?;
+    let ws = get_current_workspace(&root)?;
+    let saved = SavedNode::of_node(ws).ok_or_else(|| {
+        "The current workspace has no tiled windows.".to_owned()
+    })?;
+    persist::try_save_state(&file, &saved)?;
+    Ok(saved
+        .windows()
+        .iter()
+        .map(|(id, _)| *id)
+        .collect::<Vec<_>>()
+        .into())
+}
+
+/// Rebuilds the saved layout on the current workspace with the windows
+/// matching the saved ones best.  Other tiled windows of the workspace are
+/// put next to it.
+pub fn restore_layout(name: &str) -> CmdResult {
+    let file = layout_file(name)?;
+    let saved: SavedNode = persist::try_load_state(&file)?
+        .ok_or_else(|| format!("There is no layout named '{}'.", name))?;
+    let root = ipc::get_tree(false)
+        .map_err(|err| format!("Couldn't get tree: {}", err))?;
+    let ws = get_current_workspace(&root)?;
+
+    let live: Vec<(i64, Fingerprint)> = persist::live_fingerprints(&root)
+        .into_iter()
+        .filter(|(_, fp)| matches!(fp, Fingerprint::Window { .. }))
+        .collect();
+    let windows = saved.windows();
+    // Earlier windows pick first.
+    let entries: Vec<(i64, &Fingerprint, Reverse<usize>)> = windows
+        .iter()
+        .enumerate()
+        .map(|(idx, (id, fp))| (*id, *fp, Reverse(idx)))
+        .collect();
+    let ids: HashMap<usize, i64> =
+        persist::reassociate_by_value(&entries, &live)
+            .into_iter()
+            .map(|(id, Reverse(idx))| (idx, id))
+            .collect();
+    let mut resize_cmds = vec![];
+    let arrangement = saved
+        .to_arrangement(&mut 0, &ids, &mut resize_cmds)
+        .ok_or_else(|| format!("No window matches layout '{}'.", name))?;
+    log::debug!(
+        "Restoring layout {} with {} of {} windows.",
+        name,
+        ids.len(),
+        windows.len()
+    );
+
+    let arranged: HashSet<i64> = arrangement.window_ids().into_iter().collect();
+    let others: Vec<i64> = ws
+        .iter()
+        .filter(|n| n.get_type() == ipc::Type::Window && !n.is_floating())
+        .map(|n| n.id)
+        .filter(|id| !arranged.contains(id))
+        .collect();
+    let focused = root.iter().find(|n| n.focused).map(|n| n.id);
+
+    let mut cmds = vec![];
+    for id in arrangement.window_ids().iter().chain(others.iter()) {
+        if root.iter().any(|n| n.id == *id && n.is_floating()) {
+            cmds.push(format!("[con_id={}] floating disable", id));
+        }
+        cmds.push(format!(
+            "[con_id={}] move to workspace {}",
+            id, SWAYR_TMP_WORKSPACE
+        ));
+    }
+    cmds.extend(layout::build_commands(ws.get_name(), &arrangement));
+    for id in &others {
+        cmds.push(format!(
+            "[con_id={}] move to workspace {}",
+            id,
+            ipc::quote(ws.get_name())
+        ));
+    }
+    cmds.extend(resize_cmds);
+    if let Some(id) = focused {
+        cmds.push(format!("[con_id={}] focus", id));
+    }
+    ipc::run_commands(&cmds)?;
+    Ok(arrangement.window_ids().into())
+}
+
+#[test]
+fn test_to_arrangement() {
+    let win = |id: i64, percent: f64| SavedNode::Window {
+        id,
+        percent: Some(percent),
+        fingerprint: Fingerprint::Window {
+            app_name: format!("app{}", id),
+            pid: None,
+            title: String::new(),
+        },
+    };
+    let saved = SavedNode::Container {
+        layout: ContainerLayout::SplitH,
+        percent: None,
+        nodes: vec![
+            SavedNode::Container {
+                layout: ContainerLayout::Tabbed,
+                percent: Some(0.6),
+                nodes: vec![win(1, 1.0), win(2, 1.0)],
+            },
+            win(3, 0.2),
+            win(4, 0.2),
+        ],
+    };
+    // The third saved window has no match.
+    let ids = HashMap::from([(0, 11), (1, 12), (3, 14)]);
+    let mut resize_cmds = vec![];
+    let arrangement = saved.to_arrangement(&mut 0, &ids, &mut resize_cmds);
+    assert_eq!(
+        arrangement,
+        Some(Arrangement::Container(
+            ContainerLayout::SplitH,
+            vec![
+                Arrangement::Container(
+                    ContainerLayout::Tabbed,
+                    vec![Arrangement::Window(11), Arrangement::Window(12)]
+                ),
+                Arrangement::Window(14)
+            ]
+        ))
+    );
+    assert_eq!(
+        resize_cmds,
+        vec![
+            "[con_id=11] focus",
+            "focus parent",
+            "resize set width 75 ppt"
+        ]
+    );
+}
@@ -0,0 +1,151 @@
+// Copyright (C) 2022  Tassilo Horn <tsdh@gnu.org>
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Commands for browsing and cycling the scratchpad.
+//!
+//! Sway's IPC doesn't tell if a visible window belongs to the scratchpad, so
+//! swayr remembers the scratchpad window it has shown most recently.  Together
+//! with the hidden windows in the `__i3_scratch` workspace, that makes up the
+//! scratchpad windows.
+
+use crate::cmds::{CmdOutput, CmdResult, Direction};
+use crate::focus::FocusData;
+use crate::shared::ipc;
+use crate::shared::ipc::NodeMethods;
+use crate::tree as t;
+use crate::util;
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+
+/// The scratchpad window shown most recently by swayr.
+static LAST_SHOWN: Lazy<Mutex<Option<i64>>> = Lazy::new(|| Mutex::new(None));
+
+fn get_last_shown() -> Option<i64> {
+    *LAST_SHOWN.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+fn set_last_shown(id: Option<i64>) {
+    *LAST_SHOWN.lock().unwrap_or_else(|err| err.into_inner()) = id;
+}
+
+fn is_hidden(tree: &t::Tree, id: i64) -> bool {
+    tree.get_parent_node_of_type(id, ipc::Type::Workspace)
+        .is_some_and(|ws| ws.is_scratchpad())
+}
+
+/// The scratchpad windows in LRU order together with the one shown by swayr
+/// which is still visible, if any.
+fn get_scratchpad_windows<'a>(
+    tree: &'a t::Tree<'a>,
+    fdata: &FocusData,
+) -> (Vec<t::DisplayNode<'a>>, Option<i64>) {
+    let last_shown = get_last_shown();
+    let mut wins: Vec<t::DisplayNode> = tree
+        .get_windows(fdata)
+        .into_iter()
+        .filter(|w| is_hidden(tree, w.node.id) || Some(w.node.id) == last_shown)
+        .collect();
+    wins.sort_by(|a, b| {
+        fdata
+            .last_focus_tick(b.node.id)
+            .cmp(&fdata.last_focus_tick(a.node.id))
+    });
+    // The window might have been hidden again by other means.
+    let shown = last_shown.filter(|id| {
+        wins.iter()
+            .any(|w| w.node.id == *id && !is_hidden(tree, w.node.id))
+    });
+    (wins, shown)
+}
+
+fn show(id: i64, hide: Option<i64>) -> CmdResult {
+    let mut cmds = vec![];
+    if let Some(hide) = hide {
+        cmds.push(format!("[con_id={}] move scratchpad", hide));
+    }
+    cmds.push(format!("[con_id={}] scratchpad show", id));
+    ipc::run_commands(&cmds)?;
+    set_last_shown(Some(id));
+    Ok(vec![id].into())
+}
+
+/// Shows the scratchpad window selected from a menu.
+pub fn switch_scratchpad_window(fdata: &FocusData) -> CmdResult {
+    let root = ipc::get_root_node(true)?;
+    let tree = t::get_tree(&root);
+    let (wins, shown) = get_scratchpad_windows(&tree, fdata);
+    if wins.is_empty() {
+        return Err("The scratchpad is empty.".into());
+    }
+    match util::select_from_menu("Select scratchpad window", &wins) {
+        // `scratchpad show` would hide a visible window.
+        Ok(win) if Some(win.node.id) == shown => {
+            ipc::run_command(&format!("[con_id={}] focus", win.node.id))?;
+            Ok(vec![win.node.id].into())
+        }
+        Ok(win) => show(win.node.id, None),
+        Err(input) if input.is_empty() => Ok(CmdOutput::default()),
+        Err(input) => {
+            Err(format!("No scratchpad window matches '{}'.", input).into())
+        }
+    }
+}
+
+/// Hides the scratchpad window shown by swayr and shows the next or previous
+/// one in LRU order like `next-window` and `prev-window` do.  If none is shown,
+/// shows the most recently shown one.
+pub fn cycle_scratchpad_windows(
+    dir: Direction,
+    fdata: &FocusData,
+) -> CmdResult {
+    let root = ipc::get_root_node(true)?;
+    let tree = t::get_tree(&root);
+    let (wins, shown) = get_scratchpad_windows(&tree, fdata);
+    let shown_idx =
+        shown.and_then(|id| wins.iter().position(|w| w.node.id == id));
+    let next = match shown_idx {
+        None => wins.first(),
+        Some(idx) => match dir {
+            Direction::Forward => wins.get((idx + wins.len() - 1) % wins.len()),
+            Direction::Backward => wins.get((idx + 1) % wins.len()),
+        },
+    };
+    match next {
+        Some(win) if Some(win.node.id) == shown => {
+            Err("No other scratchpad window.".into())
+        }
+        Some(win) => show(win.node.id, shown),
+        None => Err("The scratchpad is empty.".into()),
+    }
+}
+
+/// Moves the focused window to the scratchpad.
+pub fn hide_to_scratchpad(fdata: &FocusData) -> CmdResult {
+    let root = ipc::get_root_node(false)?;
+    let tree = t::get_tree(&root);
+    match tree.get_windows(fdata).iter().find(|w| w.node.focused) {
+        Some(win) => hide_window_to_scratchpad(win.node.id),
+        None => Err("No focused window.".into()),
+    }
+}
+
+/// Moves the window with the given id to the scratchpad.
+pub fn hide_window_to_scratchpad(id: i64) -> CmdResult {
+    ipc::run_command(&format!("[con_id={}] move scratchpad", id))?;
+    if get_last_shown() == Some(id) {
+        set_last_shown(None);
+    }
+    Ok(vec![id].into())
+}
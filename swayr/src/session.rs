@@ -0,0 +1,384 @@
+// Copyright (C) 2022  Tassilo Horn <tsdh@gnu.org>
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Saving the running apps and their windows' places, and relaunching them.
+//!
+//! When a session is restored, swayrd launches the apps itself and remembers
+//! their pids.  New windows are matched to those launches by pid or, e.g.,
+//! for apps which fork, by app name and moved to the saved workspace.
+
+use crate::cmds::CmdResult;
+use crate::config;
+use crate::launcher::AppEntry;
+use crate::persist;
+use crate::shared::ipc;
+use crate::shared::ipc::NodeMethods;
+use crate::util;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::path::PathBuf;
+use std::process as proc;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use swayipc as s;
+
+const SESSION_FILE: &str = "session.json";
+
+/// The place of a window of a saved session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionWindow {
+    pub app_name: String,
+    pub title: String,
+    pub workspace: String,
+    pub output: String,
+    pub floating: bool,
+}
+
+/// An app of a saved session, i.e., a process with its windows.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionApp {
+    /// The desktop entry file launching the app, if there is one.
+    pub desktop_entry: Option<PathBuf>,
+    /// The command line of the app's process.
+    pub cmdline: Vec<String>,
+    pub windows: Vec<SessionWindow>,
+}
+
+fn read_cmdline(pid: i32) -> Vec<String> {
+    std::fs::read(format!("/proc/{}/cmdline", pid))
+        .map(|bytes| {
+            bytes
+                .split(|b| *b == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Collects the windows of all workspaces grouped by process.
+fn collect_session(root: &s::Node) -> Vec<SessionApp> {
+    let mut apps: Vec<(Option<i32>, SessionApp)> = vec![];
+    for output in root.nodes.iter().filter(|o| !o.is_scratchpad()) {
+        for ws in &output.nodes {
+            for win in ws.iter().filter(|n| n.get_type() == ipc::Type::Window) {
+                let window = SessionWindow {
+                    app_name: win.get_app_name().to_owned(),
+                    title: win.get_name().to_owned(),
+                    workspace: ws.get_name().to_owned(),
+                    output: output.get_name().to_owned(),
+                    floating: win.is_floating(),
+                };
+                match apps
+                    .iter_mut()
+                    .find(|(pid, _)| pid.is_some() && *pid == win.pid)
+                {
+                    Some((_, app)) => app.windows.push(window),
+                    None => apps.push((
+                        win.pid,
+                        SessionApp {
+                            desktop_entry: util::get_desktop_entry(
+                                win.get_app_name(),
+                            )
+                            .map(|e| e.to_path_buf()),
+                            cmdline: win
+                                .pid
+                                .map(read_cmdline)
+                                .unwrap_or_default(),
+                            windows: vec![window],
+                        },
+                    )),
+                }
+            }
+        }
+    }
+    apps.into_iter().map(|(_, app)| app).collect()
+}
+
+/// Saves the apps of all windows together with their workspaces, outputs and
+/// floating states.
+pub fn save_session() -> CmdResult {
+    let root = ipc::get_tree(false)
+        .map_err(|err| format!("Couldn't get tree: {}", err))?;
+    let apps = collect_session(&root);
+    persist::try_save_state(SESSION_FILE, &apps)?;
+    log::info!("Saved session with {} apps.", apps.len());
+    Ok(root
+        .iter()
+        .filter(|n| n.get_type() == ipc::Type::Window)
+        .map(|n| n.id)
+        .collect::<Vec<_>>()
+        .into())
+}
+
+/// Launches the app and returns the pid of its process.
+fn launch(app: &SessionApp) -> Result<u32, String> {
+    if let Some(entry) =
+        app.desktop_entry.as_deref().and_then(AppEntry::of_file)
+    {
+        return entry.launch(&config::get_config().get_misc_terminal());
+    }
+    match app.cmdline.split_first() {
+        Some((program, args)) => {
+            util::spawn_detached(proc::Command::new(program).args(args))
+        }
+        None => Err(format!(
+            "Don't know how to launch {}.",
+            app.windows[0].app_name
+        )),
+    }
+}
+
+/// A launched app whose windows haven't appeared yet.
+#[derive(Debug)]
+struct PendingLaunch {
+    pid: u32,
+    windows: Vec<SessionWindow>,
+    deadline: Instant,
+}
+
+static PENDING_LAUNCHES: Lazy<Mutex<Vec<PendingLaunch>>> =
+    Lazy::new(|| Mutex::new(vec![]));
+
+fn lock_pending_launches() -> MutexGuard<'static, Vec<PendingLaunch>> {
+    PENDING_LAUNCHES
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+}
+
+/// Takes the saved window a new window with the given pid and app name
+/// belongs to.  Windows of the launched process win over windows of other
+/// processes with the same app name.
+fn take_pending_window(
+    pending: &mut Vec<PendingLaunch>,
+    pid: Option<i32>,
+    app_name: &str,
+) -> Option<SessionWindow> {
+    let by_pid = pending
+        .iter()
+        .position(|l| pid.is_some_and(|pid| l.pid as i32 == pid));
+    let (launch_idx, win_idx) = match by_pid {
+        Some(idx) => {
+            let win_idx = pending[idx]
+                .windows
+                .iter()
+                .position(|w| w.app_name == app_name)
+                .unwrap_or(0);
+            (idx, win_idx)
+        }
+        None => pending.iter().enumerate().find_map(|(idx, l)| {
+            l.windows
+                .iter()
+                .position(|w| w.app_name == app_name)
+                .map(|win_idx| (idx, win_idx))
+        })?,
+    };
+    let launch = &mut pending[launch_idx];
+    let win = launch.windows.remove(win_idx);
+    if launch.windows.is_empty() {
+        pending.remove(launch_idx);
+    }
+    Some(win)
+}
+
+fn place_commands(id: i64, win: &SessionWindow) -> Vec<String> {
+    vec![
+        format!(
+            "[con_id={}] floating {}",
+            id,
+            if win.floating { "enable" } else { "disable" }
+        ),
+        format!(
+            "[con_id={}] move to workspace {}",
+            id,
+            ipc::quote(&win.workspace)
+        ),
+    ]
+}
+
+/// Moves a new window to its place if it belongs to an app launched by
+/// `restore-session`.
+pub fn place_new_window(node: &s::Node) {
+    let win = {
+        let mut pending = lock_pending_launches();
+        if pending.is_empty() {
+            return;
+        }
+        let now = Instant::now();
+        pending.retain(|l| {
+            let alive = l.deadline > now;
+            if !alive {
+                log::warn!(
+                    "Gave up waiting for {} windows of process {}.",
+                    l.windows.len(),
+                    l.pid
+                );
+            }
+            alive
+        });
+        take_pending_window(&mut pending, node.pid, node.get_app_name())
+    };
+    if let Some(win) = win {
+        log::debug!("Placing new window {} like {:?}.", node.id, win);
+        if let Err(err) = ipc::run_commands(&place_commands(node.id, &win)) {
+            log::error!("Could not place window {}: {}", node.id, err);
+        }
+    }
+}
+
+/// The live window of the given app which best matches the saved window,
+/// preferring one with the saved title.
+fn find_live_window<'a>(
+    live: &[&'a s::Node],
+    taken: &HashSet<i64>,
+    win: &SessionWindow,
+) -> Option<&'a s::Node> {
+    let candidates = || {
+        live.iter().copied().filter(|n| {
+            !taken.contains(&n.id) && n.get_app_name() == win.app_name
+        })
+    };
+    candidates()
+        .find(|n| n.get_name() == win.title)
+        .or_else(|| candidates().next())
+}
+
+/// Moves existing windows to their saved places and relaunches the apps of
+/// the other saved windows.  Their windows are placed when they appear within
+/// `timeout`.  If `assign_outputs` is true, the saved workspaces are assigned
+/// to their saved outputs which lasts for the rest of the sway session.
+pub fn restore_session(timeout: Duration, assign_outputs: bool) -> CmdResult {
+    let apps: Vec<SessionApp> = persist::try_load_state(SESSION_FILE)?
+        .ok_or_else(|| "There is no saved session.".to_owned())?;
+    let root = ipc::get_tree(false)
+        .map_err(|err| format!("Couldn't get tree: {}", err))?;
+    let live: Vec<&s::Node> = root
+        .iter()
+        .filter(|n| n.get_type() == ipc::Type::Window)
+        .collect();
+
+    let mut cmds = vec![];
+    if assign_outputs {
+        let workspaces: BTreeSet<(&str, &str)> = apps
+            .iter()
+            .flat_map(|app| app.windows.iter())
+            .map(|w| (w.workspace.as_str(), w.output.as_str()))
+            .collect();
+        for (ws, output) in workspaces {
+            cmds.push(format!(
+                "workspace {} output {}",
+                ipc::quote(ws),
+                ipc::quote(output)
+            ));
+        }
+    }
+
+    let mut placed = vec![];
+    let mut taken: HashSet<i64> = HashSet::new();
+    let mut pending = lock_pending_launches();
+    for app in &apps {
+        let mut missing = vec![];
+        for win in &app.windows {
+            match find_live_window(&live, &taken, win) {
+                Some(n) => {
+                    taken.insert(n.id);
+                    placed.push(n.id);
+                    cmds.extend(place_commands(n.id, win));
+                }
+                None => missing.push(win.clone()),
+            }
+        }
+        if missing.is_empty() {
+            continue;
+        }
+        match launch(app) {
+            Ok(pid) => {
+                log::debug!(
+                    "Launched {} with pid {}.",
+                    missing[0].app_name,
+                    pid
+                );
+                pending.push(PendingLaunch {
+                    pid,
+                    windows: missing,
+                    deadline: Instant::now() + timeout,
+                });
+            }
+            Err(err) => log::error!("{}", err),
+        }
+    }
+    drop(pending);
+    ipc::run_commands(&cmds)?;
+    Ok(placed.into())
+}
+
+#[test]
+fn test_take_pending_window() {
+    let win = |app_name: &str, workspace: &str| SessionWindow {
+        app_name: app_name.to_owned(),
+        title: String::new(),
+        workspace: workspace.to_owned(),
+        output: "eDP-1".to_owned(),
+        floating: false,
+    };
+    let mut pending = vec![
+        PendingLaunch {
+            pid: 10,
+            windows: vec![win("foot", "1"), win("foot", "2")],
+            deadline: Instant::now(),
+        },
+        PendingLaunch {
+            pid: 20,
+            windows: vec![win("firefox", "3")],
+            deadline: Instant::now(),
+        },
+    ];
+    // Matched by app name.
+    assert_eq!(
+        take_pending_window(&mut pending, Some(99), "firefox"),
+        Some(win("firefox", "3"))
+    );
+    assert_eq!(pending.len(), 1);
+    // Matched by pid.
+    assert_eq!(
+        take_pending_window(&mut pending, Some(10), "footclient"),
+        Some(win("foot", "1"))
+    );
+    assert_eq!(take_pending_window(&mut pending, None, "emacs"), None);
+    assert_eq!(
+        take_pending_window(&mut pending, None, "foot"),
+        Some(win("foot", "2"))
+    );
+    assert!(pending.is_empty());
+}
+
+#[test]
+fn test_place_commands() {
+    let win = SessionWindow {
+        app_name: "foot".to_owned(),
+        title: String::new(),
+        workspace: r#"2"; kill"#.to_owned(),
+        output: "eDP-1".to_owned(),
+        floating: true,
+    };
+    assert_eq!(
+        place_commands(7, &win),
+        vec![
+            "[con_id=7] floating enable",
+            r#"[con_id=7] move to workspace "2\"; kill""#,
+        ]
+    );
+}
@@ -49,9 +49,10 @@ where
         .read(false)
         .write(true)
         .create(true)
+        .truncate(true)
         .open(path)
         .unwrap();
-    file.write_all(content.as_str().as_bytes()).unwrap();
+    file.write_all(content.as_bytes()).unwrap();
 }
 
 pub fn load_config<T>(project: &str) -> T
@@ -102,23 +103,37 @@ where
             "Config file {} does not exist.",
             config_file.to_string_lossy()
         );
-    } else {
-        log::debug!("Loading config from {}.", config_file.to_string_lossy());
     }
-    let mut file = OpenOptions::new()
-        .read(true)
-        .write(false)
-        .create(false)
-        .open(config_file)
-        .unwrap();
-    let mut buf: String = String::new();
-    file.read_to_string(&mut buf).unwrap();
-    match toml::from_str::<T>(&buf) {
+    match try_load_config_file(config_file) {
         Ok(cfg) => cfg,
         Err(err) => {
-            log::error!("Invalid config: {}", err);
+            log::error!("{}", err);
             log::error!("Using default configuration.");
             T::default()
         }
     }
 }
+
+/// Like `load_config_file` but returns an error instead of falling back to the
+/// default config so that callers can keep their current config.
+pub fn try_load_config_file<T>(config_file: &Path) -> Result<T, String>
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    log::debug!("Loading config from {}.", config_file.to_string_lossy());
+    let mut buf: String = String::new();
+    OpenOptions::new()
+        .read(true)
+        .write(false)
+        .create(false)
+        .open(config_file)
+        .and_then(|mut file| file.read_to_string(&mut buf))
+        .map_err(|err| {
+            format!(
+                "Could not read config file {}: {}",
+                config_file.to_string_lossy(),
+                err
+            )
+        })?;
+    toml::from_str::<T>(&buf).map_err(|err| format!("Invalid config: {}", err))
+}
@@ -71,15 +71,15 @@ impl From<String> for FmtArg {
     }
 }
 
-impl ToString for FmtArg {
-    fn to_string(&self) -> String {
+impl fmt::Display for FmtArg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            FmtArg::String(x) => x.clone(),
-            FmtArg::I64(x) => x.to_string(),
-            FmtArg::I32(x) => x.to_string(),
-            FmtArg::U8(x) => x.to_string(),
-            FmtArg::F64(x) => x.to_string(),
-            FmtArg::F32(x) => x.to_string(),
+            FmtArg::String(x) => write!(f, "{}", x),
+            FmtArg::I64(x) => write!(f, "{}", x),
+            FmtArg::I32(x) => write!(f, "{}", x),
+            FmtArg::U8(x) => write!(f, "{}", x),
+            FmtArg::F64(x) => write!(f, "{}", x),
+            FmtArg::F32(x) => write!(f, "{}", x),
         }
     }
 }
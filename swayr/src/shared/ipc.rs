@@ -15,28 +15,108 @@
 
 //! Basic sway IPC.
 
-use std::{cell::RefCell, sync::Mutex};
+use std::sync::Mutex;
 
 use once_cell::sync::Lazy;
 use swayipc as s;
 
-static SWAY_IPC_CONNECTION: Lazy<Mutex<RefCell<s::Connection>>> =
-    Lazy::new(|| {
-        Mutex::new(RefCell::new(
-            s::Connection::new().expect("Could not open sway IPC connection."),
-        ))
-    });
+/// The connection shared by all IPC functions below.  It's established lazily
+/// and re-established when it turns out to be broken.
+static SWAY_IPC_CONNECTION: Lazy<Mutex<Option<s::Connection>>> =
+    Lazy::new(|| Mutex::new(None));
 
-pub fn get_root_node(include_scratchpad: bool) -> s::Node {
-    let mut root = match SWAY_IPC_CONNECTION.lock() {
-        Ok(cell) => cell.borrow_mut().get_tree().expect("Couldn't get tree"),
-        Err(err) => panic!("{}", err),
-    };
+/// Calls `f` with the shared sway IPC connection.  If the connection is
+/// broken, e.g., because sway has been restarted, a new connection is
+/// established.  Only if `f` is `idempotent`, it's called once more with the
+/// new connection.  Otherwise, the error is returned because `f` might have
+/// had its effect before the reply got lost, e.g., a `kill` command.
+pub fn with_connection<T, F>(idempotent: bool, f: F) -> s::Fallible<T>
+where
+    F: Fn(&mut s::Connection) -> s::Fallible<T>,
+{
+    let mut con = SWAY_IPC_CONNECTION
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    let mut retried = false;
+    loop {
+        if con.is_none() {
+            *con = Some(s::Connection::new()?);
+        }
+        match f(con.as_mut().unwrap()) {
+            Err(s::Error::Io(err)) if idempotent && !retried => {
+                log::warn!(
+                    "Sway IPC connection broken ({}), reconnecting.",
+                    err
+                );
+                *con = None;
+                retried = true;
+            }
+            // Anything but a failed or unparsable command means that the
+            // connection is in an unknown state.
+            Err(err @ s::Error::CommandFailed(_))
+            | Err(err @ s::Error::CommandParse(_)) => return Err(err),
+            Err(err) => {
+                *con = None;
+                return Err(err);
+            }
+            result => return result,
+        }
+    }
+}
 
+pub fn get_tree(include_scratchpad: bool) -> s::Fallible<s::Node> {
+    let mut root = with_connection(true, |con| con.get_tree())?;
     if !include_scratchpad {
         root.nodes.retain(|o| !o.is_scratchpad());
     }
-    root
+    Ok(root)
+}
+
+pub fn get_root_node(include_scratchpad: bool) -> Result<s::Node, String> {
+    get_tree(include_scratchpad)
+        .map_err(|err| format!("Couldn't get tree: {}", err))
+}
+
+pub fn get_outputs() -> s::Fallible<Vec<s::Output>> {
+    with_connection(true, |con| con.get_outputs())
+}
+
+pub fn get_workspaces() -> s::Fallible<Vec<s::Workspace>> {
+    with_connection(true, |con| con.get_workspaces())
+}
+
+/// Runs the given sway commands with a single IPC message where they are
+/// separated by `;`.  That's faster and flickers less than running them one
+/// by one.  Fails if the message couldn't be sent or any command failed.
+pub fn run_commands<S: AsRef<str>>(cmds: &[S]) -> Result<(), String> {
+    if cmds.is_empty() {
+        return Ok(());
+    }
+    let cmd = cmds
+        .iter()
+        .map(|c| c.as_ref())
+        .collect::<Vec<&str>>()
+        .join("; ");
+    log::debug!("Running sway command: {}", cmd);
+    match with_connection(false, |con| con.run_command(&cmd)) {
+        Ok(outcomes) => outcomes.into_iter().try_for_each(|o| {
+            o.map_err(|err| format!("Sway command '{}' failed: {}", cmd, err))
+        }),
+        Err(err) => {
+            Err(format!("Could not run sway command '{}': {}", cmd, err))
+        }
+    }
+}
+
+pub fn run_command(cmd: &str) -> Result<(), String> {
+    run_commands(&[cmd])
+}
+
+/// Quotes `arg` for use as an argument in a sway command where `"` and `\`
+/// are escaped with a backslash.  So, e.g., a workspace name can contain
+/// quotes and `;` without breaking or extending the command.
+pub fn quote(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
 }
 
 /// Immutable Node Iterator
@@ -47,7 +127,7 @@ pub struct NodeIter<'a> {
 }
 
 impl<'a> NodeIter<'a> {
-    pub fn new(node: &'a s::Node) -> NodeIter {
+    pub fn new(node: &'a s::Node) -> NodeIter<'a> {
         NodeIter { stack: vec![node] }
     }
 }
@@ -81,7 +161,7 @@ pub enum Type {
 
 /// Extension methods for [`swayipc::Node`].
 pub trait NodeMethods {
-    fn iter(&self) -> NodeIter;
+    fn iter(&self) -> NodeIter<'_>;
     fn get_type(&self) -> Type;
     fn get_app_name(&self) -> &str;
     fn nodes_of_type(&self, t: Type) -> Vec<&s::Node>;
@@ -92,7 +172,7 @@ pub trait NodeMethods {
 }
 
 impl NodeMethods for s::Node {
-    fn iter(&self) -> NodeIter {
+    fn iter(&self) -> NodeIter<'_> {
         NodeIter::new(self)
     }
 
@@ -179,3 +259,9 @@ impl NodeMethods for s::Node {
         self.iter().any(|n| n.focused)
     }
 }
+
+#[test]
+fn test_quote() {
+    assert_eq!(quote("1: web"), r#""1: web""#);
+    assert_eq!(quote(r#"a"; kill; "b\"#), r#""a\"; kill; \"b\\""#);
+}
@@ -0,0 +1,160 @@
+// Copyright (C) 2022  Tassilo Horn <tsdh@gnu.org>
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Numbered slots for quickly jumping to windows.
+//!
+//! Each slot holds at most one window which additionally gets a swayr-managed
+//! mark so that it can also be addressed by sway commands.
+
+use crate::config;
+use crate::persist::{self, Fingerprint};
+use crate::shared::ipc;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Mutex;
+
+const SLOTS_FILE: &str = "slots.json";
+
+/// The window con_ids by slot number.
+static SLOTS: Lazy<Mutex<BTreeMap<u32, i64>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// The mark of the window in the given slot.  Marks starting with an
+/// underscore aren't shown in title bars.
+pub fn slot_mark(slot: u32) -> String {
+    format!("_swayr_slot_{}", slot)
+}
+
+fn lock_slots() -> std::sync::MutexGuard<'static, BTreeMap<u32, i64>> {
+    SLOTS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+pub fn get_slot(slot: u32) -> Option<i64> {
+    lock_slots().get(&slot).copied()
+}
+
+/// All `(slot, con_id)` pairs ordered by slot.
+pub fn get_slots() -> Vec<(u32, i64)> {
+    lock_slots().iter().map(|(s, id)| (*s, *id)).collect()
+}
+
+/// Puts the window with the given id into `slot` replacing the window which
+/// has been there before.
+pub fn assign_slot(slot: u32, id: i64) -> Result<(), String> {
+    // Marks are unique, so sway removes it from the previous window.
+    ipc::run_command(&format!(
+        "[con_id={}] mark --add {}",
+        id,
+        slot_mark(slot)
+    ))?;
+    lock_slots().insert(slot, id);
+    maybe_save_slots();
+    Ok(())
+}
+
+/// Clears all slots of the window with the given id, e.g., because it has been
+/// closed.
+pub fn remove_window(id: i64) {
+    let removed = {
+        let mut slots = lock_slots();
+        let len = slots.len();
+        slots.retain(|_, win_id| *win_id != id);
+        len != slots.len()
+    };
+    if removed {
+        maybe_save_slots();
+    }
+}
+
+/// Clears the slots of all windows not in `live_ids`.
+pub fn retain_windows(live_ids: &HashSet<i64>) {
+    lock_slots().retain(|_, id| live_ids.contains(id));
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SlotEntry {
+    slot: u32,
+    id: i64,
+    fingerprint: Fingerprint,
+}
+
+fn maybe_save_slots() {
+    if config::get_config().get_slots_persist() {
+        save_slots();
+    }
+}
+
+pub fn save_slots() {
+    let root = match ipc::get_tree(true) {
+        Ok(root) => root,
+        Err(err) => {
+            log::warn!("Could not get tree for saving slots: {}", err);
+            return;
+        }
+    };
+    let live = persist::live_fingerprints(&root);
+    let entries: Vec<SlotEntry> = get_slots()
+        .into_iter()
+        .filter_map(|(slot, id)| {
+            live.iter()
+                .find(|(live_id, _)| *live_id == id)
+                .map(|(_, fp)| SlotEntry {
+                    slot,
+                    id,
+                    fingerprint: fp.clone(),
+                })
+        })
+        .collect();
+    log::debug!("Saving {} slots.", entries.len());
+    persist::save_state(SLOTS_FILE, &entries);
+}
+
+/// Restores the slots saved by a previous swayrd instance and marks their
+/// windows again.
+pub fn restore_slots() {
+    let entries: Vec<SlotEntry> = match persist::load_state(SLOTS_FILE) {
+        Some(entries) => entries,
+        None => return,
+    };
+    let root = match ipc::get_tree(true) {
+        Ok(root) => root,
+        Err(err) => {
+            log::warn!("Could not get tree for restoring slots: {}", err);
+            return;
+        }
+    };
+    let live: Vec<(i64, Fingerprint)> = persist::live_fingerprints(&root)
+        .into_iter()
+        .filter(|(_, fp)| matches!(fp, Fingerprint::Window { .. }))
+        .collect();
+    let entries: Vec<(i64, &Fingerprint, std::cmp::Reverse<u32>)> = entries
+        .iter()
+        .map(|e| (e.id, &e.fingerprint, std::cmp::Reverse(e.slot)))
+        .collect();
+    let restored = persist::reassociate_by_value(&entries, &live);
+    log::debug!("Restored {} of {} slots.", restored.len(), entries.len());
+
+    let mut cmds = vec![];
+    let mut slots = lock_slots();
+    for (id, std::cmp::Reverse(slot)) in restored {
+        cmds.push(format!("[con_id={}] mark --add {}", id, slot_mark(slot)));
+        slots.insert(slot, id);
+    }
+    drop(slots);
+    if let Err(err) = ipc::run_commands(&cmds) {
+        log::warn!("Could not mark restored slot windows: {}", err);
+    }
+}
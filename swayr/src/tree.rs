@@ -98,7 +98,7 @@ impl<'a> Tree<'a> {
         &self,
         v: &[&'a s::Node],
         indent_level: IndentLevel,
-    ) -> Vec<DisplayNode> {
+    ) -> Vec<DisplayNode<'_>> {
         v.iter()
             .map(|node| DisplayNode {
                 node,
@@ -115,7 +115,7 @@ impl<'a> Tree<'a> {
             .expect("No current Workspace")
     }
 
-    pub fn get_outputs(&self) -> Vec<DisplayNode> {
+    pub fn get_outputs(&self) -> Vec<DisplayNode<'_>> {
         let outputs: Vec<&s::Node> = self
             .root
             .iter()
@@ -124,7 +124,7 @@ impl<'a> Tree<'a> {
         self.as_display_nodes(&outputs, IndentLevel::Fixed(0))
     }
 
-    pub fn get_workspaces(&self, fdata: &FocusData) -> Vec<DisplayNode> {
+    pub fn get_workspaces(&self, fdata: &FocusData) -> Vec<DisplayNode<'_>> {
         let mut v = self.sorted_nodes_of_type(ipc::Type::Workspace, fdata);
         if !v.is_empty() {
             v.rotate_left(1);
@@ -132,7 +132,7 @@ impl<'a> Tree<'a> {
         self.as_display_nodes(&v, IndentLevel::Fixed(0))
     }
 
-    pub fn get_windows(&self, fdata: &FocusData) -> Vec<DisplayNode> {
+    pub fn get_windows(&self, fdata: &FocusData) -> Vec<DisplayNode<'_>> {
         let mut v = self.sorted_nodes_of_type(ipc::Type::Window, fdata);
         // Rotate, but only non-urgent windows.  Those should stay at the front
         // as they are the most likely switch candidates.
@@ -159,7 +159,7 @@ impl<'a> Tree<'a> {
     pub fn get_workspaces_and_windows(
         &self,
         fdata: &FocusData,
-    ) -> Vec<DisplayNode> {
+    ) -> Vec<DisplayNode<'_>> {
         let workspaces = self.sorted_nodes_of_type(ipc::Type::Workspace, fdata);
         let mut first = true;
         let mut v = vec![];
@@ -215,28 +215,28 @@ impl<'a> Tree<'a> {
     pub fn get_outputs_workspaces_containers_and_windows(
         &self,
         fdata: &FocusData,
-    ) -> Vec<DisplayNode> {
+    ) -> Vec<DisplayNode<'_>> {
         let outputs = self.sorted_nodes_of_type(ipc::Type::Output, fdata);
         let v: Rc<RefCell<Vec<&s::Node>>> = Rc::new(RefCell::new(vec![]));
         for o in outputs {
             self.push_subtree_sorted(o, Rc::clone(&v), fdata);
         }
 
-        let x = self.as_display_nodes(&*v.borrow(), IndentLevel::TreeDepth(1));
+        let x = self.as_display_nodes(&v.borrow(), IndentLevel::TreeDepth(1));
         x
     }
 
     pub fn get_workspaces_containers_and_windows(
         &self,
         fdata: &FocusData,
-    ) -> Vec<DisplayNode> {
+    ) -> Vec<DisplayNode<'_>> {
         let workspaces = self.sorted_nodes_of_type(ipc::Type::Workspace, fdata);
         let v: Rc<RefCell<Vec<&s::Node>>> = Rc::new(RefCell::new(vec![]));
         for ws in workspaces {
             self.push_subtree_sorted(ws, Rc::clone(&v), fdata);
         }
 
-        let x = self.as_display_nodes(&*v.borrow(), IndentLevel::TreeDepth(2));
+        let x = self.as_display_nodes(&v.borrow(), IndentLevel::TreeDepth(2));
         x
     }
 
@@ -281,7 +281,7 @@ fn init_id_parent<'a>(
     }
 }
 
-pub fn get_tree(root: &s::Node) -> Tree {
+pub fn get_tree(root: &s::Node) -> Tree<'_> {
     let mut id_node: HashMap<i64, &s::Node> = HashMap::new();
     let mut id_parent: HashMap<i64, i64> = HashMap::new();
     init_id_parent(root, None, &mut id_node, &mut id_parent);
@@ -362,7 +362,7 @@ impl DisplayFormat for DisplayNode<'_> {
                     })
                     .or(fallback_icon)
                     .map(|i| i.to_string_lossy().into_owned())
-                    .unwrap_or_else(String::new)
+                    .unwrap_or_default()
                     .as_str(),
             );
 
@@ -385,7 +385,7 @@ impl DisplayFormat for DisplayNode<'_> {
 
     fn get_indent_level(&self) -> usize {
         match self.indent_level {
-            IndentLevel::Fixed(level) => level as usize,
+            IndentLevel::Fixed(level) => level,
             IndentLevel::WorkspacesZeroWindowsOne => {
                 match self.node.get_type(){
                     ipc::Type::Workspace => 0,
@@ -400,11 +400,7 @@ impl DisplayFormat for DisplayNode<'_> {
                     depth += 1;
                     node = p;
                 }
-                if offset > depth {
-                    0
-                } else {
-                    depth - offset as usize
-                }
+                depth.saturating_sub(offset)
             }
         }
     }
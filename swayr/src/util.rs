@@ -19,8 +19,9 @@ use once_cell::sync::Lazy;
 use regex::Regex;
 
 use crate::config as cfg;
+use crate::launcher::AppEntry;
 use std::collections::HashMap;
-use std::io::{BufRead, Write};
+use std::io::{BufRead, Read, Write};
 use std::path as p;
 use std::process as proc;
 use std::sync::Mutex;
@@ -84,7 +85,8 @@ fn desktop_entry_folders() -> Vec<Box<p::Path>> {
     dirs
 }
 
-fn desktop_entries() -> Vec<Box<p::Path>> {
+/// All desktop entry files in the XDG application directories.
+pub fn desktop_entries() -> Vec<Box<p::Path>> {
     let mut entries = vec![];
     for dir in desktop_entry_folders() {
         if let Ok(readdir) = dir.read_dir() {
@@ -102,7 +104,10 @@ fn desktop_entries() -> Vec<Box<p::Path>> {
     entries
 }
 
-fn find_icon(icon_name: &str, icon_dirs: &[String]) -> Option<Box<p::Path>> {
+pub fn find_icon(
+    icon_name: &str,
+    icon_dirs: &[String],
+) -> Option<Box<p::Path>> {
     let p = p::Path::new(icon_name);
     if p.is_file() {
         log::debug!("(1) Icon name '{}' -> {}", icon_name, p.display());
@@ -134,6 +139,194 @@ static WM_CLASS_OR_ICON_RX: Lazy<Regex> =
 static REV_DOMAIN_NAME_RX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^(?:[a-zA-Z0-9-]+\.)+([a-zA-Z0-9-]+)$").unwrap());
 
+/// The app_ids which windows of apps launched from the desktop entry `e`
+/// usually have.
+fn desktop_entry_app_ids(e: &p::Path, wm_class: Option<String>) -> Vec<String> {
+    let mut app_ids = vec![];
+
+    // Sometimes the StartupWMClass is the app_id, e.g. FF Dev Edition has
+    // StartupWMClass firefoxdeveloperedition although the desktop file is
+    // named firefox-developer-edition.
+    if let Some(wm_class) = wm_class {
+        app_ids.push(wm_class);
+    }
+
+    // Some apps have a reverse domain name desktop file, e.g.,
+    // org.gnome.eog.desktop but reports as just eog.
+    let desktop_file_name = String::from(
+        e.with_extension("").file_name().unwrap().to_string_lossy(),
+    );
+    if let Some(caps) = REV_DOMAIN_NAME_RX.captures(&desktop_file_name) {
+        app_ids.push(caps.get(1).unwrap().as_str().to_string());
+    }
+
+    // The usual case is that the app with foo.desktop also has the app_id
+    // foo.
+    app_ids.push(desktop_file_name);
+    app_ids
+}
+
+/// The key-value pairs of the `[Desktop Entry]` group of the given desktop
+/// entry file.
+pub fn read_desktop_entry(e: &p::Path) -> HashMap<String, String> {
+    let mut map = HashMap::new();
+    if let Ok(f) = std::fs::File::open(e) {
+        let mut in_group = false;
+        for line in std::io::BufReader::new(f).lines().map_while(Result::ok) {
+            let line = line.trim();
+            if line.starts_with('[') {
+                in_group = line == "[Desktop Entry]";
+            } else if in_group {
+                if let Some((key, value)) = line.split_once('=') {
+                    map.entry(key.trim().to_owned())
+                        .or_insert_with(|| value.trim().to_owned());
+                }
+            }
+        }
+    }
+    map
+}
+
+static APP_ID_TO_DESKTOP_ENTRY_MAP: Lazy<HashMap<String, Box<p::Path>>> =
+    Lazy::new(|| {
+        let mut map = HashMap::new();
+        for e in desktop_entries() {
+            let wm_class = read_desktop_entry(&e).remove("StartupWMClass");
+            for app_id in desktop_entry_app_ids(&e, wm_class) {
+                map.entry(app_id).or_insert_with(|| e.clone());
+            }
+        }
+        map
+    });
+
+/// The desktop entry file which launches the app with the given app_id.
+pub fn get_desktop_entry(app_id: &str) -> Option<Box<p::Path>> {
+    APP_ID_TO_DESKTOP_ENTRY_MAP.get(app_id).cloned()
+}
+
+/// Undoes the escapes of desktop entry string values like `\s`.
+fn unescape_desktop_entry_value(value: &str) -> String {
+    let mut result = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => result.push(' '),
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some(c) => result.push(c),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// Splits the `Exec` value of the given desktop entry into the program and its
+/// arguments according to the Desktop Entry Specification.  `%i` expands to
+/// `--icon <Icon>`, `%c` to the name, and `%k` to the file of the entry.  The
+/// file and URL field codes like `%U` are dropped, arguments consisting only
+/// of them entirely, and `%%` becomes `%`.
+pub fn parse_exec(entry: &AppEntry) -> Result<Vec<String>, String> {
+    let exec = &entry.exec;
+    let mut args = vec![];
+    // The current argument and whether it consisted only of field codes so
+    // far.
+    let mut arg: Option<(String, bool)> = None;
+    let mut quoted = false;
+    let value = unescape_desktop_entry_value(exec);
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                arg.get_or_insert_with(|| (String::new(), false)).1 = false;
+            }
+            '\\' if quoted => match chars.next() {
+                Some(c @ ('"' | '`' | '$' | '\\')) => {
+                    let arg = arg.get_or_insert_with(Default::default);
+                    arg.0.push(c);
+                    arg.1 = false;
+                }
+                _ => return Err(format!("Invalid escape in Exec '{}'.", exec)),
+            },
+            c if c.is_whitespace() && !quoted => {
+                if let Some((arg, only_field_codes)) = arg.take() {
+                    if !only_field_codes {
+                        args.push(arg);
+                    }
+                }
+            }
+            '%' => {
+                let arg = arg.get_or_insert_with(|| (String::new(), true));
+                match chars.next() {
+                    Some('%') => {
+                        arg.0.push('%');
+                        arg.1 = false;
+                    }
+                    Some('c') => {
+                        arg.0.push_str(&entry.name);
+                        arg.1 = false;
+                    }
+                    Some('k') => {
+                        arg.0.push_str(&entry.file.to_string_lossy());
+                        arg.1 = false;
+                    }
+                    // `%i` may only be used as a whole argument and becomes
+                    // two arguments, or none if there's no icon.
+                    Some('i') => {
+                        if let Some(icon) =
+                            entry.icon.as_ref().filter(|i| !i.is_empty())
+                        {
+                            args.push("--icon".to_owned());
+                            arg.0.push_str(icon);
+                            arg.1 = false;
+                        }
+                    }
+                    Some(_) => (),
+                    None => {
+                        return Err(format!(
+                            "Incomplete field code in '{}'.",
+                            exec
+                        ))
+                    }
+                }
+            }
+            c => {
+                let arg = arg.get_or_insert_with(Default::default);
+                arg.0.push(c);
+                arg.1 = false;
+            }
+        }
+    }
+    if quoted {
+        return Err(format!("Unbalanced quotes in Exec '{}'.", exec));
+    }
+    if let Some((arg, only_field_codes)) = arg {
+        if !only_field_codes {
+            args.push(arg);
+        }
+    }
+    Ok(args)
+}
+
+/// Spawns `cmd` detached from swayr's stdio and returns the pid of the new
+/// process.  The process is reaped when it exits.
+pub fn spawn_detached(cmd: &mut proc::Command) -> Result<u32, String> {
+    let mut child = cmd
+        .stdin(proc::Stdio::null())
+        .stdout(proc::Stdio::null())
+        .stderr(proc::Stdio::null())
+        .spawn()
+        .map_err(|err| format!("Could not run {:?}: {}", cmd, err))?;
+    let pid = child.id();
+    std::thread::spawn(move || child.wait());
+    Ok(pid)
+}
+
 fn get_app_id_to_icon_map(
     icon_dirs: &[String],
 ) -> HashMap<String, Box<p::Path>> {
@@ -166,30 +359,9 @@ fn get_app_id_to_icon_map(
             }
 
             if let Some(icon) = icon {
-                // Sometimes the StartupWMClass is the app_id, e.g. FF Dev
-                // Edition has StartupWMClass firefoxdeveloperedition although
-                // the desktop file is named firefox-developer-edition.
-                if let Some(wm_class) = wm_class {
-                    map.insert(wm_class, icon.clone());
+                for app_id in desktop_entry_app_ids(&e, wm_class) {
+                    map.insert(app_id, icon.clone());
                 }
-
-                // Some apps have a reverse domain name desktop file, e.g.,
-                // org.gnome.eog.desktop but reports as just eog.
-                let desktop_file_name = String::from(
-                    e.with_extension("").file_name().unwrap().to_string_lossy(),
-                );
-                if let Some(caps) =
-                    REV_DOMAIN_NAME_RX.captures(&desktop_file_name)
-                {
-                    map.insert(
-                        caps.get(1).unwrap().as_str().to_string(),
-                        icon.clone(),
-                    );
-                }
-
-                // The usual case is that the app with foo.desktop also has the
-                // app_id foo.
-                map.insert(desktop_file_name.clone(), icon);
             }
         }
     }
@@ -239,8 +411,29 @@ pub trait DisplayFormat {
     fn get_indent_level(&self) -> usize;
 }
 
-pub fn select_from_menu<'a, 'b, TS>(
-    prompt: &'a str,
+/// The menu program currently waiting for the user's choice, if any.
+static MENU_PROCESS: Lazy<Mutex<Option<proc::Child>>> =
+    Lazy::new(|| Mutex::new(None));
+
+pub fn is_menu_open() -> bool {
+    MENU_PROCESS.lock().expect("Could not lock mutex").is_some()
+}
+
+/// Kills the currently open menu program, if any.  The command which opened
+/// it then behaves as if the user had cancelled the menu.
+pub fn cancel_menu() {
+    if let Some(menu) =
+        MENU_PROCESS.lock().expect("Could not lock mutex").as_mut()
+    {
+        log::debug!("Cancelling menu program with pid {}.", menu.id());
+        if let Err(err) = menu.kill() {
+            log::error!("Could not cancel menu program: {}", err);
+        }
+    }
+}
+
+pub fn select_from_menu<'b, TS>(
+    prompt: &str,
     choices: &'b [TS],
 ) -> Result<&'b TS, String>
 where
@@ -248,7 +441,7 @@ where
 {
     let mut map: HashMap<String, &TS> = HashMap::new();
     let mut strs: Vec<String> = vec![];
-    let cfg = cfg::load_config();
+    let cfg = cfg::get_config();
     for c in choices {
         let s = c.format_for_display(&cfg);
         strs.push(s.clone());
@@ -277,12 +470,12 @@ where
         .stdin(proc::Stdio::piped())
         .stdout(proc::Stdio::piped())
         .spawn()
-        .expect(&("Error running ".to_owned() + &menu_exec));
+        .unwrap_or_else(|_| panic!("Error running {}", menu_exec));
 
     {
-        let stdin = menu
+        let mut stdin = menu
             .stdin
-            .as_mut()
+            .take()
             .expect("Failed to open the menu program's stdin");
         let input = strs.join("\n");
         //log::debug!("Menu program {} input:\n{}", menu_exec, input);
@@ -291,9 +484,74 @@ where
             .expect("Failed to write to the menu program's stdin");
     }
 
-    let output = menu.wait_with_output().expect("Failed to read stdout");
-    let choice = String::from_utf8_lossy(&output.stdout);
+    let mut stdout = menu
+        .stdout
+        .take()
+        .expect("Failed to open the menu program's stdout");
+    *MENU_PROCESS.lock().expect("Could not lock mutex") = Some(menu);
+    // Reading ends when the user has made a choice or the menu has been
+    // cancelled using cancel_menu().
+    let mut output = vec![];
+    stdout
+        .read_to_end(&mut output)
+        .expect("Failed to read stdout");
+    if let Some(mut menu) =
+        MENU_PROCESS.lock().expect("Could not lock mutex").take()
+    {
+        if let Err(err) = menu.wait() {
+            log::error!("Error waiting for menu program: {}", err);
+        }
+    }
+
+    let choice = String::from_utf8_lossy(&output);
     let mut choice = String::from(choice);
     choice.pop(); // Remove trailing \n from choice.
     map.get(&choice).copied().ok_or(choice)
 }
+
+#[test]
+fn test_parse_exec() {
+    let entry = |exec: &str| AppEntry {
+        id: "foo.desktop".to_owned(),
+        file: p::PathBuf::from("/apps/foo.desktop"),
+        name: "Foo".to_owned(),
+        comment: String::new(),
+        icon: Some("foo-icon".to_owned()),
+        exec: exec.to_owned(),
+        terminal: false,
+        path: None,
+        hidden: false,
+    };
+    let args = |v: &[&str]| Ok(v.iter().map(|s| s.to_string()).collect());
+    assert_eq!(parse_exec(&entry("firefox %u")), args(&["firefox"]));
+    assert_eq!(
+        parse_exec(&entry("foo --bar=%F -x 100%%")),
+        args(&["foo", "--bar=", "-x", "100%"])
+    );
+    // Whitespace and escapes in quoted arguments are kept.
+    assert_eq!(
+        parse_exec(&entry(r#"sh -c "echo  \\"a\\$b\\" | less" "" %U"#)),
+        args(&["sh", "-c", "echo  \"a$b\" | less", ""])
+    );
+    assert_eq!(
+        parse_exec(&entry(r#""foo\sbar" x"#)),
+        args(&["foo bar", "x"])
+    );
+    assert!(parse_exec(&entry("foo \"bar")).is_err());
+    // Icon, name, and file of the entry.
+    assert_eq!(
+        parse_exec(&entry("foo %i --class=%c %k")),
+        args(&[
+            "foo",
+            "--icon",
+            "foo-icon",
+            "--class=Foo",
+            "/apps/foo.desktop"
+        ])
+    );
+    let no_icon = AppEntry {
+        icon: None,
+        ..entry("foo %i %f")
+    };
+    assert_eq!(parse_exec(&no_icon), args(&["foo"]));
+}
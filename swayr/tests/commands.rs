@@ -0,0 +1,956 @@
+// Copyright (C) 2022  Tassilo Horn <tsdh@gnu.org>
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Runs swayrd against a mock sway and checks the sway commands it sends.
+
+mod common;
+
+use common::mock_sway;
+use common::Swayrd;
+use serde_json::{json, Value};
+use swayr::cmds::{ConsiderFloating, ConsiderWindows, SwayrCommand};
+
+/// Starts swayrd and focuses the windows so that the LRU order is emacs (21,
+/// focused in the fixture), mpv (22), firefox (12), foot (11).
+fn start_with_lru() -> Swayrd {
+    let swayrd = Swayrd::start();
+    for id in [11, 12, 22, 21] {
+        swayrd.focus(id);
+    }
+    assert_eq!(swayrd.lru(), vec![21, 22, 12, 11]);
+    swayrd.sway.clear_commands();
+    swayrd
+}
+
+#[test]
+fn test_get_windows() {
+    let swayrd = start_with_lru();
+    let resp = swayrd.send(SwayrCommand::GetWindows);
+    assert!(resp.success);
+    let wins = resp.data.unwrap();
+    let wins = wins.as_array().unwrap();
+    // LRU order but the focused window comes last.
+    let ids: Vec<i64> =
+        wins.iter().map(|w| w["id"].as_i64().unwrap()).collect();
+    assert_eq!(ids, vec![22, 12, 11, 21]);
+    assert_eq!(wins[0]["app_name"], "mpv");
+    assert_eq!(wins[0]["workspace"], "2");
+    assert_eq!(wins[0]["floating"], true);
+    assert!(swayrd.sway.commands().is_empty());
+}
+
+#[test]
+fn test_next_and_prev_window() {
+    let swayrd = start_with_lru();
+
+    let resp = swayrd.send(SwayrCommand::PrevWindow {
+        windows: ConsiderWindows::AllWorkspaces,
+    });
+    assert!(resp.success);
+    assert_eq!(resp.con_ids, vec![22]);
+
+    let resp = swayrd.send(SwayrCommand::NextWindow {
+        windows: ConsiderWindows::AllWorkspaces,
+    });
+    assert_eq!(resp.con_ids, vec![11]);
+
+    // Only emacs and mpv are on the current workspace.
+    swayrd.send(SwayrCommand::NextWindow {
+        windows: ConsiderWindows::CurrentWorkspace,
+    });
+
+    assert_eq!(
+        swayrd.sway.commands(),
+        vec![
+            "[con_id=22] focus",
+            "[con_id=11] focus",
+            "[con_id=22] focus"
+        ]
+    );
+}
+
+#[test]
+fn test_switch_to_app_or_urgent_or_lru_window() {
+    let swayrd = start_with_lru();
+
+    let resp = swayrd.send(SwayrCommand::SwitchToAppOrUrgentOrLRUWindow {
+        name: "firefox".to_owned(),
+        skip_urgent: false,
+        skip_lru: false,
+        skip_origin: false,
+    });
+    assert!(resp.success);
+    assert_eq!(swayrd.sway.commands(), vec!["[con_id=12] focus"]);
+
+    swayrd.sway.clear_commands();
+    swayrd.send(SwayrCommand::SwitchToUrgentOrLRUWindow {
+        skip_urgent: false,
+        skip_lru: false,
+        skip_origin: false,
+    });
+    assert_eq!(swayrd.sway.commands(), vec!["[con_id=22] focus"]);
+}
+
+#[test]
+fn test_switch_window_menu() {
+    let swayrd = start_with_lru();
+    // The menu program is `head -n 1`, i.e., it selects the first window.
+    let resp = swayrd.send(SwayrCommand::SwitchWindow);
+    assert!(resp.success);
+    assert_eq!(swayrd.sway.commands(), vec!["[con_id=22] focus"]);
+}
+
+#[test]
+fn test_tile_workspace() {
+    let swayrd = start_with_lru();
+
+    let resp = swayrd.send(SwayrCommand::TileWorkspace {
+        floating: ConsiderFloating::IncludeFloating,
+    });
+    assert!(resp.success, "{:?}", resp.error);
+
+    // The windows are moved away and back with one message each.
+    assert_eq!(
+        swayrd.sway.messages(),
+        vec![
+            "[con_id=21] move to workspace ✨; \
+             [con_id=22] move to workspace ✨",
+            "focus parent; layout splith; \
+             [con_id=22] floating disable; \
+             [con_id=22] move to workspace current; \
+             [con_id=21] move to workspace current; \
+             [con_id=21] focus",
+        ]
+    );
+
+    swayrd.sway.clear_commands();
+    swayrd.send(SwayrCommand::TabWorkspace {
+        floating: ConsiderFloating::ExcludeFloating,
+    });
+    assert_eq!(
+        swayrd.sway.commands(),
+        vec![
+            "[con_id=21] move to workspace ✨",
+            "focus parent",
+            "layout tabbed",
+            "[con_id=21] move to workspace current",
+            "[con_id=21] focus",
+        ]
+    );
+}
+
+#[test]
+fn test_command_errors() {
+    let swayrd = start_with_lru();
+
+    let resp = swayrd.send(SwayrCommand::NextMatchingWindow {
+        criteria: "[app_id=\"nope\"]".to_owned(),
+    });
+    assert!(!resp.success);
+    assert_eq!(resp.error.as_deref(), Some("No matching window."));
+
+    let resp = swayrd.send(SwayrCommand::NextMatchingWindow {
+        criteria: "[app_id=".to_owned(),
+    });
+    assert!(!resp.success);
+    assert!(resp.error.unwrap().contains("at offset 8: expected"));
+    let details = resp.error_details.unwrap();
+    assert_eq!(details["offset"], 8);
+    assert!(details["kind"]["syntax"]["expected"].is_array());
+
+    assert!(swayrd.sway.commands().is_empty());
+}
+
+#[test]
+fn test_check_criteria() {
+    let swayrd = Swayrd::start();
+
+    let resp = swayrd.send(SwayrCommand::CheckCriteria {
+        criteria: "[app_id=\"^f\"]".to_owned(),
+    });
+    assert!(resp.success);
+    let data = resp.data.unwrap();
+    assert_eq!(
+        data["criteria"],
+        json!({
+            "type": "and",
+            "args": [{ "type": "app_id", "args": { "regex": "^f" } }],
+        })
+    );
+    let mut ids: Vec<i64> = data["windows"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|w| w["id"].as_i64().unwrap())
+        .collect();
+    ids.sort();
+    assert_eq!(ids, vec![11, 12]);
+
+    let resp = swayrd.send(SwayrCommand::CheckCriteria {
+        criteria: "[title=\"(\"]".to_owned(),
+    });
+    assert!(!resp.success);
+    assert!(resp.error.unwrap().contains("Invalid regex"));
+    assert_eq!(resp.error_details.unwrap()["kind"]["regex"]["regex"], "(");
+    assert!(swayrd.sway.commands().is_empty());
+}
+
+#[test]
+fn test_extended_criteria() {
+    let swayrd = Swayrd::start();
+    let next = |criteria: &str| {
+        swayrd
+            .send(SwayrCommand::NextMatchingWindow {
+                criteria: criteria.to_owned(),
+            })
+            .con_ids
+    };
+
+    assert_eq!(
+        next("[output=\"^eDP\" width<=960 floating visible]"),
+        vec![22]
+    );
+    assert_eq!(next("[width>1000 output=__focused__]"), vec![21]);
+    assert!(next("[or urgent sticky fullscreen con_mark]").is_empty());
+}
+
+#[test]
+fn test_recency_criteria() {
+    let swayrd = start_with_lru();
+    let next = |criteria: &str| {
+        swayrd
+            .send(SwayrCommand::NextMatchingWindow {
+                criteria: criteria.to_owned(),
+            })
+            .con_ids
+    };
+
+    // The LRU order is 21, 22, 12, 11.
+    assert_eq!(next("[lru<3 tiling not con_id=__focused__]"), vec![12]);
+    assert_eq!(next("[lru>=3]"), vec![11]);
+    assert_eq!(next("[focused_within=1h app_name=\"foot\"]"), vec![11]);
+    assert_eq!(next("[newest]"), vec![22]);
+    assert!(next("never_focused").is_empty());
+}
+
+#[test]
+fn test_closed_windows_leave_lru() {
+    let swayrd = start_with_lru();
+    swayrd.sway.emit_window_event("close", 22);
+    common::wait_until("22 is removed", || {
+        swayrd
+            .send(SwayrCommand::GetLru)
+            .data
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|w| w["id"] == 22 && w["last_focus_tick"] == 0)
+    });
+}
+
+#[test]
+fn test_status() {
+    let swayrd = start_with_lru();
+    let status = swayrd.send(SwayrCommand::Status).data.unwrap();
+    assert_eq!(status["health"]["state"], "connected");
+    assert_eq!(status["sequence_inhibited"], false);
+    assert!(status["pending_lock_in"].is_null());
+    assert!(status["last_command"].is_null());
+    let lru: Vec<i64> = status["lru"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["id"].as_i64().unwrap())
+        .collect();
+    // Whether the workspaces have been locked in, too, depends on timing.
+    let windows: Vec<i64> = lru
+        .iter()
+        .copied()
+        .filter(|id| ![10, 20].contains(id))
+        .collect();
+    assert_eq!(windows[0..4], [21, 22, 12, 11]);
+    assert_eq!(status["tracked_nodes"], lru.len());
+
+    swayrd.send(SwayrCommand::NextMatchingWindow {
+        criteria: "[app_id=\"nope\"]".to_owned(),
+    });
+    let status = swayrd.send(SwayrCommand::Status).data.unwrap();
+    assert_eq!(status["last_command"]["success"], false);
+    assert_eq!(status["last_command"]["error"], "No matching window.");
+    assert!(swayrd.sway.commands().is_empty());
+}
+
+/// The fixture tree where workspace 2 is on a second output DP-1 together
+/// with the empty workspace 3.
+fn two_outputs_tree() -> Value {
+    let mut tree = common::tree_fixture();
+    let edp1 = tree["nodes"][1].clone();
+    let ws2 = edp1["nodes"][1].clone();
+    let mut ws3 = edp1["nodes"][0].clone();
+    ws3["id"] = json!(30);
+    ws3["name"] = json!("3");
+    ws3["num"] = json!(3);
+    ws3["nodes"] = json!([]);
+    ws3["floating_nodes"] = json!([]);
+    let mut dp1 = edp1;
+    dp1["id"] = json!(5);
+    dp1["name"] = json!("DP-1");
+    dp1["nodes"] = json!([ws2, ws3]);
+    mock_sway::remove_node(&mut tree, 20);
+    tree["nodes"].as_array_mut().unwrap().push(dp1);
+    tree
+}
+
+#[test]
+fn test_lru_on_output() {
+    let swayrd = Swayrd::start_with_tree(two_outputs_tree());
+    for id in [22, 11, 12, 21] {
+        swayrd.focus(id);
+    }
+
+    // Firefox is moved from workspace 1 on eDP-1 to workspace 2 on DP-1
+    // without being focused there.
+    let mut tree = two_outputs_tree();
+    let firefox = tree["nodes"][1]["nodes"][0]["nodes"][1].clone();
+    assert_eq!(firefox["id"], 12);
+    mock_sway::remove_node(&mut tree, 12);
+    tree["nodes"][2]["nodes"][0]["nodes"]
+        .as_array_mut()
+        .unwrap()
+        .push(firefox);
+    swayrd.sway.set_tree(tree);
+    swayrd.sway.clear_commands();
+
+    // Globally, firefox is the LRU window but on DP-1 it's mpv.
+    let resp = swayrd.send(SwayrCommand::SwitchToLruWindowOnOutput);
+    assert!(resp.success, "{:?}", resp.error);
+    let resp = swayrd.send(SwayrCommand::SwitchToLruWindowOnWorkspace);
+    assert!(resp.success, "{:?}", resp.error);
+    swayrd.send(SwayrCommand::NextWindow {
+        windows: ConsiderWindows::CurrentOutput,
+    });
+    swayrd.send(SwayrCommand::SwitchToLruWindowOnOutput);
+    swayrd.send(SwayrCommand::SwitchToUrgentOrLRUWindow {
+        skip_urgent: false,
+        skip_lru: false,
+        skip_origin: false,
+    });
+    assert_eq!(
+        swayrd.sway.commands(),
+        vec![
+            "[con_id=22] focus",
+            "[con_id=22] focus",
+            "[con_id=12] focus",
+            "[con_id=22] focus",
+            "[con_id=12] focus",
+        ]
+    );
+
+    swayrd.sway.clear_commands();
+    let resp = swayrd.send(SwayrCommand::SwitchToLruWorkspaceOnOutput);
+    assert_eq!(resp.con_ids, vec![30]);
+    assert_eq!(swayrd.sway.commands(), vec!["workspace 3"]);
+}
+
+#[test]
+fn test_focus_back_and_forward() {
+    let swayrd = Swayrd::start();
+    for id in [11, 12, 22, 21] {
+        swayrd.focus(id);
+    }
+    swayrd.sway.clear_commands();
+
+    let resp = swayrd.send(SwayrCommand::FocusBack);
+    assert_eq!(resp.con_ids, vec![22]);
+    swayrd.focus(22);
+    swayrd.send(SwayrCommand::FocusBack);
+    swayrd.focus(12);
+    // Unlike the LRU order, the history still has mpv after firefox.
+    swayrd.send(SwayrCommand::FocusForward);
+    swayrd.focus(22);
+
+    // Closed windows are skipped.
+    swayrd.sway.emit_window_event("close", 12);
+    common::wait_until("12 is removed", || {
+        swayrd
+            .send(SwayrCommand::GetLru)
+            .data
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|w| w["id"] == 12 && w["last_focus_tick"] == 0)
+    });
+    let resp = swayrd.send(SwayrCommand::FocusBack);
+    assert_eq!(resp.con_ids, vec![11]);
+
+    let resp = swayrd.send(SwayrCommand::FocusBack);
+    assert!(!resp.success);
+    assert_eq!(
+        swayrd.sway.commands(),
+        vec![
+            "[con_id=22] focus",
+            "[con_id=12] focus",
+            "[con_id=22] focus",
+            "[con_id=11] focus",
+        ]
+    );
+}
+
+#[test]
+fn test_slots() {
+    let swayrd = start_with_lru();
+
+    let resp = swayrd.send(SwayrCommand::AssignSlot { slot: 1 });
+    assert_eq!(resp.con_ids, vec![21]);
+    swayrd.focus(12);
+    swayrd.send(SwayrCommand::AssignSlot { slot: 2 });
+    let jump = |slot| SwayrCommand::JumpToSlot {
+        slot,
+        skip_urgent: false,
+        skip_lru: false,
+        skip_origin: false,
+    };
+    swayrd.send(jump(1));
+    // Firefox in slot 2 is focused already, so fall back to the LRU window.
+    swayrd.send(jump(2));
+    // The menu program selects the first slot.
+    swayrd.send(SwayrCommand::ShowSlots);
+    assert_eq!(
+        swayrd.sway.commands(),
+        vec![
+            "[con_id=21] mark --add _swayr_slot_1",
+            "[con_id=12] mark --add _swayr_slot_2",
+            "[con_id=21] focus",
+            "[con_id=21] focus",
+            "[con_id=21] focus",
+        ]
+    );
+
+    swayrd.sway.emit_window_event("close", 21);
+    common::wait_until("slot 1 is cleared", || !swayrd.send(jump(1)).success);
+    assert_eq!(
+        swayrd.send(jump(1)).error.as_deref(),
+        Some("Slot 1 is empty.")
+    );
+}
+
+/// Adds a floating window to the workspace with the given index of output
+/// eDP-1, or to the scratchpad.
+fn add_floating_window(tree: &mut Value, ws: Option<usize>, id: i64) {
+    let mut win = mock_sway::find_node(tree, 22).unwrap().clone();
+    win["id"] = json!(id);
+    win["app_id"] = json!(format!("app{}", id));
+    win["focused"] = json!(false);
+    let ws = match ws {
+        Some(idx) => &mut tree["nodes"][1]["nodes"][idx],
+        None => &mut tree["nodes"][0]["nodes"][0],
+    };
+    ws["floating_nodes"].as_array_mut().unwrap().push(win);
+}
+
+#[test]
+fn test_scratchpad() {
+    let mut tree = common::tree_fixture();
+    add_floating_window(&mut tree, None, 31);
+    add_floating_window(&mut tree, None, 32);
+    let swayrd = Swayrd::start_with_tree(tree);
+    for id in [32, 31, 21] {
+        swayrd.focus(id);
+    }
+    swayrd.sway.clear_commands();
+
+    // Nothing is shown, so show the most recent scratchpad window.
+    let resp = swayrd.send(SwayrCommand::NextScratchpadWindow);
+    assert_eq!(resp.con_ids, vec![31]);
+
+    // Now it's visible on workspace 2.
+    let mut tree = common::tree_fixture();
+    add_floating_window(&mut tree, Some(1), 31);
+    add_floating_window(&mut tree, None, 32);
+    swayrd.sway.set_tree(tree);
+    swayrd.focus(31);
+
+    let resp = swayrd.send(SwayrCommand::NextScratchpadWindow);
+    assert_eq!(resp.con_ids, vec![32]);
+    let resp = swayrd.send(SwayrCommand::HideToScratchpad);
+    assert_eq!(resp.con_ids, vec![31]);
+    assert_eq!(
+        swayrd.sway.messages(),
+        vec![
+            "[con_id=31] scratchpad show",
+            "[con_id=31] move scratchpad; [con_id=32] scratchpad show",
+            "[con_id=31] move scratchpad",
+        ]
+    );
+
+    // The menu program selects the most recent scratchpad window.
+    swayrd.sway.clear_commands();
+    let mut tree = common::tree_fixture();
+    add_floating_window(&mut tree, None, 31);
+    add_floating_window(&mut tree, None, 32);
+    swayrd.sway.set_tree(tree);
+    swayrd.send(SwayrCommand::SwitchScratchpadWindow);
+    assert_eq!(swayrd.sway.commands(), vec!["[con_id=31] scratchpad show"]);
+}
+
+#[test]
+fn test_rules() {
+    let swayrd = Swayrd::start_with_extra_config(
+        r#"
+[[rules]]
+criteria = '[app_name="mpv"]'
+actions = ["floating enable", "swayr assign-slot 3"]
+
+[[rules]]
+criteria = '[title="^News" tiling]'
+actions = ["move to workspace 9"]
+on_title_change = true
+"#,
+    );
+
+    swayrd.sway.emit_window_event("new", 22);
+    common::wait_until("the mpv rule is applied", || {
+        swayrd.sway.commands().len() == 2
+    });
+    // The swayr action acts on the matched window, too.
+    assert_eq!(
+        swayrd.sway.commands(),
+        vec![
+            "[con_id=22] floating enable",
+            "[con_id=22] mark --add _swayr_slot_3",
+        ]
+    );
+
+    // Only the second rule applies on title changes.
+    swayrd.sway.clear_commands();
+    swayrd.sway.emit_window_event("title", 22);
+    swayrd.sway.emit_window_event("title", 12);
+    common::wait_until("the title rule is applied", || {
+        !swayrd.sway.commands().is_empty()
+    });
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert_eq!(
+        swayrd.sway.commands(),
+        vec!["[con_id=12] move to workspace 9"]
+    );
+
+    // Rules are checked when the config is loaded.
+    swayrd.write_extra_config(
+        r#"
+[[rules]]
+criteria = '[app_name="mpv"]'
+actions = ["swayr switch-window"]
+"#,
+    );
+    let resp = swayrd.send(SwayrCommand::ReloadConfig);
+    assert!(resp
+        .error
+        .unwrap()
+        .contains("it can't act on the matched window"));
+}
+
+#[test]
+fn test_dynamic_layout() {
+    let tree = common::tree_fixture();
+    let swayrd = Swayrd::start_with_extra_config(
+        r#"
+[layout]
+dynamic_layout_per_workspace = { "1" = "master-left" }
+"#,
+    );
+
+    // Workspace 1 gets arranged on the first window event.
+    swayrd.sway.emit_window_event("move", 11);
+    common::wait_until("workspace 1 is arranged", || {
+        swayrd.sway.commands().len() == 7
+    });
+    assert_eq!(
+        swayrd.sway.commands(),
+        vec![
+            "[con_id=11] move to workspace ✨",
+            "[con_id=12] move to workspace ✨",
+            "[con_id=11] move to workspace \"1\"",
+            "[con_id=11] layout splith",
+            "[con_id=11] mark --add _swayr_layout",
+            "[con_id=12] move to mark _swayr_layout",
+            "unmark _swayr_layout",
+        ]
+    );
+
+    // A new window becomes the master.
+    swayrd.sway.clear_commands();
+    swayrd.add_new_window(tree, 13, "foot");
+    common::wait_until("workspace 1 is arranged again", || {
+        swayrd.sway.commands().len() == 11
+    });
+    assert_eq!(
+        swayrd.sway.commands()[3..],
+        vec![
+            "[con_id=13] move to workspace \"1\"",
+            "[con_id=13] layout splith",
+            "[con_id=13] mark --add _swayr_layout",
+            "[con_id=11] move to mark _swayr_layout",
+            "[con_id=11] splitv",
+            "[con_id=11] mark --add _swayr_layout",
+            "[con_id=12] move to mark _swayr_layout",
+            "unmark _swayr_layout",
+        ]
+    );
+
+    // Workspace 2 with the focused window has no dynamic layout.
+    assert_eq!(
+        swayrd.send(SwayrCommand::SwapWithMaster).error.as_deref(),
+        Some("The current workspace has no dynamic layout.")
+    );
+    // Cycling starts with the first layout but there's only one tiled window.
+    swayrd.sway.clear_commands();
+    assert_eq!(swayrd.send(SwayrCommand::CycleLayout).con_ids, vec![20]);
+
+    // The master swaps places with the first stack window.
+    swayrd.focus(13);
+    swayrd.sway.clear_commands();
+    swayrd.send(SwayrCommand::SwapWithMaster);
+    let cmds = swayrd.sway.commands();
+    assert_eq!(cmds[3], "[con_id=11] move to workspace \"1\"");
+    assert_eq!(cmds.last().unwrap(), "[con_id=13] focus");
+
+    // The second master joins the first one's column.
+    swayrd.sway.clear_commands();
+    swayrd.send(SwayrCommand::IncreaseMasterCount);
+    let cmds = swayrd.sway.commands();
+    assert_eq!(cmds[4], "[con_id=11] layout splith");
+    assert_eq!(cmds[7], "[con_id=11] splitv");
+    assert_eq!(cmds[8], "[con_id=11] mark --add _swayr_layout");
+    assert_eq!(cmds[9], "[con_id=13] move to mark _swayr_layout");
+}
+
+#[test]
+fn test_save_and_restore_layout() {
+    // Workspace 2 gets a tabbed container with emacs (21) and another window
+    // next to a third window.
+    let mut tree = common::tree_fixture();
+    let emacs = mock_sway::find_node(&tree, 21).unwrap().clone();
+    let win = |id: i64, percent: f64| {
+        let mut win = emacs.clone();
+        win["id"] = json!(id);
+        win["app_id"] = json!(format!("app{}", id));
+        win["focused"] = json!(id == 21);
+        win["percent"] = json!(percent);
+        win
+    };
+    let mut tabbed = emacs.clone();
+    for key in ["name", "app_id", "pid", "shell", "window_properties"] {
+        tabbed[key] = Value::Null;
+    }
+    tabbed["id"] = json!(30);
+    tabbed["focused"] = json!(false);
+    tabbed["layout"] = json!("tabbed");
+    tabbed["percent"] = json!(0.6);
+    tabbed["nodes"] = json!([win(21, 0.5), win(23, 0.5)]);
+    tree["nodes"][1]["nodes"][1]["nodes"] = json!([tabbed, win(24, 0.4)]);
+    let swayrd = Swayrd::start_with_tree(tree);
+
+    let save = |name: &str| SwayrCommand::SaveLayout {
+        name: name.to_owned(),
+    };
+    let restore = |name: &str| SwayrCommand::RestoreLayout {
+        name: name.to_owned(),
+    };
+    assert_eq!(swayrd.send(save("dev")).con_ids, vec![21, 23, 24]);
+    assert_eq!(
+        swayrd.send(save("../dev")).error.as_deref(),
+        Some("Invalid layout name '../dev'.")
+    );
+    assert_eq!(
+        swayrd.send(restore("none")).error.as_deref(),
+        Some("There is no layout named 'none'.")
+    );
+
+    assert_eq!(swayrd.send(restore("dev")).con_ids, vec![21, 23, 24]);
+    assert_eq!(
+        swayrd.sway.commands(),
+        vec![
+            "[con_id=21] move to workspace ✨",
+            "[con_id=23] move to workspace ✨",
+            "[con_id=24] move to workspace ✨",
+            "[con_id=21] move to workspace \"2\"",
+            "[con_id=21] layout splith",
+            "[con_id=21] mark --add _swayr_layout",
+            "[con_id=24] move to mark _swayr_layout",
+            "[con_id=21] splitv",
+            "[con_id=21] layout tabbed",
+            "[con_id=21] mark --add _swayr_layout",
+            "[con_id=23] move to mark _swayr_layout",
+            "unmark _swayr_layout",
+            "[con_id=21] focus",
+            "focus parent",
+            "resize set width 60 ppt",
+            "[con_id=21] focus",
+        ]
+    );
+}
+
+#[test]
+fn test_save_and_restore_session() {
+    let swayrd = Swayrd::start();
+    let restore = |assign_outputs| SwayrCommand::RestoreSession {
+        timeout: 1,
+        assign_outputs,
+    };
+    assert_eq!(
+        swayrd.send(restore(false)).error.as_deref(),
+        Some("There is no saved session.")
+    );
+    let mut saved = swayrd.send(SwayrCommand::SaveSession).con_ids;
+    saved.sort();
+    assert_eq!(saved, vec![11, 12, 21, 22]);
+
+    // All saved windows still exist, so nothing is launched.
+    let mut restored = swayrd.send(restore(false)).con_ids;
+    restored.sort();
+    assert_eq!(restored, vec![11, 12, 21, 22]);
+    assert_eq!(swayrd.sway.commands().len(), 8);
+
+    // Workspaces are only assigned to outputs on request.
+    swayrd.sway.clear_commands();
+    swayrd.send(restore(true));
+    assert_eq!(
+        swayrd.sway.commands(),
+        vec![
+            "workspace \"1\" output \"eDP-1\"",
+            "workspace \"2\" output \"eDP-1\"",
+            "[con_id=12] floating disable",
+            "[con_id=12] move to workspace \"1\"",
+            "[con_id=11] floating disable",
+            "[con_id=11] move to workspace \"1\"",
+            "[con_id=21] floating disable",
+            "[con_id=21] move to workspace \"2\"",
+            "[con_id=22] floating enable",
+            "[con_id=22] move to workspace \"2\"",
+        ]
+    );
+}
+
+#[test]
+fn test_run_or_raise() {
+    let tree = common::tree_fixture();
+    let swayrd = Swayrd::start();
+    let run_or_raise = |criteria: &str| SwayrCommand::RunOrRaise {
+        criteria: criteria.to_owned(),
+        command: "true".to_owned(),
+        workspace: Some("9".to_owned()),
+        floating: true,
+        mark: Some("my editor".to_owned()),
+        timeout: 10,
+    };
+
+    // A matching window is raised.
+    assert_eq!(
+        swayrd.send(run_or_raise("[app_name=\"firefox\"]")).con_ids,
+        vec![12]
+    );
+    assert_eq!(swayrd.sway.commands(), vec!["[con_id=12] focus"]);
+
+    // Otherwise, the command is run and the new window is placed.
+    swayrd.sway.clear_commands();
+    let response = swayrd.send(run_or_raise("[app_name=\"gedit\"]"));
+    assert_eq!(response.error, None);
+    assert!(swayrd.sway.commands().is_empty());
+    swayrd.add_new_window(tree, 13, "gedit");
+    common::wait_until("the new window is placed", || {
+        swayrd.sway.commands().len() == 3
+    });
+    assert_eq!(
+        swayrd.sway.commands(),
+        vec![
+            "[con_id=13] move to workspace \"9\"",
+            "[con_id=13] floating enable",
+            "[con_id=13] mark --add \"my editor\"",
+        ]
+    );
+}
+
+#[test]
+fn test_bulk_actions() {
+    let swayrd = Swayrd::start();
+
+    // A dry run only lists the matching windows.
+    let response = swayrd.send(SwayrCommand::QuitMatching {
+        criteria: "[workspace=\"1\"]".to_owned(),
+        kill: false,
+        dry_run: true,
+    });
+    assert_eq!(response.con_ids, vec![12, 11]);
+    let app_names: Vec<&str> = response
+        .data
+        .as_ref()
+        .unwrap()
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|w| w["app_name"].as_str().unwrap())
+        .collect();
+    assert_eq!(app_names, vec!["firefox", "foot"]);
+    assert!(swayrd.sway.commands().is_empty());
+
+    swayrd.send(SwayrCommand::QuitMatching {
+        criteria: "[workspace=\"1\"]".to_owned(),
+        kill: false,
+        dry_run: false,
+    });
+    assert_eq!(
+        swayrd.sway.commands(),
+        vec!["[con_id=12] kill", "[con_id=11] kill"]
+    );
+
+    swayrd.sway.clear_commands();
+    swayrd.send(SwayrCommand::ForEachMatching {
+        criteria: "[app_name=\"foot\"]".to_owned(),
+        command: "floating enable".to_owned(),
+        dry_run: false,
+    });
+    swayrd.send(SwayrCommand::MoveMatchingToWorkspace {
+        criteria: "[floating]".to_owned(),
+        workspace: "3:music".to_owned(),
+        dry_run: false,
+    });
+    swayrd.send(SwayrCommand::StealMatching {
+        criteria: "[app_name=\"firefox\"]".to_owned(),
+        dry_run: false,
+    });
+    assert_eq!(
+        swayrd.sway.commands(),
+        vec![
+            "[con_id=11] floating enable",
+            "[con_id=22] move to workspace number \"3:music\"",
+            "[con_id=12] move to workspace current",
+        ]
+    );
+
+    swayrd.sway.clear_commands();
+    swayrd.send(SwayrCommand::MoveMatchingToWorkspace {
+        criteria: "[app_name=\"foot\"]".to_owned(),
+        workspace: r#"a"; kill; "b"#.to_owned(),
+        dry_run: false,
+    });
+    assert_eq!(
+        swayrd.sway.messages(),
+        vec![r#"[con_id=11] move to workspace "a\"; kill; \"b""#]
+    );
+
+    let response = swayrd.send(SwayrCommand::StealMatching {
+        criteria: "[app_name=\"nope\"]".to_owned(),
+        dry_run: false,
+    });
+    assert_eq!(response.error.as_deref(), Some("No matching window."));
+
+    swayrd.sway.clear_commands();
+    let response = swayrd.send(SwayrCommand::ForEachMatching {
+        criteria: "[app_name=\"foot\"]".to_owned(),
+        command: "floating enable; kill".to_owned(),
+        dry_run: false,
+    });
+    assert_eq!(
+        response.error.as_deref(),
+        Some("The command must not contain ';'.")
+    );
+    assert!(swayrd.sway.commands().is_empty());
+}
+
+#[test]
+fn test_quit_matching_kill_reports_failures() {
+    // No process can have a pid above the kernel's `pid_max`.
+    fn set_invalid_pids(node: &mut Value) {
+        if node["pid"].is_i64() {
+            node["pid"] = json!(i32::MAX);
+        }
+        for key in ["nodes", "floating_nodes"] {
+            if let Some(children) = node[key].as_array_mut() {
+                children.iter_mut().for_each(set_invalid_pids);
+            }
+        }
+    }
+    let mut tree = common::tree_fixture();
+    set_invalid_pids(&mut tree);
+    let swayrd = Swayrd::start_with_tree(tree);
+
+    let response = swayrd.send(SwayrCommand::QuitMatching {
+        criteria: "[workspace=\"1\"]".to_owned(),
+        kill: true,
+        dry_run: false,
+    });
+    assert!(!response.success);
+    // Both windows share the pid, so it's killed only once.
+    let error = response.error.unwrap();
+    assert!(
+        error.starts_with(&format!(
+            "Could not kill all processes: Error killing process {}",
+            i32::MAX
+        )),
+        "{}",
+        error
+    );
+    assert_eq!(error.matches("Error killing").count(), 1);
+    assert!(swayrd.sway.commands().is_empty());
+}
+
+#[test]
+fn test_named_criteria() {
+    let swayrd = Swayrd::start_with_extra_config(
+        r#"
+[criteria]
+browsers = '[or app_name="firefox" app_name="chromium"]'
+media = '[or @browsers app_name="mpv"]'
+"#,
+    );
+    let matching = |criteria: &str| {
+        let resp = swayrd.send(SwayrCommand::CheckCriteria {
+            criteria: criteria.to_owned(),
+        });
+        let mut ids: Vec<i64> = resp.data.unwrap()["windows"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|w| w["id"].as_i64().unwrap())
+            .collect();
+        ids.sort();
+        ids
+    };
+    assert_eq!(matching("[@browsers]"), vec![12]);
+    assert_eq!(matching("@media"), vec![12, 22]);
+    assert_eq!(matching("[not @media tiling]"), vec![11, 21]);
+
+    let resp = swayrd.send(SwayrCommand::CheckCriteria {
+        criteria: "[@games]".to_owned(),
+    });
+    assert!(resp
+        .error
+        .unwrap()
+        .starts_with("Unknown named criteria @games"));
+
+    // A cyclic config is rejected on reload and the old one is kept.
+    swayrd.write_extra_config(
+        r#"
+[criteria]
+a = '[@b]'
+b = '[@a]'
+"#,
+    );
+    let resp = swayrd.send(SwayrCommand::ReloadConfig);
+    assert!(resp.error.unwrap().contains("@a -> @b -> @a"));
+    assert_eq!(matching("[@browsers]"), vec![12]);
+}
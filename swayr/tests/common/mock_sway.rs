@@ -0,0 +1,348 @@
+// Copyright (C) 2022  Tassilo Horn <tsdh@gnu.org>
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! A stand-in for sway speaking the i3/sway IPC wire format on a Unix socket.
+//!
+//! It serves a tree given as JSON, records all received commands, and can
+//! send events to its subscribers.
+
+use serde_json::{json, Value};
+use std::io::{self, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const MAGIC: &[u8; 6] = b"i3-ipc";
+
+const RUN_COMMAND: u32 = 0;
+const GET_WORKSPACES: u32 = 1;
+const SUBSCRIBE: u32 = 2;
+const GET_OUTPUTS: u32 = 3;
+const GET_TREE: u32 = 4;
+const GET_MARKS: u32 = 5;
+const GET_VERSION: u32 = 7;
+
+pub const EVENT_WORKSPACE: u32 = 0;
+pub const EVENT_WINDOW: u32 = 3;
+pub const EVENT_SHUTDOWN: u32 = 6;
+
+const EVENT_NAMES: [(&str, u32); 3] = [
+    ("workspace", EVENT_WORKSPACE),
+    ("window", EVENT_WINDOW),
+    ("shutdown", EVENT_SHUTDOWN),
+];
+
+struct Subscriber {
+    stream: UnixStream,
+    events: Vec<u32>,
+}
+
+#[derive(Default)]
+struct State {
+    tree: Value,
+    /// The payloads of all RUN_COMMAND messages.
+    messages: Vec<String>,
+    subscribers: Vec<Subscriber>,
+}
+
+pub struct MockSway {
+    socket_path: PathBuf,
+    state: Arc<Mutex<State>>,
+}
+
+fn read_message(stream: &mut UnixStream) -> io::Result<(u32, Vec<u8>)> {
+    let mut header = [0u8; 14];
+    stream.read_exact(&mut header)?;
+    if &header[0..6] != MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Bad magic."));
+    }
+    let len = u32::from_ne_bytes(header[6..10].try_into().unwrap());
+    let msg_type = u32::from_ne_bytes(header[10..14].try_into().unwrap());
+    let mut payload = vec![0u8; len as usize];
+    stream.read_exact(&mut payload)?;
+    Ok((msg_type, payload))
+}
+
+fn write_message(
+    stream: &mut UnixStream,
+    msg_type: u32,
+    payload: &Value,
+) -> io::Result<()> {
+    let payload = serde_json::to_vec(payload)?;
+    let mut msg = MAGIC.to_vec();
+    msg.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    msg.extend_from_slice(&msg_type.to_ne_bytes());
+    msg.extend_from_slice(&payload);
+    stream.write_all(&msg)
+}
+
+pub fn find_node(node: &Value, id: i64) -> Option<&Value> {
+    if node["id"].as_i64() == Some(id) {
+        return Some(node);
+    }
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|k| node[k].as_array())
+        .flatten()
+        .find_map(|n| find_node(n, id))
+}
+
+/// Removes the node with the given id from `tree`.
+pub fn remove_node(tree: &mut Value, id: i64) {
+    for k in ["nodes", "floating_nodes"] {
+        if let Some(nodes) = tree[k].as_array_mut() {
+            nodes.retain(|n| n["id"].as_i64() != Some(id));
+            for n in nodes {
+                remove_node(n, id);
+            }
+        }
+    }
+}
+
+/// The workspace containing the node with the given id.
+fn workspace_of(node: &Value, id: i64) -> Option<&Value> {
+    if node["type"] == "workspace" {
+        return find_node(node, id).map(|_| node);
+    }
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|k| node[k].as_array())
+        .flatten()
+        .find_map(|n| workspace_of(n, id))
+}
+
+/// The id of the focused node.
+fn focused_id(node: &Value) -> Option<i64> {
+    if node["focused"] == true {
+        return node["id"].as_i64();
+    }
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|k| node[k].as_array())
+        .flatten()
+        .find_map(focused_id)
+}
+
+/// Makes the node with the given id the only focused one in `tree`.
+pub fn set_focused(tree: &mut Value, id: i64) {
+    tree["focused"] = Value::Bool(tree["id"].as_i64() == Some(id));
+    for k in ["nodes", "floating_nodes"] {
+        if let Some(nodes) = tree[k].as_array_mut() {
+            for n in nodes {
+                set_focused(n, id);
+            }
+        }
+    }
+}
+
+fn workspaces(node: &Value, output: Option<&str>, result: &mut Vec<Value>) {
+    let output = if node["type"] == "output" {
+        node["name"].as_str()
+    } else {
+        output
+    };
+    if node["type"] == "workspace" {
+        result.push(json!({
+            "id": node["id"],
+            "num": node["num"].as_i64().unwrap_or(-1),
+            "name": node["name"],
+            "layout": node["layout"],
+            "visible": true,
+            "focused": node["focused"],
+            "urgent": node["urgent"],
+            "representation": node["representation"],
+            "orientation": "horizontal",
+            "rect": node["rect"],
+            "output": output,
+            "focus": node["focus"],
+        }));
+    }
+    for n in ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|k| node[k].as_array())
+        .flatten()
+    {
+        workspaces(n, output, result);
+    }
+}
+
+impl MockSway {
+    /// Starts serving `tree` on a socket at `socket_path`.
+    pub fn start(socket_path: &Path, tree: Value) -> MockSway {
+        let listener =
+            UnixListener::bind(socket_path).expect("Could not bind socket");
+        let state = Arc::new(Mutex::new(State {
+            tree,
+            ..State::default()
+        }));
+        {
+            let state = state.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let state = state.clone();
+                    thread::spawn(move || serve(stream, state));
+                }
+            });
+        }
+        MockSway {
+            socket_path: socket_path.to_owned(),
+            state,
+        }
+    }
+
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    pub fn set_tree(&self, tree: Value) {
+        self.state.lock().unwrap().tree = tree;
+    }
+
+    /// Focuses the node with the given id and sends the corresponding window
+    /// event.  Like sway, it's preceded by a workspace event if the focus
+    /// moves to another workspace.
+    pub fn focus(&self, id: i64) {
+        let ws_event = {
+            let mut state = self.state.lock().unwrap();
+            let old = focused_id(&state.tree)
+                .and_then(|old| workspace_of(&state.tree, old))
+                .cloned();
+            set_focused(&mut state.tree, id);
+            workspace_of(&state.tree, id)
+                .filter(|ws| old.as_ref().map(|o| &o["id"]) != Some(&ws["id"]))
+                .map(|ws| json!({ "change": "focus", "current": ws, "old": old }))
+        };
+        if let Some(ws_event) = ws_event {
+            self.emit(EVENT_WORKSPACE, ws_event);
+        }
+        self.emit_window_event("focus", id);
+    }
+
+    pub fn node(&self, id: i64) -> Value {
+        let state = self.state.lock().unwrap();
+        find_node(&state.tree, id)
+            .unwrap_or_else(|| panic!("No node with id {}", id))
+            .clone()
+    }
+
+    /// The payloads of all received RUN_COMMAND messages.
+    pub fn messages(&self) -> Vec<String> {
+        self.state.lock().unwrap().messages.clone()
+    }
+
+    /// All received commands where messages containing several commands
+    /// separated by `;` have been split.
+    pub fn commands(&self) -> Vec<String> {
+        self.messages()
+            .iter()
+            .flat_map(|m| m.split(';').map(|c| c.trim().to_owned()))
+            .collect()
+    }
+
+    pub fn clear_commands(&self) {
+        self.state.lock().unwrap().messages.clear();
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.state.lock().unwrap().subscribers.len()
+    }
+
+    /// Closes the connections of all subscribers as if sway had crashed.
+    pub fn disconnect_subscribers(&self) {
+        for s in self.state.lock().unwrap().subscribers.drain(..) {
+            let _ = s.stream.shutdown(std::net::Shutdown::Both);
+        }
+    }
+
+    /// Sends an event of the given type to all subscribers interested in it.
+    pub fn emit(&self, event_type: u32, payload: Value) {
+        let mut state = self.state.lock().unwrap();
+        state.subscribers.retain_mut(|s| {
+            !s.events.contains(&event_type)
+                || write_message(
+                    &mut s.stream,
+                    event_type | 0x8000_0000,
+                    &payload,
+                )
+                .is_ok()
+        });
+    }
+
+    pub fn emit_window_event(&self, change: &str, id: i64) {
+        let container = self.node(id);
+        self.emit(
+            EVENT_WINDOW,
+            json!({ "change": change, "container": container }),
+        );
+    }
+}
+
+fn serve(mut stream: UnixStream, state: Arc<Mutex<State>>) {
+    while let Ok((msg_type, payload)) = read_message(&mut stream) {
+        let payload = String::from_utf8_lossy(&payload).into_owned();
+        let reply = match msg_type {
+            RUN_COMMAND => {
+                let count = payload.split(';').count();
+                state.lock().unwrap().messages.push(payload);
+                Value::Array(vec![json!({ "success": true }); count])
+            }
+            GET_WORKSPACES => {
+                let mut result = vec![];
+                workspaces(&state.lock().unwrap().tree, None, &mut result);
+                Value::Array(result)
+            }
+            SUBSCRIBE => {
+                let names: Vec<String> =
+                    serde_json::from_str(&payload).unwrap_or_default();
+                let events = EVENT_NAMES
+                    .iter()
+                    .filter(|(name, _)| names.iter().any(|n| n == name))
+                    .map(|(_, t)| *t)
+                    .collect();
+                if write_message(
+                    &mut stream,
+                    msg_type,
+                    &json!({ "success": true }),
+                )
+                .is_ok()
+                {
+                    let stream = stream.try_clone().unwrap();
+                    state
+                        .lock()
+                        .unwrap()
+                        .subscribers
+                        .push(Subscriber { stream, events });
+                }
+                // A subscribed connection only receives events.
+                return;
+            }
+            GET_OUTPUTS => json!([]),
+            GET_TREE => state.lock().unwrap().tree.clone(),
+            GET_MARKS => json!([]),
+            GET_VERSION => json!({
+                "major": 1,
+                "minor": 7,
+                "patch": 0,
+                "human_readable": "1.7 (mock)",
+                "loaded_config_file_name": "/dev/null",
+            }),
+            _ => json!({ "success": false, "error": "Not implemented." }),
+        };
+        if write_message(&mut stream, msg_type, &reply).is_err() {
+            return;
+        }
+    }
+}
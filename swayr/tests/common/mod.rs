@@ -0,0 +1,219 @@
+// Copyright (C) 2022  Tassilo Horn <tsdh@gnu.org>
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Runs swayrd against a [`MockSway`] in an isolated environment.
+
+#![allow(dead_code)]
+
+pub mod mock_sway;
+
+use mock_sway::MockSway;
+use serde_json::{json, Value};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::{Child, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use swayr::cmds::SwayrCommand;
+use swayr::protocol;
+
+const DEFAULT_CONFIG: &str = r#"
+[menu]
+executable = "head"
+args = ["-n", "1"]
+
+[format]
+window_format = "{id} {app_name} {title}"
+workspace_format = "{id} {name}"
+container_format = "{id} {layout}"
+output_format = "{id} {name}"
+html_escape = false
+
+[focus]
+lockin_delay = 0
+persist_history = false
+"#;
+
+pub fn tree_fixture() -> Value {
+    serde_json::from_str(include_str!("../fixtures/tree.json"))
+        .expect("Invalid tree fixture")
+}
+
+/// Calls `f` until it returns true or panics after a few seconds.
+pub fn wait_until<F: FnMut() -> bool>(what: &str, mut f: F) {
+    let start = Instant::now();
+    while !f() {
+        if start.elapsed() > Duration::from_secs(10) {
+            panic!("Timed out waiting until {}.", what);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+pub struct Swayrd {
+    pub sway: MockSway,
+    dir: PathBuf,
+    child: Child,
+}
+
+impl Swayrd {
+    pub fn start() -> Swayrd {
+        Swayrd::start_with(tree_fixture(), DEFAULT_CONFIG)
+    }
+
+    pub fn start_with_tree(tree: Value) -> Swayrd {
+        Swayrd::start_with(tree, DEFAULT_CONFIG)
+    }
+
+    /// Starts swayrd with the default test config extended by `extra`.
+    pub fn start_with_extra_config(extra: &str) -> Swayrd {
+        Swayrd::start_with(
+            tree_fixture(),
+            &format!("{}{}", DEFAULT_CONFIG, extra),
+        )
+    }
+
+    /// Starts swayrd with its own runtime, config, and state directories
+    /// talking to a mock sway serving `tree`.
+    pub fn start_with(tree: Value, config: &str) -> Swayrd {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "swayr-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("config/swayr")).unwrap();
+        std::fs::write(dir.join("config/swayr/config.toml"), config).unwrap();
+
+        let sway = MockSway::start(&dir.join("sway.sock"), tree);
+        let child = Command::new(env!("CARGO_BIN_EXE_swayrd"))
+            .env_remove("I3SOCK")
+            .env("SWAYSOCK", sway.socket_path())
+            .env("XDG_RUNTIME_DIR", &dir)
+            .env("WAYLAND_DISPLAY", "test")
+            .env("HOME", &dir)
+            .env("XDG_CONFIG_HOME", dir.join("config"))
+            .env("XDG_STATE_HOME", dir.join("state"))
+            .env(
+                "RUST_LOG",
+                std::env::var("RUST_LOG").unwrap_or_else(|_| "off".to_owned()),
+            )
+            .spawn()
+            .expect("Could not start swayrd");
+
+        let swayrd = Swayrd { sway, dir, child };
+        wait_until("swayrd listens", || swayrd.socket_path().exists());
+        wait_until("swayrd subscribes", || swayrd.sway.subscriber_count() > 0);
+        swayrd
+    }
+
+    /// Adds a copy of the foot window 11 with the given id and app_id to
+    /// workspace 1 of `tree`, serves that tree, and emits a `new` window event
+    /// for the window.
+    pub fn add_new_window(&self, mut tree: Value, id: i64, app_id: &str) {
+        let mut win = mock_sway::find_node(&tree, 11).unwrap().clone();
+        win["id"] = json!(id);
+        win["app_id"] = json!(app_id);
+        tree["nodes"][1]["nodes"][0]["nodes"]
+            .as_array_mut()
+            .unwrap()
+            .push(win);
+        self.sway.set_tree(tree);
+        self.sway.emit_window_event("new", id);
+    }
+
+    /// Replaces the config file with the default test config extended by
+    /// `extra`.  It takes effect on `reload-config`.
+    pub fn write_extra_config(&self, extra: &str) {
+        std::fs::write(
+            self.dir.join("config/swayr/config.toml"),
+            format!("{}{}", DEFAULT_CONFIG, extra),
+        )
+        .unwrap();
+    }
+
+    fn socket_path(&self) -> PathBuf {
+        self.dir.join("swayr-test.sock")
+    }
+
+    pub fn send(&self, cmd: SwayrCommand) -> protocol::Response {
+        let mut sock = UnixStream::connect(self.socket_path())
+            .expect("Could not connect to swayrd");
+        protocol::write_frame(&mut sock, &protocol::Request::new(cmd)).unwrap();
+        let frame = protocol::read_frame(&mut sock).unwrap();
+        serde_json::from_slice(&frame).unwrap()
+    }
+
+    /// Subscribes to swayrd's events.
+    pub fn subscribe(&self) -> UnixStream {
+        let mut sock = UnixStream::connect(self.socket_path())
+            .expect("Could not connect to swayrd");
+        protocol::write_frame(
+            &mut sock,
+            &protocol::Request::new(SwayrCommand::Subscribe),
+        )
+        .unwrap();
+        protocol::read_frame(&mut sock).unwrap();
+        sock
+    }
+
+    /// Reads events from `sock` until one satisfies `pred`.
+    pub fn wait_for_event<P: Fn(&Value) -> bool>(
+        sock: &mut UnixStream,
+        pred: P,
+    ) -> Value {
+        sock.set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        loop {
+            let frame = protocol::read_frame(sock).expect("No event");
+            let event: Value = serde_json::from_slice(&frame).unwrap();
+            if pred(&event) {
+                return event;
+            }
+        }
+    }
+
+    pub fn has_exited(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(Some(_)))
+    }
+
+    /// The ids of the windows in LRU order.
+    pub fn lru(&self) -> Vec<i64> {
+        let data = self.send(SwayrCommand::GetLru).data.unwrap();
+        data.as_array()
+            .unwrap()
+            .iter()
+            .map(|w| w["id"].as_i64().unwrap())
+            .collect()
+    }
+
+    /// Lets the window with the given id receive the focus and waits until
+    /// swayrd has locked it in.
+    pub fn focus(&self, id: i64) {
+        self.sway.focus(id);
+        wait_until(&format!("{} is locked in", id), || {
+            self.lru().first() == Some(&id)
+        });
+    }
+}
+
+impl Drop for Swayrd {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
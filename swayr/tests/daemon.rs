@@ -0,0 +1,67 @@
+// Copyright (C) 2022  Tassilo Horn <tsdh@gnu.org>
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Tests of swayrd's connection handling using a mock sway.
+
+mod common;
+
+use common::mock_sway::{self, EVENT_SHUTDOWN};
+use common::Swayrd;
+use serde_json::json;
+use swayr::cmds::SwayrCommand;
+
+#[test]
+fn test_reconnect_and_resync() {
+    let swayrd = Swayrd::start();
+    for id in [11, 12, 21] {
+        swayrd.focus(id);
+    }
+    let mut events = swayrd.subscribe();
+
+    // Firefox is closed while swayrd is disconnected.
+    let mut tree = common::tree_fixture();
+    mock_sway::remove_node(&mut tree, 12);
+    swayrd.sway.set_tree(tree);
+    swayrd.sway.disconnect_subscribers();
+
+    let ev = Swayrd::wait_for_event(&mut events, |ev| {
+        ev["event"] == "health_changed"
+    });
+    assert_eq!(ev["health"]["state"], "reconnecting");
+    Swayrd::wait_for_event(&mut events, |ev| {
+        ev["event"] == "health_changed" && ev["health"]["state"] == "connected"
+    });
+    assert_eq!(swayrd.lru(), vec![21, 11, 22]);
+
+    // A new window with the same id has no focus history.
+    swayrd.sway.set_tree(common::tree_fixture());
+    let lru = swayrd.send(SwayrCommand::GetLru).data.unwrap();
+    let firefox = lru
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|w| w["id"] == 12)
+        .unwrap();
+    assert_eq!(firefox["last_focus_tick"], 0);
+}
+
+#[test]
+fn test_exit_on_sway_shutdown() {
+    let mut swayrd = Swayrd::start();
+    swayrd
+        .sway
+        .emit(EVENT_SHUTDOWN, json!({ "change": "exit" }));
+    common::wait_until("swayrd exits", || swayrd.has_exited());
+}
@@ -221,7 +221,7 @@ fn execute_command(cmd: &[String]) {
 }
 
 fn sway_subscribe() -> si::Fallible<si::EventStream> {
-    si::Connection::new()?.subscribe(&[
+    si::Connection::new()?.subscribe([
         si::EventType::Window,
         si::EventType::Shutdown,
         si::EventType::Workspace,
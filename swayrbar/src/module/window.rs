@@ -155,7 +155,7 @@ impl BarModuleFn for BarModuleWindow {
         let state = self.state.lock().expect("Could not lock state.");
         let cmd = cmd
             .iter()
-            .map(|arg| subst_placeholders(arg, false, &*state))
+            .map(|arg| subst_placeholders(arg, false, &state))
             .collect();
         Some(cmd)
     }